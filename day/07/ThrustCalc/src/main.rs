@@ -1,61 +1,27 @@
 use intcode::*;
+use intcode::network::NetworkOutcome;
 
 fn feedback_calc(input: &Memory, combo: &[Integer;5]) -> Value {
 
-    let mut machines: Vec<Machine> = Vec::new();
-
-    machines.push(Machine::new(input));
-    machines.push(Machine::new(input));
-    machines.push(Machine::new(input));
-    machines.push(Machine::new(input));
-    machines.push(Machine::new(input));
-    
-    // Give each machine their setting input
-
+    // Give each machine their setting input, and wire them in a ring
+    let mut network = Network::new();
+    let nodes: Vec<NodeId> = combo
+        .iter()
+        .map(|setting| network.add_node(input, &[*setting]))
+        .collect();
     for i in 0..5 {
-        machines[i].input().send(Value(combo[i])).unwrap();
+        network.connect(nodes[i], nodes[(i + 1) % 5]);
     }
 
     //Kick off the initial input
-    machines[0].input().send(Value(0)).unwrap();
-    
-    let mut outvalue = Value(0);
-
-    // execute feedback loop
-    loop {
-
-        let mut executed = false;
-        for i in 0..5 {
-            match machines[i].run() {
-                Err(e) if e == Error::InputNotAvailable => {
-                    executed = true;
-                },
-                Ok(()) => {
-                    continue;
-                },
-                Err(e) if e == Error::Terminated => {
-                },
-                Err(e) => {
-                    panic!("Machine {} error {:?}", i, e);
-                }
-            }
-
-            let next = (i + 1) % 5;
-            let next_input = machines[next].input().clone();
-            for v in machines[i].output().as_ref().unwrap().try_iter() {
-                if i == 4 {
-                    outvalue = v;
-                }
-                next_input.send(v).unwrap();
-            }
-        }
+    network.send(nodes[0], Value(0));
 
-        if !executed {
-            break;
-        }
+    match network.run() {
+        Ok(NetworkOutcome::AllHalted) => {},
+        other => panic!("Feedback loop stopped early: {:?}", other),
     }
 
-    outvalue
+    network.stats(nodes[4]).last_output.unwrap()
 }
 
 fn max_thrust_feedback_calc(input: &Memory) -> ([Integer; 5], Value) {
@@ -118,9 +84,7 @@ fn max_thrust_feedback_calc(input: &Memory) -> ([Integer; 5], Value) {
 
 
 fn main() {
-    let file = std::fs::read_to_string("input.txt").unwrap();
-
-    let original: Vec<Integer> = file.split(",").map(|x| x.parse::<Integer>().unwrap()).collect();
+    let original: Vec<Integer> = Program::from_path("input.txt").unwrap().into_vec();
 
     let mut program = original.clone();
 
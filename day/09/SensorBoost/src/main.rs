@@ -12,9 +12,7 @@ fn testfn() {
 }
 
 fn main() {
-    let file = std::fs::read_to_string("input.txt").unwrap();
-
-    let original: Vec<Integer> = file.split(",").map(|x| x.parse::<Integer>().unwrap()).collect();
+    let original = Program::from_path("input.txt").unwrap();
 
     let mut machine = Machine::new(&original);
     machine.input().send(Value(2)).unwrap();
@@ -22,6 +20,7 @@ fn main() {
     let start = std::time::Instant::now();
     let result = machine.run();
     println!("Runtime is {:?}", start.elapsed());
+    println!("Instructions executed: {}", machine.instruction_count());
     println!("Output is: {:?}", result);
 
     let output : Vec<Value> = machine.output().as_ref().unwrap().try_iter().collect();
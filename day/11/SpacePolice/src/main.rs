@@ -124,8 +124,7 @@ struct Hull {
 
 fn main() {
     
-    let file = std::fs::read_to_string("input.txt").unwrap();
-    let program: Vec<Integer> = file.split(",").map(|x| x.parse::<Integer>().unwrap()).collect();
+    let program = Program::from_path("input.txt").unwrap();
 
     let mut m = Machine::new(&program);
     
@@ -136,12 +135,12 @@ fn main() {
     tiles.insert(Point::new(0,0), Color::White);
     loop {
         let result = m.run();
-        if result.is_ok() {
+        if result == Ok(RunOutcome::Halted) {
             break;
         }
 
-        if let Err(e) = result {
-            if e == Error::InputNotAvailable {
+        if let Ok(outcome) = result {
+            if outcome == RunOutcome::NeedsInput {
 
                 loop {
                     let paint_instruction = m.output().as_ref().unwrap().try_recv();
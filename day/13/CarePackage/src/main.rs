@@ -219,11 +219,7 @@ fn process_output(m: &mut Machine, state: &mut GameState) {
 
 }
 fn main() {
-    let file = std::fs::read_to_string("input.txt").unwrap();
-    let mut program: Vec<Integer> = file
-        .split(",")
-        .map(|x| x.parse::<Integer>().unwrap())
-        .collect();
+    let mut program = Program::from_path("input.txt").unwrap().into_vec();
 
     program[0] = 2;
 
@@ -244,7 +240,7 @@ fn main() {
 
     loop {
         match m.run() {
-            Err(Error::InputNotAvailable) => {
+            Ok(RunOutcome::NeedsInput) => {
                 
                 process_output(&mut m, &mut state);
 
@@ -185,7 +185,7 @@ impl Map {
 }
 
 struct Robot {
-    m: Machine,
+    m: Machine<BufferIo>,
     location: Point,
 }
 
@@ -200,10 +200,9 @@ enum MoveResult {
 impl Robot {
     fn new() -> Self {
 
-        let file = std::fs::read_to_string("input.txt").unwrap();
-        let program: Vec<Integer> = file.split(",").map(|x| x.parse::<Integer>().unwrap()).collect();
+        let program = Program::from_path("input.txt").unwrap();
     
-        let mut m = Machine::new(&program);
+        let mut m = Machine::with_io(&program, BufferIo::new());
 
         Self {
             m: m,
@@ -212,17 +211,17 @@ impl Robot {
     }
 
     fn move_robot(&mut self, dir: Direction) -> MoveResult{
-        self.m.input().send(dir.value()).unwrap();
+        self.m.io_mut().input.push_back(dir.value());
 
         let result = self.m.run();
         match result {
-            Err(e) if e == Error::InputNotAvailable => {
+            Ok(RunOutcome::NeedsInput) => {
                 //expected
             },
             _ => { panic!("Unexpected run result: {:?}", result); }
         }
 
-        let output = self.m.output().as_ref().unwrap().recv().unwrap();
+        let output = self.m.io_mut().output.pop_front().unwrap();
 
         match output {
             Value(0) => MoveResult::Wall,
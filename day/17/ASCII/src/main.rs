@@ -1,7 +1,6 @@
 use intcode::*;
 
 use std::collections::{HashMap, HashSet};
-use std::convert::TryInto;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 struct Point {
@@ -351,7 +350,7 @@ impl Robot {
 
         let result = self.m.run();
         match result {
-            Err(e) if e == Error::InputNotAvailable => {
+            Ok(RunOutcome::NeedsInput) => {
                 //expected
             },
             _ => { panic!("Unexpected run result: {:?}", result); }
@@ -465,78 +464,41 @@ struct ASCII {
 
 impl ASCII {
     fn new() -> Self {
-        let file = std::fs::read_to_string("input.txt").unwrap();
-        let program: Vec<Integer> = file
-            .split(",")
-            .map(|x| x.parse::<Integer>().unwrap())
-            .collect();
+        let program = Program::from_path("input.txt").unwrap().into_vec();
 
         Self { program: program }
     }
 
     fn get_map(&self) -> Map {
-        let mut machine = Machine::new(&self.program);
-
-        machine.run().unwrap();
-
-        
-        let chars : Vec<char> = machine.output().as_ref().unwrap().try_iter().map(|x| std::char::from_u32(x.0.try_into().unwrap()).unwrap() ).collect();
+        let mut machine = AsciiMachine::new(&self.program);
 
-        let mut map = Map::new(&chars);
-        
-        map
+        let mut chars = Vec::new();
+        for output in machine.read_until_prompt().unwrap() {
+            if let AsciiOutput::Line(line) = output {
+                chars.extend(line.chars());
+                chars.push('\n');
+            }
+        }
 
+        Map::new(&chars)
     }
 
     fn run(&self, main: &str, A: &str, B: &str, C: &str) {
         let mut program = self.program.clone();
         program[0] = 2;
-        let mut machine = Machine::new(&program);
-
-        for c in main.chars() {
-            machine.input().send(Value(c as i64));
-        }
-        machine.input().send(Value(10));
-
-        
-        for c in A.chars() {
-            machine.input().send(Value(c as i64));
-        }
-        machine.input().send(Value(10));
+        let mut machine = AsciiMachine::new(&program);
 
-        
-        for c in B.chars() {
-            machine.input().send(Value(c as i64));
+        // The last line answers the continuous video feed prompt.
+        for line in &[main, A, B, C, "n"] {
+            machine.send_line(line);
         }
-        machine.input().send(Value(10));
 
-        
-        for c in C.chars() {
-            machine.input().send(Value(c as i64));
-        }
-        machine.input().send(Value(10));
-
-        // feed
-        machine.input().send(Value('n' as i64));
-        machine.input().send(Value(10));
-
-        machine.run().unwrap();
-
-        let output : Vec<Value> = machine.output().as_ref().unwrap().try_iter().collect();
-   
-        let chars : Vec<char> = output.iter().map(|x| std::char::from_u32(x.0.try_into().unwrap()).unwrap() ).collect();
-
-        for c in chars {
-            if c.is_ascii() {
-            print!("{}", c);
-            }
-            else {
-                println!("{}", c as i64);
+        for output in machine.read_until_prompt().unwrap() {
+            match output {
+                AsciiOutput::Line(line) => println!("{}", line),
+                AsciiOutput::Value(v) => println!("{}", v.0),
             }
         }
-        //let map = Map::new(&chars);
-        //draw_map(&map);
-        //println!("output: {:?}", output);
     }
 }
 
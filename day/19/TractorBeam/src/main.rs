@@ -1,13 +1,12 @@
 use intcode::*;
 
 fn hit(program: &[Integer], x: Integer, y: Integer) -> bool {
-    let mut m = Machine::new(&program);
-    m.input().send(Value(x));
-    m.input().send(Value(y));
+    let mut m = FastMachine::new(&program);
+    let mut io = BufferIo::with_input(vec![Value(x), Value(y)]);
 
-    m.run();
+    m.run(&mut io).unwrap();
 
-    let v = m.output().as_ref().unwrap().recv().unwrap();
+    let v = io.output.pop_front().unwrap();
 
     v.0 != 0
 }
@@ -36,11 +35,7 @@ fn fits(program: &[Integer], x: Integer, y: Integer) -> bool {
 }
 
 fn main() {
-    let file = std::fs::read_to_string("input.txt").unwrap();
-    let program: Vec<Integer> = file
-        .split(",")
-        .map(|x| x.parse::<Integer>().unwrap())
-        .collect();
+    let program = Program::from_path("input.txt").unwrap();
 
     let mut ystart = 0;
     let mut c = 0;
@@ -0,0 +1,40 @@
+//! Compiles every checked-in program that runs on lib/intcode, for the tests
+//! in src/lib.rs to check against `Machine`. Day 7's intcode3 reads the same
+//! input as ThrustCalc, so the `thrust_calc` entry covers it. Day 20 depends
+//! on lib/intcode too, but its input is a maze, not a program.
+
+use intcode::{aot, Integer, Program};
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// Module name, input, and the value the puzzle writes to address 0 first.
+const PROGRAMS: [(&str, &str, Option<Integer>); 11] = [
+    ("gravity_assist", "02/intcode", None),
+    ("diagnostics", "05/intcode2", None),
+    ("thrust_calc", "07/ThrustCalc", None),
+    ("sensor_boost", "09/SensorBoost", None),
+    ("space_police", "11/SpacePolice", None),
+    ("care_package", "13/CarePackage", None),
+    ("care_package_free_play", "13/CarePackage", Some(2)),
+    ("oxygen_system", "15/OxygenSystem", None),
+    ("ascii", "17/ASCII", None),
+    ("ascii_wake_up", "17/ASCII", Some(2)),
+    ("tractor_beam", "19/TractorBeam", None),
+];
+
+fn main() {
+    let manifest = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let mut source = String::new();
+    for (name, day, first) in PROGRAMS.iter() {
+        let path = Path::new(&manifest).join(format!("../../day/{}/input.txt", day));
+        println!("cargo:rerun-if-changed={}", path.display());
+        let mut program = Program::from_path(&path).unwrap().into_vec();
+        if let Some(first) = first {
+            program[0] = *first;
+        }
+        source.push_str(&aot::compile(&program, name));
+    }
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("programs.rs");
+    fs::write(out, source).unwrap();
+}
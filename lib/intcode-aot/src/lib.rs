@@ -0,0 +1,148 @@
+//! The checked-in puzzle programs, compiled to Rust by `intcode::aot` in
+//! build.rs, and checked against `Machine`.
+
+include!(concat!(env!("OUT_DIR"), "/programs.rs"));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use intcode::aot::CompiledMachine;
+    use intcode::{Address, BufferIo, Integer, Machine, Value};
+
+    type New = fn(BufferIo) -> CompiledMachine<BufferIo>;
+
+    /// Runs `program` on a `Machine` and compiled, after poking `pokes` into
+    /// both, handing both each chunk of `inputs` in turn until they stop
+    /// asking for more. Checks that every run ends the same way with the
+    /// same outputs and the same state, and returns whether the compiled
+    /// code was still in use at the end.
+    fn compare(
+        program: &[Integer],
+        new: New,
+        pokes: &[(usize, Integer)],
+        inputs: &[Vec<Integer>],
+    ) -> bool {
+        let mut m = Machine::with_io(program, BufferIo::new());
+        let mut compiled = new(BufferIo::new());
+        for (address, value) in pokes {
+            m.poke(Address(*address), Value(*value)).unwrap();
+            compiled.poke(Address(*address), Value(*value)).unwrap();
+        }
+        for (i, chunk) in inputs.iter().enumerate() {
+            let chunk: Vec<Value> = chunk.iter().map(|v| Value(*v)).collect();
+            m.io_mut().input.extend(&chunk);
+            compiled.io_mut().input.extend(&chunk);
+
+            let expected = m.run();
+            assert_eq!(compiled.run(), expected, "run {}", i);
+            assert_eq!(compiled.io(), m.io(), "run {}", i);
+            assert_eq!(compiled.ip(), m.ip(), "run {}", i);
+            assert_eq!(compiled.relbase(), m.relbase(), "run {}", i);
+            assert_eq!(compiled.memory(), &m.memory().to_vec()[..], "run {}", i);
+            assert_eq!(compiled.is_terminated(), m.is_terminated());
+            m.io_mut().output.clear();
+            compiled.io_mut().output.clear();
+            if expected != Ok(intcode::RunOutcome::NeedsInput) {
+                break;
+            }
+        }
+        compiled.is_compiled()
+    }
+
+    /// `compare` for a program that never rewrites its code, so the
+    /// compiled code stays in use.
+    fn check(program: &[Integer], new: New, inputs: &[Vec<Integer>]) {
+        assert!(compare(program, new, &[], inputs));
+    }
+
+    /// `n` inputs picked from `values` by a fixed pseudo-random sequence.
+    fn moves(values: &[Integer], n: usize) -> Vec<Vec<Integer>> {
+        let mut seed: u64 = 19;
+        (0..n)
+            .map(|_| {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                vec![values[(seed >> 33) as usize % values.len()]]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn gravity_assist_falls_back() {
+        // The program stores its result over its first opcode, and the
+        // puzzle pokes the noun and verb into that instruction's parameters,
+        // so both hand over to the interpreter.
+        let (program, new) = (gravity_assist::PROGRAM, gravity_assist::new);
+        assert!(!compare(program, new, &[], &[vec![]]));
+        for &(noun, verb) in &[(12, 2), (84, 44), (99, 99)] {
+            assert!(!compare(program, new, &[(1, noun), (2, verb)], &[vec![]]));
+        }
+    }
+
+    #[test]
+    fn diagnostics() {
+        for system in &[1, 5] {
+            check(diagnostics::PROGRAM, diagnostics::new, &[vec![*system]]);
+        }
+    }
+
+    #[test]
+    fn thrust_calc() {
+        for phase in 0..10 {
+            let mut inputs = vec![vec![phase, 0]];
+            inputs.extend((1..20).map(|signal| vec![signal * 37]));
+            check(thrust_calc::PROGRAM, thrust_calc::new, &inputs);
+        }
+    }
+
+    #[test]
+    fn sensor_boost() {
+        for mode in 1..=2 {
+            check(sensor_boost::PROGRAM, sensor_boost::new, &[vec![mode]]);
+        }
+    }
+
+    #[test]
+    fn space_police() {
+        let inputs = moves(&[0, 1], 500);
+        check(space_police::PROGRAM, space_police::new, &inputs);
+    }
+
+    #[test]
+    fn care_package() {
+        check(care_package::PROGRAM, care_package::new, &[vec![]]);
+        let inputs = moves(&[-1, 0, 1], 500);
+        check(
+            care_package_free_play::PROGRAM,
+            care_package_free_play::new,
+            &inputs,
+        );
+    }
+
+    #[test]
+    fn oxygen_system() {
+        let inputs = moves(&[1, 2, 3, 4], 1000);
+        check(oxygen_system::PROGRAM, oxygen_system::new, &inputs);
+    }
+
+    #[test]
+    fn ascii() {
+        check(ascii::PROGRAM, ascii::new, &[vec![]]);
+        let lines = ["A,B,C,B\n", "L,4,R,8\n", "R,2\n", "L,12,L,2\n", "n\n"];
+        let inputs: Vec<Vec<Integer>> = lines
+            .iter()
+            .map(|line| line.bytes().map(Integer::from).collect())
+            .collect();
+        check(ascii_wake_up::PROGRAM, ascii_wake_up::new, &inputs);
+    }
+
+    #[test]
+    fn tractor_beam() {
+        for x in 0..50 {
+            for y in 0..50 {
+                check(tractor_beam::PROGRAM, tractor_beam::new, &[vec![x, y]]);
+            }
+        }
+    }
+}
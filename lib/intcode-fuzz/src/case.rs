@@ -0,0 +1,317 @@
+//! Random test programs.
+//!
+//! A `Case` is a run of instructions drawn from the opcodes and parameter
+//! modes of one `Profile`, then `hlt`, then a block of data cells.
+//! Jumps only go forward to another instruction and writes only go to the
+//! data block, so every case terminates without rewriting its own code.
+//! Day 9 cases start with an `rb` that points the relative base at the data
+//! block, so relative parameters address data cells too.
+
+use intcode::{Arg, Instruction, InstructionCode, Integer, ParameterMode, Profile, Value};
+use std::fmt;
+
+/// Opcodes a generated instruction may use: those of the profile other
+/// than `hlt`, which ends every case, and `rb`, which only appears in the
+/// day 9 prelude.
+pub fn opcodes(profile: Profile) -> Vec<InstructionCode> {
+    profile
+        .opcodes()
+        .iter()
+        .copied()
+        .filter(|code| {
+            *code != InstructionCode::Terminate && *code != InstructionCode::RelBaseAdjsust
+        })
+        .collect()
+}
+
+/// Range of the values placed in data cells, immediates and inputs. Day 2
+/// values stay positive for the unsigned day 2 interpreter.
+fn values(profile: Profile) -> (Integer, Integer) {
+    match profile {
+        Profile::Day2 => (0, 20),
+        Profile::Day5 | Profile::Day9 => (-20, 20),
+    }
+}
+
+/// A small xorshift generator, so a case can be rebuilt from its seed.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // The state must never be zero.
+        Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A value in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// A value in `lo..=hi`.
+    pub fn between(&mut self, lo: Integer, hi: Integer) -> Integer {
+        lo + (self.next_u64() % (hi - lo + 1) as u64) as Integer
+    }
+}
+
+/// A parameter of a generated instruction, with cells named by their index
+/// in the data block so the code can be edited without fixing up addresses.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Operand {
+    /// A data cell in position mode.
+    Data(usize),
+    /// A data cell in relative mode.
+    Relative(usize),
+    Immediate(Integer),
+    /// An immediate jump target: the index of an instruction, or the number
+    /// of instructions for the final `hlt`.
+    Target(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Op {
+    pub code: InstructionCode,
+    pub operands: Vec<Operand>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    pub profile: Profile,
+    pub ops: Vec<Op>,
+    pub data: Vec<Integer>,
+    /// One value for each `in` instruction, in program order.
+    pub inputs: Vec<Integer>,
+}
+
+impl Case {
+    /// A case of up to `size` instructions.
+    pub fn generate(rng: &mut Rng, profile: Profile, size: usize) -> Self {
+        let op_count = 1 + rng.below(size.max(1));
+        let data_len = 1 + rng.below(8);
+        let (lo, hi) = values(profile);
+        let opcodes = opcodes(profile);
+        let modes = profile.modes();
+        let relative = modes.contains(&ParameterMode::Relative);
+
+        let mut ops = Vec::with_capacity(op_count);
+        for index in 0..op_count {
+            let code = opcodes[rng.below(opcodes.len())];
+            let operands = (0..code.arity())
+                .map(|p| {
+                    if is_jump(code) && p == 1 {
+                        Operand::Target(index + 1 + rng.below(op_count - index))
+                    } else if code.writes_parameter(p) {
+                        if relative && rng.below(2) == 0 {
+                            Operand::Relative(rng.below(data_len))
+                        } else {
+                            Operand::Data(rng.below(data_len))
+                        }
+                    } else {
+                        match modes[rng.below(modes.len())] {
+                            ParameterMode::Position => Operand::Data(rng.below(data_len)),
+                            ParameterMode::Immediate => Operand::Immediate(rng.between(lo, hi)),
+                            ParameterMode::Relative => Operand::Relative(rng.below(data_len)),
+                        }
+                    }
+                })
+                .collect();
+            ops.push(Op { code, operands });
+        }
+
+        let data = (0..data_len).map(|_| rng.between(lo, hi)).collect();
+        let input_count = ops
+            .iter()
+            .filter(|op| op.code == InstructionCode::Input)
+            .count();
+        let inputs = (0..input_count).map(|_| rng.between(lo, hi)).collect();
+        Case {
+            profile,
+            ops,
+            data,
+            inputs,
+        }
+    }
+
+    fn has_prelude(&self) -> bool {
+        self.profile.supports(InstructionCode::RelBaseAdjsust)
+    }
+
+    /// The code, ending in `hlt`, and the address of the data block after it.
+    pub fn instructions(&self) -> (Vec<Instruction>, usize) {
+        let prelude = if self.has_prelude() { 2 } else { 0 };
+        let mut addresses = Vec::with_capacity(self.ops.len() + 1);
+        let mut address = prelude;
+        for op in &self.ops {
+            addresses.push(address);
+            address += 1 + op.code.arity();
+        }
+        addresses.push(address);
+        let data_start = address + 1;
+
+        let mut code = Vec::new();
+        if self.has_prelude() {
+            code.push(Instruction::RelBaseAdjust {
+                amount: Arg::Value(Value(data_start as Integer)),
+            });
+        }
+        for op in &self.ops {
+            let args: Vec<Arg> = op
+                .operands
+                .iter()
+                .map(|operand| match *operand {
+                    Operand::Data(i) => Arg::Address(intcode::Address(data_start + i)),
+                    Operand::Relative(i) => Arg::Offset(Value(i as Integer)),
+                    Operand::Immediate(v) => Arg::Value(Value(v)),
+                    Operand::Target(i) => Arg::Value(Value(addresses[i] as Integer)),
+                })
+                .collect();
+            code.push(Instruction::from_args(op.code, &args).unwrap());
+        }
+        code.push(Instruction::Terminate);
+        (code, data_start)
+    }
+
+    pub fn program(&self) -> Vec<Integer> {
+        let (code, _) = self.instructions();
+        let mut program: Vec<Integer> = code.iter().flat_map(|i| i.encode()).collect();
+        program.extend(&self.data);
+        program
+    }
+
+    /// The number of leading data cells that instructions refer to.
+    pub fn data_in_use(&self) -> usize {
+        self.ops
+            .iter()
+            .flat_map(|op| &op.operands)
+            .filter_map(|operand| match operand {
+                Operand::Data(i) | Operand::Relative(i) => Some(i + 1),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The case without instruction `index`, and without its input if it
+    /// was an `in`.
+    pub fn without_op(&self, index: usize) -> Case {
+        let mut case = self.clone();
+        if case.ops[index].code == InstructionCode::Input {
+            let input = self.ops[..index]
+                .iter()
+                .filter(|op| op.code == InstructionCode::Input)
+                .count();
+            case.inputs.remove(input);
+        }
+        case.ops.remove(index);
+        for op in &mut case.ops {
+            for operand in &mut op.operands {
+                if let Operand::Target(target) = operand {
+                    if *target > index {
+                        *target -= 1;
+                    }
+                }
+            }
+        }
+        case
+    }
+}
+
+fn is_jump(code: InstructionCode) -> bool {
+    code == InstructionCode::JumpIfTrue || code == InstructionCode::JumpIfFalse
+}
+
+impl fmt::Display for Case {
+    /// A disassembly, then the program and inputs in puzzle input format.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (code, data_start) = self.instructions();
+        let mut address = 0;
+        for instruction in &code {
+            writeln!(f, "  {:4}  {}", address, instruction)?;
+            address += instruction.size();
+        }
+        writeln!(f, "  {:4}  .data {:?}", data_start, self.data)?;
+        let program: Vec<String> = self.program().iter().map(|v| v.to_string()).collect();
+        writeln!(f, "program: {}", program.join(","))?;
+        let inputs: Vec<String> = self.inputs.iter().map(|v| v.to_string()).collect();
+        write!(f, "inputs: {}", inputs.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use intcode::{Address, BufferIo, Error, Machine, RunOutcome};
+
+    #[test]
+    fn cases_stay_within_their_profile() {
+        for seed in 0..200 {
+            for &profile in &Profile::ALL {
+                let case = Case::generate(&mut Rng::new(seed), profile, 12);
+                let (code, data_start) = case.instructions();
+                let program = case.program();
+                let mut m = Machine::with_io(&program, BufferIo::new());
+                m.set_profile(profile);
+
+                let mut address = 0;
+                for instruction in &code {
+                    assert_eq!(m.instruction_at(Address(address)), Ok(*instruction));
+                    address += instruction.size();
+                }
+                assert_eq!(address, data_start);
+
+                // Overflowing is the one fault a generated case can hit.
+                m.io_mut()
+                    .input
+                    .extend(case.inputs.iter().map(|v| Value(*v)));
+                let result = m.run_for(10_000);
+                assert!(
+                    matches!(
+                        result,
+                        Ok(RunOutcome::Halted) | Err(Error::ArithmeticOverflow { .. })
+                    ),
+                    "{}",
+                    case
+                );
+                assert_eq!(&m.memory().to_vec()[..data_start], &program[..data_start]);
+            }
+        }
+    }
+
+    #[test]
+    fn removing_ops_keeps_targets_and_inputs() {
+        let case = Case {
+            profile: Profile::Day5,
+            ops: vec![
+                Op {
+                    code: InstructionCode::JumpIfTrue,
+                    operands: vec![Operand::Immediate(1), Operand::Target(2)],
+                },
+                Op {
+                    code: InstructionCode::Input,
+                    operands: vec![Operand::Data(0)],
+                },
+                Op {
+                    code: InstructionCode::Input,
+                    operands: vec![Operand::Data(0)],
+                },
+            ],
+            data: vec![0],
+            inputs: vec![5, 6],
+        };
+        let smaller = case.without_op(1);
+        assert_eq!(smaller.ops[0].operands[1], Operand::Target(1));
+        assert_eq!(smaller.inputs, vec![6]);
+        assert_eq!(smaller.program(), vec![1105, 1, 3, 3, 6, 99, 0]);
+
+        assert_eq!(case.without_op(0).ops.len(), 2);
+        assert_eq!(case.without_op(2).ops[0].operands[1], Operand::Target(2));
+    }
+}
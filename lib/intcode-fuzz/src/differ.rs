@@ -0,0 +1,168 @@
+//! Running a case on every engine and shrinking the ones they disagree on.
+
+use crate::case::Case;
+use crate::engines::{value_range, Engine, Outcome, ENGINES};
+use std::fmt;
+
+/// A case the engines disagree on, with what each of them did.
+pub struct Divergence {
+    pub case: Case,
+    pub outcomes: Vec<(&'static str, Outcome)>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} case:", self.case.profile.name())?;
+        writeln!(f, "{}", self.case)?;
+        for (name, outcome) in &self.outcomes {
+            writeln!(f, "  {:>11}: {}", name, outcome)?;
+        }
+        Ok(())
+    }
+}
+
+/// The engines that can run `case`: those that support its profile and
+/// whose integers can hold every value it reaches.
+pub fn engines_for(case: &Case) -> Vec<&'static Engine> {
+    let (lo, hi) = value_range(&case.program(), &case.inputs);
+    ENGINES
+        .iter()
+        .filter(|e| e.supports(case.profile) && e.range.0 <= lo && hi <= e.range.1)
+        .collect()
+}
+
+/// Runs `case` on every engine that can run it, and returns the outcomes if
+/// any two of them disagree.
+pub fn check(case: &Case) -> Option<Divergence> {
+    let program = case.program();
+    let outcomes: Vec<(&'static str, Outcome)> = engines_for(case)
+        .into_iter()
+        .map(|e| (e.name, e.run(case.profile, &program, &case.inputs)))
+        .collect();
+
+    let agree = outcomes
+        .windows(2)
+        .all(|pair| pair[0].1.agrees_with(&pair[1].1));
+    if agree {
+        None
+    } else {
+        Some(Divergence {
+            case: case.clone(),
+            outcomes,
+        })
+    }
+}
+
+/// Shrinks `case` while `fails` still holds for it: drops instructions one at
+/// a time and then unused data cells, and zeroes the remaining data cells and
+/// inputs, until nothing more can go.
+pub fn minimize<F: Fn(&Case) -> bool>(mut case: Case, fails: F) -> Case {
+    loop {
+        let mut smaller = false;
+
+        for index in (0..case.ops.len()).rev() {
+            let candidate = case.without_op(index);
+            if fails(&candidate) {
+                case = candidate;
+                smaller = true;
+            }
+        }
+        if case.data.len() > case.data_in_use() {
+            let mut candidate = case.clone();
+            candidate.data.truncate(case.data_in_use());
+            if fails(&candidate) {
+                case = candidate;
+                smaller = true;
+            }
+        }
+        for index in 0..case.data.len() {
+            if case.data[index] != 0 {
+                let mut candidate = case.clone();
+                candidate.data[index] = 0;
+                if fails(&candidate) {
+                    case = candidate;
+                    smaller = true;
+                }
+            }
+        }
+        for index in 0..case.inputs.len() {
+            if case.inputs[index] != 0 {
+                let mut candidate = case.clone();
+                candidate.inputs[index] = 0;
+                if fails(&candidate) {
+                    case = candidate;
+                    smaller = true;
+                }
+            }
+        }
+
+        if !smaller {
+            return case;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::case::{Op, Operand, Rng};
+    use intcode::{InstructionCode, Profile};
+
+    #[test]
+    fn engines_agree_on_generated_cases() {
+        for &profile in &Profile::ALL {
+            for seed in 0..300 {
+                let case = Case::generate(&mut Rng::new(seed), profile, 16);
+                if let Some(divergence) = check(&case) {
+                    panic!("seed {}:\n{}", seed, divergence);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn narrow_engines_sit_out_large_values() {
+        // mul #100000, #100000, [0]: too big for the day 5 and 7 interpreters.
+        let case = Case {
+            profile: Profile::Day5,
+            ops: vec![Op {
+                code: InstructionCode::Mult,
+                operands: vec![
+                    Operand::Immediate(100_000),
+                    Operand::Immediate(100_000),
+                    Operand::Data(0),
+                ],
+            }],
+            data: vec![0],
+            inputs: vec![],
+        };
+        let names: Vec<&str> = engines_for(&case).iter().map(|e| e.name).collect();
+        assert_eq!(names, ["Machine", "FastMachine"]);
+        assert!(check(&case).is_none());
+
+        let mut small = case.clone();
+        small.ops[0].operands[0] = Operand::Immediate(3);
+        assert_eq!(engines_for(&small).len(), 4);
+    }
+
+    #[test]
+    fn minimizes_to_what_fails() {
+        // Pretend any case that multiplies data cell 1 by something fails.
+        let fails = |case: &Case| {
+            case.ops.iter().any(|op| {
+                op.code == InstructionCode::Mult && op.operands[..2].contains(&Operand::Data(1))
+            })
+        };
+        let case = (0..)
+            .map(|seed| Case::generate(&mut Rng::new(seed), Profile::Day5, 16))
+            .find(|case| case.ops.len() > 8 && fails(case))
+            .unwrap();
+
+        let minimal = minimize(case.clone(), fails);
+        assert_eq!(minimal.ops.len(), 1);
+        assert_eq!(minimal.ops[0].code, InstructionCode::Mult);
+        assert!(minimal.data.iter().all(|v| *v == 0));
+        assert!(minimal.inputs.is_empty());
+        assert_eq!(minimal.data, vec![0, 0]);
+    }
+}
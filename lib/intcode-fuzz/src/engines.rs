@@ -0,0 +1,334 @@
+//! The interpreters under test, each behind the same `run` signature.
+
+use crate::legacy;
+use intcode::trace::TraceRecord;
+use intcode::{BufferIo, Error, FastMachine, Integer, Machine, Profile, RunOutcome, Value};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+
+/// Instructions an engine may run before it is stopped. Generated cases
+/// always terminate well within this; engines that cannot be stopped early
+/// rely on that.
+pub const STEP_LIMIT: u64 = 100_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    Halted,
+    /// An error or a panic. Only the library engines report an `Error`.
+    Fault {
+        error: Option<Error>,
+        message: String,
+    },
+    StepLimit,
+}
+
+impl Status {
+    pub(crate) fn legacy_fault<E: fmt::Debug>(error: E) -> Self {
+        Status::Fault {
+            error: None,
+            message: format!("{:?}", error),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub status: Status,
+    /// Outputs up to the end of the run, or up to the panic.
+    pub outputs: Vec<Integer>,
+    /// Final memory. Empty if the engine panicked.
+    pub memory: Vec<Integer>,
+}
+
+impl Outcome {
+    /// Whether two engines behaved the same: the same outputs, and the same
+    /// final memory unless either panicked. Faults are compared by their
+    /// `Error` when both engines report one; the older interpreters have
+    /// their own error types and may panic instead, so any fault of theirs
+    /// matches any other fault.
+    pub fn agrees_with(&self, other: &Outcome) -> bool {
+        let same_status = match (&self.status, &other.status) {
+            (Status::Halted, Status::Halted) => true,
+            (Status::Fault { error: Some(a), .. }, Status::Fault { error: Some(b), .. }) => a == b,
+            (Status::Fault { .. }, Status::Fault { .. }) => true,
+            (Status::StepLimit, Status::StepLimit) => true,
+            _ => false,
+        };
+        let panicked = self.memory.is_empty() || other.memory.is_empty();
+        same_status && self.outputs == other.outputs && (panicked || self.memory == other.memory)
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.status {
+            Status::Halted => write!(f, "halted")?,
+            Status::Fault { message, .. } => write!(f, "fault: {}", message)?,
+            Status::StepLimit => write!(f, "still running after {} steps", STEP_LIMIT)?,
+        }
+        write!(f, ", outputs {:?}", self.outputs)?;
+        if !self.memory.is_empty() {
+            write!(f, ", memory {:?}", self.memory)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs a program on its inputs, adding each output to the vector as it is
+/// made, and returns how the run ended and the final memory.
+type RunFn = fn(Profile, &[Integer], &[Integer], &mut Vec<Integer>) -> (Status, Vec<Integer>);
+
+pub struct Engine {
+    pub name: &'static str,
+    pub profiles: &'static [Profile],
+    /// Smallest and largest value the engine's integer type holds.
+    pub range: (Integer, Integer),
+    run: RunFn,
+}
+
+impl Engine {
+    /// Runs `program` as a `profile` program, turning a panic into a fault
+    /// that keeps the outputs made before it.
+    pub fn run(&self, profile: Profile, program: &[Integer], inputs: &[Integer]) -> Outcome {
+        let run = self.run;
+        let mut outputs = Vec::new();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            run(profile, program, inputs, &mut outputs)
+        }));
+        let (status, memory) = result.unwrap_or_else(|payload| {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            let status = Status::Fault {
+                error: None,
+                message: format!("panicked: {}", message),
+            };
+            (status, Vec::new())
+        });
+        Outcome {
+            status,
+            outputs,
+            memory,
+        }
+    }
+
+    pub fn supports(&self, profile: Profile) -> bool {
+        self.profiles.contains(&profile)
+    }
+}
+
+const I32: (Integer, Integer) = (i32::MIN as Integer, i32::MAX as Integer);
+const I64: (Integer, Integer) = (Integer::MIN, Integer::MAX);
+
+pub const ENGINES: [Engine; 5] = [
+    Engine {
+        name: "day 02",
+        profiles: &[Profile::Day2],
+        range: (0, u32::MAX as Integer),
+        run: legacy::day02::run,
+    },
+    Engine {
+        name: "day 05",
+        profiles: &[Profile::Day2, Profile::Day5],
+        range: I32,
+        run: legacy::day05::run,
+    },
+    Engine {
+        name: "day 07",
+        profiles: &[Profile::Day2, Profile::Day5],
+        range: I32,
+        run: legacy::day07::run,
+    },
+    Engine {
+        name: "Machine",
+        profiles: &Profile::ALL,
+        range: I64,
+        run: run_machine,
+    },
+    Engine {
+        name: "FastMachine",
+        profiles: &Profile::ALL,
+        range: I64,
+        run: run_fast,
+    },
+];
+
+fn inputs(values: &[Integer]) -> BufferIo {
+    BufferIo::with_input(values.iter().map(|v| Value(*v)))
+}
+
+fn status(result: Result<RunOutcome, Error>) -> Status {
+    match result {
+        Ok(RunOutcome::Halted) => Status::Halted,
+        Ok(RunOutcome::StepLimit) => Status::StepLimit,
+        Ok(outcome) => Status::Fault {
+            error: None,
+            message: format!("stopped with {:?}", outcome),
+        },
+        Err(e) => Status::Fault {
+            error: Some(e),
+            message: format!("{:?}", e),
+        },
+    }
+}
+
+/// `Machine` runs with the case's profile, so it also checks that every
+/// generated instruction belongs to it.
+fn run_machine(
+    profile: Profile,
+    program: &[Integer],
+    input: &[Integer],
+    outputs: &mut Vec<Integer>,
+) -> (Status, Vec<Integer>) {
+    let mut m = Machine::with_io(program, inputs(input));
+    m.set_profile(profile);
+    let status = status(m.run_for(STEP_LIMIT));
+    outputs.extend(m.io().output.iter().map(|v| v.0));
+    (status, m.memory().to_vec())
+}
+
+fn run_fast(
+    _profile: Profile,
+    program: &[Integer],
+    input: &[Integer],
+    outputs: &mut Vec<Integer>,
+) -> (Status, Vec<Integer>) {
+    let mut m = FastMachine::new(program);
+    let mut io = inputs(input);
+    let status = status(m.run(&mut io));
+    outputs.extend(io.output.iter().map(|v| v.0));
+    (status, m.memory().to_vec())
+}
+
+/// The smallest and largest value `program` holds or produces at any point
+/// while `Machine` runs it, including its inputs. A program whose `add` or
+/// `mul` overflows `Integer` needs the whole range, and only the engines
+/// that report `Error::ArithmeticOverflow` can run it.
+pub fn value_range(program: &[Integer], input: &[Integer]) -> (Integer, Integer) {
+    let initial = program.iter().chain(input).copied();
+    let range = initial.fold((0, 0), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    let range = Arc::new(Mutex::new(range));
+
+    let mut m = Machine::with_io(program, inputs(input));
+    let traced = Arc::clone(&range);
+    m.set_tracer(move |record: &TraceRecord| {
+        let (lo, hi) = &mut *traced.lock().unwrap();
+        let written = record.writes.iter().map(|(_, v)| v.0);
+        for v in written.chain(record.output.map(|v| v.0)) {
+            *lo = (*lo).min(v);
+            *hi = (*hi).max(v);
+        }
+    });
+    if let Err(Error::ArithmeticOverflow { .. }) = m.run_for(STEP_LIMIT) {
+        return I64;
+    }
+    let range = *range.lock().unwrap();
+    range
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use intcode::Address;
+
+    #[test]
+    fn engines_agree_on_a_day5_program() {
+        // Outputs 1 if the input is less than 8, then doubles cell 14.
+        let program = [3, 13, 7, 13, 14, 15, 4, 15, 1002, 14, 2, 14, 99, 0, 8, 0];
+        let outcomes: Vec<Outcome> = ENGINES
+            .iter()
+            .filter(|e| e.supports(Profile::Day5))
+            .map(|e| e.run(Profile::Day5, &program, &[5]))
+            .collect();
+        assert_eq!(outcomes.len(), 4);
+        assert_eq!(outcomes[0].status, Status::Halted);
+        assert_eq!(outcomes[0].outputs, vec![1]);
+        assert_eq!(outcomes[0].memory[13..], [5, 16, 1]);
+        for outcome in &outcomes {
+            assert!(outcome.agrees_with(&outcomes[0]), "{}", outcome);
+        }
+    }
+
+    #[test]
+    fn panics_become_faults() {
+        // Both old interpreters panic on a write to an immediate parameter.
+        let program = [11101, 1, 1, 0, 99];
+        let day05 = ENGINES[1].run(Profile::Day5, &program, &[]);
+        assert!(
+            matches!(&day05.status, Status::Fault { error: None, message }
+            if message.starts_with("panicked"))
+        );
+
+        let machine = ENGINES[3].run(Profile::Day5, &program, &[]);
+        assert!(matches!(
+            machine.status,
+            Status::Fault {
+                error: Some(Error::WriteToImmediate { .. }),
+                ..
+            }
+        ));
+        assert!(day05.agrees_with(&machine));
+        assert!(!machine.agrees_with(&ENGINES[3].run(Profile::Day5, &[99], &[])));
+    }
+
+    #[test]
+    fn measures_value_range() {
+        // mul [5], [5], [5]; hlt; 70000
+        assert_eq!(
+            value_range(&[2, 5, 5, 5, 99, 70000], &[]),
+            (0, 4_900_000_000)
+        );
+        assert_eq!(value_range(&[3, 0, 99], &[-4]), (-4, 99));
+        // Squares [8] until it overflows: mul [8], [8], [8]; jt #1, #0
+        let square = [2, 8, 8, 8, 1105, 1, 0, 99, 3];
+        assert_eq!(value_range(&square, &[]), I64);
+        let outcomes: Vec<Outcome> = ENGINES[3..]
+            .iter()
+            .map(|e| e.run(Profile::Day5, &square, &[]))
+            .collect();
+        assert_eq!(
+            outcomes[0].status,
+            Status::Fault {
+                error: Some(Error::ArithmeticOverflow { ip: Address(0) }),
+                message: "ArithmeticOverflow { ip: Address(0) }".to_string(),
+            }
+        );
+        assert!(outcomes[1].agrees_with(&outcomes[0]));
+    }
+
+    #[test]
+    fn faults_compare_outputs_and_memory() {
+        let fault = |error: Option<Error>, outputs: &[Integer], memory: &[Integer]| Outcome {
+            status: Status::Fault {
+                message: format!("{:?}", error),
+                error,
+            },
+            outputs: outputs.to_vec(),
+            memory: memory.to_vec(),
+        };
+        let overflow = Some(Error::ArithmeticOverflow { ip: Address(2) });
+        let outcome = fault(overflow, &[1], &[2, 3]);
+        assert!(outcome.agrees_with(&fault(overflow, &[1], &[2, 3])));
+        assert!(!outcome.agrees_with(&fault(overflow, &[4], &[2, 3])));
+        assert!(!outcome.agrees_with(&fault(overflow, &[1], &[2, 4])));
+        // An older interpreter's fault, and a panic that lost the memory.
+        assert!(outcome.agrees_with(&fault(None, &[1], &[2, 3])));
+        assert!(!outcome.agrees_with(&fault(None, &[], &[2, 3])));
+        assert!(outcome.agrees_with(&fault(None, &[1], &[])));
+        assert!(!outcome.agrees_with(&fault(None, &[], &[])));
+    }
+
+    #[test]
+    fn panics_keep_earlier_outputs() {
+        // out #7; then a write to an immediate, which the day 5 interpreter
+        // panics on.
+        let program = [104, 7, 11101, 1, 1, 0, 99];
+        let day05 = ENGINES[1].run(Profile::Day5, &program, &[]);
+        assert!(day05.memory.is_empty());
+        assert_eq!(day05.outputs, vec![7]);
+        assert!(day05.agrees_with(&ENGINES[3].run(Profile::Day5, &program, &[])));
+    }
+}
@@ -0,0 +1,126 @@
+//! The interpreters days 2, 5 and 7 were solved with, before they moved to
+//! `intcode::Machine`.
+//!
+//! The `legacy` directory keeps each puzzle's `main.rs` from then, unchanged.
+//! Each module includes one and adds a `run` that drives that interpreter
+//! the way its puzzle did. Values are
+//! converted to the interpreter's integer type with `as`; callers keep
+//! cases within its range.
+
+pub mod day02 {
+    #![allow(warnings, clippy::all)]
+
+    include!("legacy/day02.rs");
+
+    use crate::engines::{Status, STEP_LIMIT};
+    use intcode::Profile;
+
+    /// The puzzle's `execute` prints every instruction, so this repeats its
+    /// loop without the printing. Day 2 has no I/O.
+    pub fn run(
+        _profile: Profile,
+        program: &[i64],
+        _input: &[i64],
+        _outputs: &mut Vec<i64>,
+    ) -> (Status, Vec<i64>) {
+        let mut memory: Vec<u32> = program.iter().map(|v| *v as u32).collect();
+        let status = {
+            let mut m = Machine {
+                memory: &mut memory,
+                ip: Address(0),
+            };
+            let mut steps = 0;
+            loop {
+                if steps == STEP_LIMIT {
+                    break Status::StepLimit;
+                }
+                steps += 1;
+                match m.pop_instruction() {
+                    Ok(Instruction::Terminate) => break Status::Halted,
+                    Ok(i) => {
+                        if let Err(e) = m.execute(i) {
+                            break Status::legacy_fault(e);
+                        }
+                    }
+                    Err(e) => break Status::legacy_fault(e),
+                }
+            }
+        };
+        (status, memory.iter().map(|v| *v as i64).collect())
+    }
+}
+
+pub mod day05 {
+    #![allow(warnings, clippy::all)]
+
+    include!("legacy/day05.rs");
+
+    use crate::engines::Status;
+    use intcode::Profile;
+
+    /// The puzzle's `execute` drops its outputs on an error and keeps a
+    /// trace, so this repeats its loop keeping the outputs instead.
+    pub fn run(
+        _profile: Profile,
+        program: &[i64],
+        input: &[i64],
+        outputs: &mut Vec<i64>,
+    ) -> (Status, Vec<i64>) {
+        let mut memory: Vec<i32> = program.iter().map(|v| *v as i32).collect();
+        let mut input = input.iter().map(|v| Value(*v as i32));
+        let status = {
+            let mut m = Machine {
+                memory: &mut memory,
+                ip: Address(0),
+            };
+            loop {
+                let i = match m.pop_instruction() {
+                    Ok(Instruction::Terminate) => break Status::Halted,
+                    Ok(i) => i,
+                    Err(e) => break Status::legacy_fault(e),
+                };
+                match m.execute(i, || input.next().unwrap()) {
+                    Ok(Some(o)) => outputs.push(o.0 as i64),
+                    Ok(None) => {}
+                    Err(e) => break Status::legacy_fault(e),
+                }
+            }
+        };
+        (status, memory.iter().map(|v| *v as i64).collect())
+    }
+}
+
+pub mod day07 {
+    #![allow(warnings, clippy::all)]
+
+    include!("legacy/day07.rs");
+
+    use crate::engines::Status;
+    use intcode::Profile;
+
+    /// Resumes the machine after every output, where the puzzle's `execute`
+    /// stops at the first one.
+    pub fn run(
+        _profile: Profile,
+        program: &[i64],
+        input: &[i64],
+        outputs: &mut Vec<i64>,
+    ) -> (Status, Vec<i64>) {
+        let program: Vec<i32> = program.iter().map(|v| *v as i32).collect();
+        let mut m = Machine::new(&program);
+        let mut input = input.iter().map(|v| Value(*v as i32));
+        let mut result = m.run(None);
+        let status = loop {
+            match result {
+                Ok(RunResult::NeedInput) => result = m.run(input.next()),
+                Ok(RunResult::Output(v)) => {
+                    outputs.push(v.0 as i64);
+                    result = m.run(None);
+                }
+                Ok(RunResult::Done) => break Status::Halted,
+                Err(e) => break Status::legacy_fault(e),
+            }
+        };
+        (status, m.memory.iter().map(|v| *v as i64).collect())
+    }
+}
@@ -0,0 +1,146 @@
+//! Differential fuzzer for the Intcode interpreters.
+//!
+//! Usage: intcode-fuzz [--seed N] [--cases N] [--size N] [--profile 2|5|9]
+//!
+//! Generates random programs for each instruction-set `Profile` and
+//! runs every one on all the interpreters that understand it: the day 2,
+//! day 5 and day 7 interpreters and the library's `Machine` and
+//! `FastMachine`. When their outputs, final memory or faults differ, the
+//! program is shrunk to a minimal reproducer and printed along with what
+//! each interpreter did. Case `i` of a run is generated from seed
+//! `seed + i`, so any report can be reproduced with `--seed`.
+
+mod case;
+mod differ;
+mod engines;
+mod legacy;
+
+use case::{Case, Rng};
+use intcode::Profile;
+
+const USAGE: &str = "\
+usage: intcode-fuzz [options]
+options:
+  --seed <n>       seed of the first case (default 0)
+  --cases <n>      cases per profile (default 10000)
+  --size <n>       most instructions in a case (default 20)
+  --profile <p>    only fuzz day 2, 5 or 9 programs";
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    seed: u64,
+    cases: u64,
+    size: usize,
+    profiles: Vec<Profile>,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options {
+        seed: 0,
+        cases: 10_000,
+        size: 20,
+        profiles: Profile::ALL.to_vec(),
+    };
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", arg))?;
+        let number = || {
+            value
+                .parse::<u64>()
+                .map_err(|_| format!("invalid value '{}' for {}", value, arg))
+        };
+        match arg.as_str() {
+            "--seed" => options.seed = number()?,
+            "--cases" => options.cases = number()?,
+            "--size" => options.size = number()? as usize,
+            "--profile" => {
+                let profile = Profile::from_name(&value)
+                    .ok_or_else(|| format!("unknown profile '{}'", value))?;
+                options.profiles = vec![profile];
+            }
+            _ => return Err(format!("unknown option '{}'", arg)),
+        }
+    }
+    Ok(options)
+}
+
+/// Fuzzes each profile until its first divergence. Returns the number of
+/// profiles that diverged.
+fn run(options: &Options) -> usize {
+    let mut failures = 0;
+    for &profile in &options.profiles {
+        let found = (options.seed..options.seed + options.cases).find_map(|seed| {
+            let case = Case::generate(&mut Rng::new(seed), profile, options.size);
+            differ::check(&case).map(|divergence| (seed, divergence))
+        });
+        match found {
+            Some((seed, divergence)) => {
+                failures += 1;
+                let minimal = differ::minimize(divergence.case, |c| differ::check(c).is_some());
+                println!(
+                    "{}: divergence at seed {}, minimized:",
+                    profile.name(),
+                    seed
+                );
+                println!("{}", differ::check(&minimal).unwrap());
+            }
+            None => println!("{}: {} cases, no divergence", profile.name(), options.cases),
+        }
+    }
+    failures
+}
+
+fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            std::process::exit(2);
+        }
+    };
+    // The older interpreters panic on bad programs; those panics are
+    // reported as faults, so keep their messages off the terminal.
+    std::panic::set_hook(Box::new(|_| {}));
+    if run(&options) > 0 {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> impl Iterator<Item = String> + '_ {
+        s.split_whitespace().map(str::to_string)
+    }
+
+    #[test]
+    fn parses_options() {
+        assert_eq!(
+            parse_args(args("--seed 7 --cases 50 --profile 5")).unwrap(),
+            Options {
+                seed: 7,
+                cases: 50,
+                size: 20,
+                profiles: vec![Profile::Day5],
+            }
+        );
+        assert_eq!(parse_args(args("")).unwrap().profiles, Profile::ALL);
+        assert!(parse_args(args("--cases")).is_err());
+        assert!(parse_args(args("--cases many")).is_err());
+        assert!(parse_args(args("--profile 7")).is_err());
+        assert!(parse_args(args("--bogus 1")).is_err());
+    }
+
+    #[test]
+    fn short_run_finds_nothing() {
+        let options = Options {
+            seed: 1000,
+            cases: 100,
+            size: 20,
+            profiles: Profile::ALL.to_vec(),
+        };
+        assert_eq!(run(&options), 0);
+    }
+}
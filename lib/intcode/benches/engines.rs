@@ -0,0 +1,75 @@
+//! Compares `Machine` with `FastMachine` on the checked-in puzzle inputs.
+//!
+//! Run with `cargo bench -p intcode`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use intcode::{BufferIo, FastMachine, Integer, Machine, Program, Value};
+
+fn load(day: &str) -> Vec<Integer> {
+    let path = format!("{}/../../day/{}/input.txt", env!("CARGO_MANIFEST_DIR"), day);
+    Program::from_path(path).unwrap().into_vec()
+}
+
+/// Runs `program` to completion on one input value and returns the last output.
+fn run_machine(program: &[Integer], input: &[Integer]) -> Value {
+    let io = BufferIo::with_input(input.iter().copied().map(Value));
+    let mut m = Machine::with_io(program, io);
+    m.run().unwrap();
+    *m.io().output.back().unwrap()
+}
+
+fn run_fast(program: &[Integer], input: &[Integer]) -> Value {
+    let mut io = BufferIo::with_input(input.iter().copied().map(Value));
+    let mut m = FastMachine::new(program);
+    m.run(&mut io).unwrap();
+    *io.output.back().unwrap()
+}
+
+fn single_runs(c: &mut Criterion) {
+    for (day, input) in &[("05/intcode2", 5), ("09/SensorBoost", 2)] {
+        let program = load(day);
+        let mut group = c.benchmark_group(*day);
+        group.bench_function("machine", |b| b.iter(|| run_machine(&program, &[*input])));
+        group.bench_function("fast", |b| b.iter(|| run_fast(&program, &[*input])));
+        group.finish();
+    }
+}
+
+/// Day 19 starts a fresh machine for every probe of the beam.
+fn tractor_beam_probes(c: &mut Criterion) {
+    let program = load("19/TractorBeam");
+    let probes: Vec<[Integer; 2]> = (0..10)
+        .flat_map(|y| (0..10).map(move |x| [x, y]))
+        .collect();
+
+    let mut group = c.benchmark_group("19/TractorBeam 10x10");
+    group.bench_function("machine (channels)", |b| {
+        b.iter(|| {
+            for p in &probes {
+                let mut m = Machine::new(&program);
+                m.input().send(Value(p[0])).unwrap();
+                m.input().send(Value(p[1])).unwrap();
+                m.run().unwrap();
+                m.output().as_ref().unwrap().recv().unwrap();
+            }
+        })
+    });
+    group.bench_function("machine", |b| {
+        b.iter(|| {
+            for p in &probes {
+                run_machine(&program, p);
+            }
+        })
+    });
+    group.bench_function("fast", |b| {
+        b.iter(|| {
+            for p in &probes {
+                run_fast(&program, p);
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, single_runs, tractor_beam_probes);
+criterion_main!(benches);
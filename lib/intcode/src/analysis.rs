@@ -0,0 +1,958 @@
+//! Static analysis of programs before they run.
+//!
+//! `analyze` finds what a program may do without executing it: which
+//! instructions can run and where control can go from each, where it reads
+//! input and writes output, which branches depend on input, and what the
+//! relative base is at each instruction where it is the same on every path.
+//!
+//! It is an abstract interpretation over the instruction decoder. On entry
+//! to each instruction it keeps the range the relative base lies in, and for
+//! each memory cell a small set of possible values, or any value above some
+//! bound, along with whether the value may derive from input. Instructions
+//! are decoded from those cells, so programs that rewrite their own
+//! parameters, as many do to index arrays, are followed too.
+//!
+//! The results are conservative as long as every opcode that runs can be
+//! worked out, which is reported as a warning when it fails, and computed
+//! jumps follow the calling convention. A call stores the address just past
+//! an unconditional jump, then takes that jump. A computed call may reach
+//! any function some other call names directly. A computed jump through a
+//! cell relative to the base returns, to one of the stored return addresses,
+//! with the relative base the call had. Any other computed jump is reported
+//! and not followed. Input dependence follows data only; a value written
+//! under a branch that depends on input is not itself marked.
+
+use crate::{get_digits, Address, Arg, Instruction, InstructionCode, Integer, Memory};
+use crate::{ParameterMode, Value};
+use num_traits::FromPrimitive;
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Most values a cell is tracked with before it may hold any value.
+const MAX_VALUES: usize = 16;
+
+/// What a parameter or memory cell may hold.
+#[derive(Debug, Clone, PartialEq)]
+struct AbstractValue {
+    /// The possible values, or `None` for any value.
+    values: Option<BTreeSet<Integer>>,
+    /// A bound below any value, if `values` is `None`.
+    floor: Option<Integer>,
+    /// Whether the value may derive from input.
+    input: bool,
+}
+
+impl AbstractValue {
+    fn constant(value: Integer) -> Self {
+        Self {
+            values: Some(std::iter::once(value).collect()),
+            floor: None,
+            input: false,
+        }
+    }
+
+    fn any(input: bool) -> Self {
+        Self {
+            values: None,
+            floor: None,
+            input,
+        }
+    }
+
+    fn lowest(&self) -> Option<Integer> {
+        match &self.values {
+            Some(values) => values.iter().next().copied(),
+            None => self.floor,
+        }
+    }
+
+    fn single(&self) -> Option<Integer> {
+        match &self.values {
+            Some(values) if values.len() == 1 => values.iter().next().copied(),
+            _ => None,
+        }
+    }
+
+    /// Widens `self` to include `other`. A floor that falls is dropped, so
+    /// loops that count down settle at once.
+    fn join(&mut self, other: &AbstractValue) {
+        let floor = match (self.lowest(), other.lowest()) {
+            (Some(a), Some(b)) if self.values.is_some() || b >= a => Some(a.min(b)),
+            _ => None,
+        };
+        self.input |= other.input;
+        self.values = match (self.values.take(), &other.values) {
+            (Some(mut values), Some(more)) => {
+                values.extend(more);
+                Some(values).filter(|v| v.len() <= MAX_VALUES)
+            }
+            _ => None,
+        };
+        self.floor = floor.filter(|_| self.values.is_none());
+    }
+
+    /// The values `f` gives for every pair of values of `self` and `other`.
+    fn combine<F>(&self, other: &AbstractValue, f: F) -> AbstractValue
+    where
+        F: Fn(Integer, Integer) -> Option<Integer>,
+    {
+        let input = self.input || other.input;
+        let values = match (&self.values, &other.values) {
+            (Some(a), Some(b)) => a
+                .iter()
+                .flat_map(|x| b.iter().map(move |y| (*x, *y)))
+                .map(|(x, y)| f(x, y))
+                .collect::<Option<BTreeSet<Integer>>>()
+                .filter(|v| v.len() <= MAX_VALUES),
+            _ => None,
+        };
+        AbstractValue {
+            values,
+            floor: None,
+            input,
+        }
+    }
+
+    fn add(&self, other: &AbstractValue) -> AbstractValue {
+        let mut sum = self.combine(other, Integer::checked_add);
+        if sum.values.is_none() {
+            sum.floor = self
+                .lowest()
+                .zip(other.lowest())
+                .and_then(|(a, b)| a.checked_add(b));
+        }
+        sum
+    }
+
+    fn mul(&self, other: &AbstractValue) -> AbstractValue {
+        let mut product = self.combine(other, Integer::checked_mul);
+        if product.values.is_none() {
+            product.floor = match (self.lowest(), other.lowest()) {
+                (Some(a), Some(b)) if a >= 0 && b >= 0 => a.checked_mul(b),
+                _ => None,
+            };
+        }
+        product
+    }
+
+    /// Whether the value may be zero, and whether it may be anything else.
+    fn truth(&self) -> (bool, bool) {
+        match &self.values {
+            Some(values) => (values.contains(&0), values.iter().any(|v| *v != 0)),
+            None => (!matches!(self.floor, Some(floor) if floor > 0), true),
+        }
+    }
+}
+
+/// The range the relative base lies in.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct RelBase {
+    lo: Integer,
+    /// `None` if there is no upper bound.
+    hi: Option<Integer>,
+}
+
+impl RelBase {
+    const ANY: RelBase = RelBase { lo: 0, hi: None };
+
+    fn known(base: Integer) -> Self {
+        RelBase {
+            lo: base,
+            hi: Some(base),
+        }
+    }
+
+    fn value(self) -> Option<Integer> {
+        Some(self.lo).filter(|lo| self.hi == Some(*lo))
+    }
+
+    /// Widens `self` to include `other`. An upper bound that moves is
+    /// dropped, so loops that raise the base settle at once; the lower bound
+    /// can fall no further than zero.
+    fn join(self, other: RelBase) -> RelBase {
+        let lo = self.lo.min(other.lo);
+        let hi = match (self.hi, other.hi) {
+            (Some(a), Some(b)) if b <= a => Some(a),
+            _ => None,
+        };
+        RelBase { lo, hi }
+    }
+
+    /// The base after adding one of `amounts`, or `None` if that always
+    /// faults.
+    fn adjust(self, amounts: &AbstractValue) -> Option<RelBase> {
+        let min = match amounts.lowest() {
+            Some(min) => min,
+            None => return Some(RelBase::ANY),
+        };
+        let max = amounts.values.as_ref().and_then(|v| v.iter().next_back());
+        let hi = match self.hi.zip(max).map(|(hi, max)| hi.checked_add(*max)) {
+            Some(Some(hi)) if hi < 0 => return None,
+            Some(hi) => hi,
+            None => None,
+        };
+        let lo = self.lo.checked_add(min).unwrap_or(0).max(0);
+        Some(RelBase { lo, hi })
+    }
+}
+
+/// Where a parameter refers to.
+enum Location {
+    /// One of these addresses; empty if every one of them faults.
+    Cells(BTreeSet<usize>),
+    /// Any address from this one on.
+    From(usize),
+}
+
+/// The machine's abstract state on entry to an instruction.
+#[derive(Debug, Clone, PartialEq)]
+struct Frame {
+    relbase: RelBase,
+    /// Cells written on some path here; the rest hold their initial value.
+    cells: BTreeMap<usize, AbstractValue>,
+    /// Everything written to an unknown address, which any cell from the
+    /// lowest such address on may hold.
+    wild: Option<(usize, AbstractValue)>,
+}
+
+impl Frame {
+    fn tainted(&self) -> bool {
+        let wild = self.wild.iter().map(|(_, value)| value);
+        self.cells.values().chain(wild).any(|v| v.input)
+    }
+}
+
+/// An instruction decoded from a frame, with the values its parameters may
+/// have.
+struct Decoded {
+    code: InstructionCode,
+    modes: [ParameterMode; 3],
+    params: Vec<AbstractValue>,
+}
+
+impl Decoded {
+    /// The instruction, if every parameter has a single value.
+    fn instruction(&self) -> Option<Instruction> {
+        let mut args = Vec::new();
+        for (param, mode) in self.params.iter().zip(&self.modes) {
+            let value = param.single()?;
+            args.push(match mode {
+                ParameterMode::Position => Arg::Address(Address(usize::try_from(value).ok()?)),
+                ParameterMode::Immediate => Arg::Value(Value(value)),
+                ParameterMode::Relative => Arg::Offset(Value(value)),
+            });
+        }
+        Instruction::from_args(self.code, &args)
+    }
+}
+
+/// The effect of one instruction, given the frame on entry to it.
+struct Step {
+    decoded: Decoded,
+    /// Values written. A write to one known cell replaces it; any other only
+    /// widens what the cells may hold.
+    writes: Vec<(Location, AbstractValue)>,
+    successors: BTreeSet<usize>,
+    /// The relative base after the instruction.
+    relbase: RelBase,
+    indirect: bool,
+    /// Whether this is a jump that makes a call.
+    call: bool,
+    /// Whether this is a computed jump that could not be followed.
+    unresolved: bool,
+    input_branch: bool,
+    immediate_write: bool,
+    negative_targets: BTreeSet<Integer>,
+}
+
+struct State<'a> {
+    program: &'a Memory,
+    /// Frame on entry to each reachable address.
+    frames: BTreeMap<usize, Frame>,
+    /// Return addresses stored by calls.
+    stored: BTreeSet<usize>,
+    /// Addresses that calls jump to directly.
+    entries: BTreeSet<usize>,
+}
+
+impl<'a> State<'a> {
+    fn initial(&self, address: usize) -> AbstractValue {
+        AbstractValue::constant(self.program.get(address).copied().unwrap_or(0))
+    }
+
+    fn cell(&self, frame: &Frame, address: usize) -> AbstractValue {
+        let mut value = match frame.cells.get(&address) {
+            Some(value) => value.clone(),
+            None => self.initial(address),
+        };
+        match &frame.wild {
+            Some((from, wild)) if address >= *from => value.join(wild),
+            _ => {}
+        }
+        value
+    }
+
+    /// Decodes the instruction at `address`, or `None` if it may not be a
+    /// valid one.
+    fn decode(&self, frame: &Frame, address: usize) -> Option<Decoded> {
+        let digits = get_digits(self.cell(frame, address).single()?);
+        let code: InstructionCode = FromPrimitive::from_i64(digits[5])?;
+        let modes = [
+            FromPrimitive::from_i64(digits[4])?,
+            FromPrimitive::from_i64(digits[3])?,
+            FromPrimitive::from_i64(digits[2])?,
+        ];
+        let mut params = Vec::new();
+        for cell in address + 1..address + 1 + code.arity() {
+            // Parameters must lie within memory.
+            if cell >= self.program.len() && !frame.cells.contains_key(&cell) {
+                return None;
+            }
+            params.push(self.cell(frame, cell));
+        }
+        Some(Decoded {
+            code,
+            modes,
+            params,
+        })
+    }
+
+    fn location(&self, frame: &Frame, d: &Decoded, i: usize) -> Location {
+        let param = &d.params[i];
+        let RelBase { lo, hi } = match d.modes[i] {
+            ParameterMode::Relative => frame.relbase,
+            _ => RelBase::known(0),
+        };
+        match (&param.values, hi) {
+            (Some(values), Some(hi)) if hi - lo < MAX_VALUES as Integer => Location::Cells(
+                (lo..=hi)
+                    .flat_map(|base| values.iter().filter_map(move |v| base.checked_add(*v)))
+                    .filter_map(|a| usize::try_from(a).ok())
+                    .collect(),
+            ),
+            _ => {
+                let lowest = param.lowest().map_or(0, |v| lo.saturating_add(v));
+                Location::From(usize::try_from(lowest).unwrap_or(0))
+            }
+        }
+    }
+
+    fn read(&self, frame: &Frame, d: &Decoded, i: usize) -> AbstractValue {
+        if d.modes[i] == ParameterMode::Immediate {
+            return d.params[i].clone();
+        }
+        match self.location(frame, d, i) {
+            Location::Cells(addresses) => {
+                let mut values = addresses.iter().map(|a| self.cell(frame, *a));
+                let first = values.next().unwrap_or_else(|| AbstractValue::any(false));
+                values.fold(first, |mut value, more| {
+                    value.join(&more);
+                    value
+                })
+            }
+            Location::From(_) => AbstractValue::any(frame.tainted()),
+        }
+    }
+
+    /// Whether `address` is just past a reachable jump that is always taken,
+    /// where a call would return to.
+    fn is_return(&self, address: usize) -> bool {
+        // Jumps are three cells long.
+        let jump = match address.checked_sub(3) {
+            Some(jump) => jump,
+            None => return false,
+        };
+        let d = match self
+            .frames
+            .get(&jump)
+            .and_then(|frame| self.decode(frame, jump))
+        {
+            Some(d) => d,
+            None => return false,
+        };
+        let taken_if = match d.code {
+            InstructionCode::JumpIfTrue => true,
+            InstructionCode::JumpIfFalse => false,
+            _ => return false,
+        };
+        d.modes[0] == ParameterMode::Immediate
+            && d.params[0].single().map(|v| v != 0) == Some(taken_if)
+    }
+
+    fn step(&self, address: usize, frame: &Frame) -> Option<Step> {
+        let d = self.decode(frame, address)?;
+        let next = address + 1 + d.params.len();
+        let mut step = Step {
+            decoded: Decoded {
+                code: d.code,
+                modes: d.modes,
+                params: Vec::new(),
+            },
+            writes: Vec::new(),
+            successors: BTreeSet::new(),
+            relbase: frame.relbase,
+            indirect: false,
+            call: false,
+            unresolved: false,
+            input_branch: false,
+            immediate_write: false,
+            negative_targets: BTreeSet::new(),
+        };
+        let read = |i| self.read(frame, &d, i);
+        let compare = |f: fn(Integer, Integer) -> Option<Integer>| read(0).combine(&read(1), f);
+
+        let written = match d.code {
+            InstructionCode::Add => Some(read(0).add(&read(1))),
+            InstructionCode::Mult => Some(read(0).mul(&read(1))),
+            InstructionCode::LessThan => Some(compare(|a, b| Some((a < b) as Integer))),
+            InstructionCode::Equals => Some(compare(|a, b| Some((a == b) as Integer))),
+            InstructionCode::Input => Some(AbstractValue::any(true)),
+            _ => None,
+        };
+        if let Some(value) = written {
+            let out = d.params.len() - 1;
+            if d.modes[out] == ParameterMode::Immediate {
+                // The write faults.
+                step.immediate_write = true;
+                step.decoded = d;
+                return Some(step);
+            }
+            step.writes.push((self.location(frame, &d, out), value));
+        }
+
+        match d.code {
+            InstructionCode::JumpIfTrue | InstructionCode::JumpIfFalse => {
+                let cond = read(0);
+                let dest = read(1);
+                step.input_branch = cond.input || dest.input;
+                let (zero, nonzero) = cond.truth();
+                let (taken, falls_through) = match d.code {
+                    InstructionCode::JumpIfTrue => (nonzero, zero),
+                    _ => (zero, nonzero),
+                };
+                if falls_through {
+                    step.successors.insert(next);
+                }
+                step.call = self.stored.contains(&next);
+                // A jump through the stack to one of several places returns.
+                let returns =
+                    !step.call && d.modes[1] == ParameterMode::Relative && dest.single().is_none();
+                if taken && returns {
+                    step.indirect = true;
+                    let possible = |a: &&usize| match &dest.values {
+                        Some(targets) => targets.contains(&(**a as Integer)),
+                        None => true,
+                    };
+                    step.successors.extend(self.stored.iter().filter(possible));
+                } else if taken {
+                    match &dest.values {
+                        Some(targets) => {
+                            for &target in targets {
+                                match usize::try_from(target) {
+                                    Ok(target) => step.successors.insert(target),
+                                    Err(_) => step.negative_targets.insert(target),
+                                };
+                            }
+                        }
+                        None if step.call => {
+                            step.indirect = true;
+                            step.successors.extend(&self.entries);
+                        }
+                        None => {
+                            step.indirect = true;
+                            step.unresolved = true;
+                        }
+                    }
+                }
+            }
+            InstructionCode::RelBaseAdjsust => {
+                // A negative base faults.
+                if let Some(relbase) = frame.relbase.adjust(&read(0)) {
+                    step.relbase = relbase;
+                    step.successors.insert(next);
+                }
+            }
+            InstructionCode::Terminate => {}
+            _ => {
+                step.successors.insert(next);
+            }
+        }
+        step.decoded = d;
+        Some(step)
+    }
+
+    /// The frame after `step`, which started from `frame`. Records the
+    /// constants it stores; returns whether any of them are new.
+    fn apply(&mut self, frame: &Frame, step: &Step) -> (Frame, bool) {
+        let mut after = frame.clone();
+        after.relbase = step.relbase;
+        let mut found = false;
+        for (location, value) in &step.writes {
+            // A call stores the address past the jump that follows it.
+            if let Some(values) = &value.values {
+                for v in values.iter().filter_map(|v| usize::try_from(*v).ok()) {
+                    let call = v >= 3 && step.successors.contains(&(v - 3));
+                    if call && self.is_return(v) {
+                        found |= self.stored.insert(v);
+                    }
+                }
+            }
+            match location {
+                Location::Cells(addresses) if addresses.len() == 1 => {
+                    let address = *addresses.iter().next().unwrap();
+                    after.cells.insert(address, value.clone());
+                }
+                Location::Cells(addresses) => {
+                    for address in addresses {
+                        let mut cell = self.cell(frame, *address);
+                        cell.join(value);
+                        after.cells.insert(*address, cell);
+                    }
+                }
+                Location::From(address) => match &mut after.wild {
+                    Some((from, wild)) => {
+                        *from = (*from).min(*address);
+                        wild.join(value);
+                    }
+                    None => after.wild = Some((*address, value.clone())),
+                },
+            }
+        }
+        if step.call && !step.indirect {
+            for &entry in &step.successors {
+                found |= self.entries.insert(entry);
+            }
+        }
+        (after, found)
+    }
+
+    /// Widens the frame at `address` to include `frame`. Returns whether it
+    /// changed.
+    fn join_frame(&mut self, address: usize, frame: &Frame) -> bool {
+        let existing = match self.frames.get(&address) {
+            Some(existing) => existing,
+            None => {
+                self.frames.insert(address, frame.clone());
+                return true;
+            }
+        };
+        let mut joined = existing.clone();
+        joined.relbase = existing.relbase.join(frame.relbase);
+        let cells = frame.cells.keys().chain(existing.cells.keys());
+        for address in cells.copied().collect::<BTreeSet<usize>>() {
+            let incoming = match frame.cells.get(&address) {
+                Some(value) => value.clone(),
+                None => self.initial(address),
+            };
+            joined
+                .cells
+                .entry(address)
+                .or_insert_with(|| self.initial(address))
+                .join(&incoming);
+        }
+        if let Some((address, wild)) = &frame.wild {
+            match &mut joined.wild {
+                Some((from, existing)) => {
+                    *from = (*from).min(*address);
+                    existing.join(wild);
+                }
+                None => joined.wild = Some((*address, wild.clone())),
+            }
+        }
+        let changed = joined != *existing;
+        self.frames.insert(address, joined);
+        changed
+    }
+}
+
+/// A reachable instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Site {
+    pub code: InstructionCode,
+    /// The instruction, if its parameters are the same on every path here.
+    /// Parameters that the program rewrites may not be.
+    pub instruction: Option<Instruction>,
+    /// The relative base on entry, if it is the same on every path here.
+    pub relbase: Option<Integer>,
+    /// Where control may go next. Empty for `hlt` and for instructions that
+    /// always fault.
+    pub successors: Vec<Address>,
+    /// Whether this is a jump to a computed destination that could only be
+    /// narrowed down by the calling convention.
+    pub indirect: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    /// A reachable instruction writes through an immediate-mode parameter,
+    /// which faults.
+    ImmediateWrite { ip: Address },
+    /// Control may reach a cell that does not hold a valid instruction, or
+    /// whose opcode depends on input. Nothing past it is followed.
+    InvalidInstruction { ip: Address },
+    /// A jump may go to a negative address.
+    NegativeJump { ip: Address, target: Integer },
+    /// A jump to a computed address that is neither a call nor a return.
+    /// Where it goes is not followed.
+    UnresolvedJump { ip: Address },
+}
+
+impl Warning {
+    pub fn ip(&self) -> Address {
+        match self {
+            Warning::ImmediateWrite { ip }
+            | Warning::InvalidInstruction { ip }
+            | Warning::NegativeJump { ip, .. }
+            | Warning::UnresolvedJump { ip } => *ip,
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}: ", self.ip().0)?;
+        match self {
+            Warning::ImmediateWrite { .. } => write!(f, "writes through an immediate parameter"),
+            Warning::InvalidInstruction { .. } => write!(f, "may not be a valid instruction"),
+            Warning::NegativeJump { target, .. } => write!(f, "may jump to {}", target),
+            Warning::UnresolvedJump { .. } => write!(f, "jumps to an address not worked out"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    /// Every instruction that may execute, keyed by address. Together with
+    /// their successors these form the control-flow graph.
+    pub instructions: BTreeMap<usize, Site>,
+    /// Instructions that read input.
+    pub inputs: Vec<Address>,
+    /// Instructions that write output.
+    pub outputs: Vec<Address>,
+    /// Conditional jumps whose condition or destination depends on input.
+    pub input_branches: Vec<Address>,
+    /// Sorted by address.
+    pub warnings: Vec<Warning>,
+}
+
+impl Analysis {
+    pub fn is_reachable(&self, address: Address) -> bool {
+        self.instructions.contains_key(&address.0)
+    }
+}
+
+pub fn analyze(program: &Memory) -> Analysis {
+    let mut state = State {
+        program,
+        frames: BTreeMap::new(),
+        stored: BTreeSet::new(),
+        entries: BTreeSet::new(),
+    };
+    let entry = Frame {
+        relbase: RelBase::known(0),
+        cells: BTreeMap::new(),
+        wild: None,
+    };
+    state.frames.insert(0, entry);
+
+    // Follow control flow until no frame changes. A newly found call or
+    // return, or a newly reached jump that makes one, may add a target to a
+    // computed jump, so each starts another round.
+    loop {
+        let mut found = false;
+        let reached = state.frames.len();
+        let mut work: Vec<usize> = state.frames.keys().copied().collect();
+        while let Some(address) = work.pop() {
+            let frame = state.frames[&address].clone();
+            let step = match state.step(address, &frame) {
+                Some(step) => step,
+                None => continue,
+            };
+            let (after, new) = state.apply(&frame, &step);
+            found |= new;
+            for &target in &step.successors {
+                let mut after = after.clone();
+                if step.indirect && !step.call {
+                    // Calls leave the relative base as they found it.
+                    after.relbase = state.frames[&(target - 3)].relbase;
+                }
+                if state.join_frame(target, &after) {
+                    work.push(target);
+                }
+            }
+        }
+        if !found && state.frames.len() == reached {
+            break;
+        }
+    }
+
+    let mut analysis = Analysis {
+        instructions: BTreeMap::new(),
+        inputs: Vec::new(),
+        outputs: Vec::new(),
+        input_branches: Vec::new(),
+        warnings: Vec::new(),
+    };
+    for (&address, frame) in &state.frames {
+        let ip = Address(address);
+        let step = match state.step(address, frame) {
+            Some(step) => step,
+            None => {
+                analysis.warnings.push(Warning::InvalidInstruction { ip });
+                continue;
+            }
+        };
+        match step.decoded.code {
+            InstructionCode::Input => analysis.inputs.push(ip),
+            InstructionCode::Output => analysis.outputs.push(ip),
+            _ if step.input_branch => analysis.input_branches.push(ip),
+            _ => {}
+        }
+        if step.immediate_write {
+            analysis.warnings.push(Warning::ImmediateWrite { ip });
+        }
+        if step.unresolved {
+            analysis.warnings.push(Warning::UnresolvedJump { ip });
+        }
+        for &target in &step.negative_targets {
+            analysis.warnings.push(Warning::NegativeJump { ip, target });
+        }
+        let site = Site {
+            code: step.decoded.code,
+            instruction: step.decoded.instruction(),
+            relbase: frame.relbase.value(),
+            successors: step.successors.iter().copied().map(Address).collect(),
+            indirect: step.indirect,
+        };
+        analysis.instructions.insert(address, site);
+    }
+    analysis.warnings.sort_by_key(|w| w.ip().0);
+    analysis
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::trace::TraceRecord;
+    use crate::{BufferIo, Machine, Program};
+    use std::sync::{Arc, Mutex};
+
+    fn day(name: &str) -> Program {
+        let path = format!(
+            "{}/../../day/{}/input.txt",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        );
+        Program::from_path(path).unwrap()
+    }
+
+    #[test]
+    fn finds_io_and_input_branches() {
+        let program = assemble(
+            "
+                    in   [count]
+            loop:   out  [count]
+                    add  [count], #-1, [count]
+                    jt   [count], #loop
+                    add  #3, #0, [fixed]
+                    jf   [fixed], #never
+                    hlt
+            never:  out  #0
+                    hlt
+            count:  .data 0
+            fixed:  .data 0
+            ",
+        )
+        .unwrap();
+        let a = analyze(&program);
+        let addresses: Vec<usize> = a.instructions.keys().copied().collect();
+        assert_eq!(addresses, [0, 2, 4, 8, 11, 15, 18]);
+        assert_eq!(a.inputs, [Address(0)]);
+        assert_eq!(a.outputs, [Address(2)]);
+        assert_eq!(a.input_branches, [Address(8)]);
+        assert_eq!(a.instructions[&8].successors, [Address(2), Address(11)]);
+        assert_eq!(a.instructions[&15].successors, [Address(18)]);
+        assert!(!a.is_reachable(Address(19)));
+        assert!(a.warnings.is_empty());
+    }
+
+    #[test]
+    fn tracks_constant_relative_base() {
+        let program = assemble(
+            "
+                    rb   #100
+                    add  #ret, #0, rb+0
+                    jt   #1, #func
+            ret:    in   [flag]
+                    jf   [flag], #done
+                    rb   #1
+                    jt   #1, #ret
+            done:   hlt
+            func:   out  rb-1
+                    jt   #1, rb+0
+            flag:   .data 0
+            ",
+        )
+        .unwrap();
+        let a = analyze(&program);
+        let relbase = |address: usize| a.instructions[&address].relbase;
+        assert_eq!(relbase(0), Some(0));
+        assert_eq!(relbase(2), Some(100));
+        // The loop moves the base on every pass.
+        assert_eq!(relbase(9), None);
+        assert_eq!(relbase(19), None);
+
+        // The return jump reads `ret` back from the stack.
+        let ret = &a.instructions[&22];
+        assert_eq!(ret.successors, [Address(9)]);
+        assert!(!ret.indirect);
+        assert_eq!(relbase(20), Some(100));
+        assert_eq!(relbase(22), Some(100));
+    }
+
+    #[test]
+    fn follows_rewritten_parameters() {
+        // Outputs table[i] for i from 2 down to 0 by patching the `out`.
+        let program = assemble(
+            "
+            loop:   add  #table, [i], [get+1]
+            get:    out  [0]
+                    add  [i], #-1, [i]
+                    lt   [i], #0, [done]
+                    jf   [done], #loop
+                    hlt
+            i:      .data 2
+            done:   .data 0
+            table:  .data 10, 20, 30
+            ",
+        )
+        .unwrap();
+        let a = analyze(&program);
+        let get = &a.instructions[&4];
+        assert_eq!(get.code, InstructionCode::Output);
+        assert_eq!(get.instruction, None);
+        assert_eq!(a.outputs, [Address(4)]);
+        assert!(a.is_reachable(Address(17)));
+        assert!(a.input_branches.is_empty());
+        assert!(a.warnings.is_empty());
+    }
+
+    #[test]
+    fn input_jumps_are_indirect() {
+        let program = assemble("in [5]\njt #1, [5]\n.data 0").unwrap();
+        let a = analyze(&program);
+        let jump = &a.instructions[&2];
+        assert!(jump.indirect);
+        assert!(jump.successors.is_empty());
+        assert_eq!(a.input_branches, [Address(2)]);
+        assert_eq!(a.warnings, [Warning::UnresolvedJump { ip: Address(2) }]);
+    }
+
+    #[test]
+    fn follows_calls_through_pointers() {
+        let program = assemble(
+            "
+                    in   [ptr]
+                    add  #one, #0, rb+0
+                    jt   #1, #func
+            one:    add  #two, #0, rb+0
+                    jt   #1, [ptr]
+            two:    hlt
+            func:   out  #7
+                    jt   #1, rb+0
+            ptr:    .data 0
+            ",
+        )
+        .unwrap();
+        let a = analyze(&program);
+        assert!(a.warnings.is_empty(), "{:?}", a.warnings);
+
+        // The input picks the function, which is one some call names.
+        let call = &a.instructions[&13];
+        assert!(call.indirect);
+        assert_eq!(call.successors, [Address(17)]);
+
+        let ret = &a.instructions[&19];
+        assert!(ret.indirect);
+        assert_eq!(ret.successors, [Address(9), Address(16)]);
+    }
+
+    #[test]
+    fn warns_about_suspect_instructions() {
+        // add #1, #1, #5; jt #1, #-4
+        let a = analyze(&[11101, 1, 1, 5, 1105, 1, -4]);
+        assert_eq!(a.warnings, [Warning::ImmediateWrite { ip: Address(0) }]);
+        assert!(!a.is_reachable(Address(4)));
+
+        let a = analyze(&[1105, 1, -4]);
+        assert_eq!(
+            a.warnings,
+            [Warning::NegativeJump {
+                ip: Address(0),
+                target: -4
+            }]
+        );
+        assert_eq!(a.warnings[0].to_string(), "0000: may jump to -4");
+
+        let a = analyze(&[1105, 1, 3, 55]);
+        assert_eq!(a.warnings, [Warning::InvalidInstruction { ip: Address(3) }]);
+
+        // Day 5 adds its input into the opcode at 6 before running it.
+        let a = analyze(&day("05/intcode2"));
+        assert_eq!(a.warnings, [Warning::InvalidInstruction { ip: Address(6) }]);
+
+        // Day 7 jumps through a table indexed by its input.
+        let a = analyze(&day("07/ThrustCalc"));
+        assert_eq!(a.warnings, [Warning::UnresolvedJump { ip: Address(6) }]);
+    }
+
+    /// Every instruction the day programs execute, and every I/O site they
+    /// use, must have been found.
+    #[test]
+    fn covers_what_day_programs_execute() {
+        let days: [(&str, &[Integer]); 2] =
+            [("09/SensorBoost", &[1]), ("19/TractorBeam", &[12, 20])];
+        for (name, input) in days.iter() {
+            let program = day(name);
+            let a = analyze(&program);
+            // Returns are not matched to their calls, so day 19's jump whose
+            // target it negates later may be reported; nothing else should be.
+            let unexpected = a
+                .warnings
+                .iter()
+                .filter(|w| !matches!(w, Warning::NegativeJump { .. }));
+            assert_eq!(unexpected.count(), 0, "day {}: {:?}", name, a.warnings);
+
+            let executed = Arc::new(Mutex::new(Vec::new()));
+            let traced = Arc::clone(&executed);
+            let input = input.iter().map(|v| Value(*v));
+            let mut m = Machine::with_io(&program, BufferIo::with_input(input));
+            m.set_tracer(move |record: &TraceRecord| {
+                traced
+                    .lock()
+                    .unwrap()
+                    .push((record.ip, record.relbase, record.instruction));
+            });
+            m.run().unwrap();
+
+            for (ip, relbase, instruction) in executed.lock().unwrap().iter() {
+                let site = &a.instructions[&ip.0];
+                assert_eq!(site.code, instruction.code(), "day {} at {}", name, ip.0);
+                if let Some(known) = site.instruction {
+                    assert_eq!(known, *instruction, "day {} at {}", name, ip.0);
+                }
+                if let Some(base) = site.relbase {
+                    assert_eq!(base as usize, relbase.0, "day {} at {}", name, ip.0);
+                }
+                match instruction {
+                    Instruction::Input { .. } => assert!(a.inputs.contains(ip)),
+                    Instruction::Output { .. } => assert!(a.outputs.contains(ip)),
+                    _ => {}
+                }
+            }
+            assert!(!a.input_branches.is_empty(), "day {}", name);
+        }
+    }
+}
@@ -0,0 +1,590 @@
+//! Ahead-of-time compilation of programs to Rust.
+//!
+//! `compile` turns a fixed program into Rust source, meant to be written out
+//! by a build script and pulled in with `include!`. The source is a module
+//! holding the program and a function with one arm per instruction that
+//! `analyze` finds reachable, already decoded. Its `new` wraps the function
+//! in a `CompiledMachine`, which runs like `Machine::run`: it reads from and
+//! writes to an `IntcodeIo`, pauses with `NeedsInput` on the `Input` that
+//! finds none, and faults with the same errors at the same ip.
+//!
+//! Parameters that the analysis finds the program rewriting are loaded from
+//! memory as the code runs; everything else is built in. A write that
+//! changes a built-in cell, by the program or by `poke`, hands the machine to
+//! the interpreter for good. Instructions that were not compiled, such as
+//! those reached only through jumps the analysis could not follow, run on the
+//! interpreter until control is back in compiled code. Arithmetic is
+//! checked, as on a default `Machine`.
+
+use crate::{analyze, Address, Arg, BufferIo, Error, Instruction, InstructionCode, IntcodeIo};
+use crate::{Integer, Machine, Memory, RunOutcome, Value};
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+/// Why compiled code stopped.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Exit {
+    Halted,
+    NeedsInput,
+    /// The instruction at `ip` was not compiled.
+    Interpret,
+    /// A write changed a cell built into the code, which can no longer run.
+    Modified,
+}
+
+/// A compiled program, as generated by `compile`.
+pub type CompiledFn = fn(&mut Core, &mut dyn IntcodeIo) -> Result<Exit, Error>;
+
+/// Memory and registers of a `CompiledMachine`, for compiled code to work on.
+#[derive(Debug, Clone)]
+pub struct Core {
+    memory: Vec<Integer>,
+    /// Whether each cell is built into the compiled code.
+    code: Vec<bool>,
+    /// Whether an instruction starting at each address was compiled.
+    entries: Vec<bool>,
+    /// Writes at or beyond this address fail, as on `Machine`.
+    limit: Option<usize>,
+    pub ip: usize,
+    pub relbase: usize,
+}
+
+impl Core {
+    /// Reads memory; addresses past the end read as zero.
+    #[inline]
+    pub fn get(&self, address: usize) -> Integer {
+        self.memory.get(address).copied().unwrap_or(0)
+    }
+
+    /// Writes memory, growing it if needed, for the instruction at `ip`.
+    /// Returns whether the write changed a cell built into the compiled code.
+    #[inline]
+    pub fn set(&mut self, address: usize, value: Integer, ip: usize) -> Result<bool, Error> {
+        if let Some(limit) = self.limit {
+            if address >= limit {
+                return Err(Error::MemoryLimitExceeded {
+                    address: Address(address),
+                    ip: Address(ip),
+                });
+            }
+        }
+        if address >= self.memory.len() {
+            self.memory.resize(address + 1, 0);
+        }
+        let changed = self.memory[address] != value;
+        self.memory[address] = value;
+        Ok(changed && self.code.get(address) == Some(&true))
+    }
+
+    /// The address in position-mode parameter `cell`, for the instruction at
+    /// `ip`.
+    #[inline]
+    pub fn position(&self, cell: usize, ip: usize) -> Result<usize, Error> {
+        let address = self.get(cell);
+        usize::try_from(address).map_err(|_| Error::InvalidAddress {
+            invalid_address: Value(address),
+            ip: Address(ip),
+        })
+    }
+
+    /// The address `offset` from the relative base, for the instruction at
+    /// `ip`.
+    #[inline]
+    pub fn relative(&self, offset: Integer, ip: usize) -> Result<usize, Error> {
+        let address = (self.relbase as Integer).checked_add(offset);
+        match address.map(usize::try_from) {
+            Some(Ok(address)) => Ok(address),
+            _ => Err(Error::InvalidAddress {
+                invalid_address: Value((self.relbase as Integer).wrapping_add(offset)),
+                ip: Address(ip),
+            }),
+        }
+    }
+
+    #[inline]
+    pub fn adjust_relbase(&mut self, offset: Integer, ip: usize) -> Result<(), Error> {
+        let relbase = (self.relbase as Integer).checked_add(offset);
+        match relbase.map(usize::try_from) {
+            Some(Ok(relbase)) => {
+                self.relbase = relbase;
+                Ok(())
+            }
+            _ => Err(Error::NegativeRelativeBase {
+                relbase: Value((self.relbase as Integer).wrapping_add(offset)),
+                ip: Address(ip),
+            }),
+        }
+    }
+}
+
+#[inline]
+pub fn add(a: Integer, b: Integer, ip: usize) -> Result<Integer, Error> {
+    a.checked_add(b)
+        .ok_or(Error::ArithmeticOverflow { ip: Address(ip) })
+}
+
+#[inline]
+pub fn mul(a: Integer, b: Integer, ip: usize) -> Result<Integer, Error> {
+    a.checked_mul(b)
+        .ok_or(Error::ArithmeticOverflow { ip: Address(ip) })
+}
+
+/// A jump target read by the instruction at `ip`.
+#[inline]
+pub fn target(value: Integer, ip: usize) -> Result<usize, Error> {
+    usize::try_from(value).map_err(|_| Error::InvalidAddress {
+        invalid_address: Value(value),
+        ip: Address(ip),
+    })
+}
+
+/// Runs a compiled program, falling back to `Machine` when it must.
+pub struct CompiledMachine<IO> {
+    core: Core,
+    /// `None` once the machine has moved to the interpreter.
+    compiled: Option<CompiledFn>,
+    terminated: bool,
+    io: IO,
+}
+
+impl<IO: IntcodeIo> CompiledMachine<IO> {
+    /// Loads `program`, whose cells in the `(start, size)` spans in `code` are
+    /// built into `compiled`. Generated modules call this from their `new`.
+    pub fn new(program: &Memory, code: &[(usize, usize)], compiled: CompiledFn, io: IO) -> Self {
+        let mut cells = vec![false; program.len()];
+        let mut entries = vec![false; program.len()];
+        for &(start, size) in code {
+            entries[start] = true;
+            for cell in &mut cells[start..start + size] {
+                *cell = true;
+            }
+        }
+        Self {
+            core: Core {
+                memory: program.to_vec(),
+                code: cells,
+                entries,
+                limit: None,
+                ip: 0,
+                relbase: 0,
+            },
+            compiled: Some(compiled),
+            terminated: false,
+            io,
+        }
+    }
+
+    pub fn io(&self) -> &IO {
+        &self.io
+    }
+
+    pub fn io_mut(&mut self) -> &mut IO {
+        &mut self.io
+    }
+
+    pub fn into_io(self) -> IO {
+        self.io
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.core.memory
+    }
+
+    pub fn ip(&self) -> Address {
+        Address(self.core.ip)
+    }
+
+    pub fn relbase(&self) -> Address {
+        Address(self.core.relbase)
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    /// Whether the compiled code is still in use.
+    pub fn is_compiled(&self) -> bool {
+        self.compiled.is_some()
+    }
+
+    /// Reads memory directly; addresses past the end read as zero.
+    pub fn peek(&self, address: Address) -> Value {
+        Value(self.core.get(address.0))
+    }
+
+    /// Makes writes at or beyond `limit` fail, as `Machine::set_memory_limit`
+    /// does.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.core.limit = limit;
+    }
+
+    /// Writes memory directly, growing it if needed, as `Machine::poke` does.
+    /// Changing a cell built into the compiled code moves the machine to the
+    /// interpreter.
+    pub fn poke(&mut self, address: Address, value: Value) -> Result<(), Error> {
+        if self.core.set(address.0, value.0, self.core.ip)? {
+            self.compiled = None;
+        }
+        Ok(())
+    }
+
+    /// Runs until the program halts or needs input, as `Machine::run` does.
+    pub fn run(&mut self) -> Result<RunOutcome, Error> {
+        loop {
+            if self.terminated {
+                return Ok(RunOutcome::Halted);
+            }
+            if let Some(compiled) = self.compiled {
+                match compiled(&mut self.core, &mut self.io)? {
+                    Exit::Halted => {
+                        self.terminated = true;
+                        return Ok(RunOutcome::Halted);
+                    }
+                    Exit::NeedsInput => return Ok(RunOutcome::NeedsInput),
+                    Exit::Interpret => {}
+                    Exit::Modified => self.compiled = None,
+                }
+            }
+            if let Some(outcome) = self.interpret()? {
+                return Ok(outcome);
+            }
+        }
+    }
+
+    /// Runs on the interpreter until the program stops, or reaches compiled
+    /// code that can take over, which gives `None`.
+    fn interpret(&mut self) -> Result<Option<RunOutcome>, Error> {
+        let mut m = Machine::with_io(&self.core.memory, &mut self.io);
+        m.set_ip(Address(self.core.ip));
+        m.set_relbase(Address(self.core.relbase));
+        m.set_memory_limit(self.core.limit);
+        let compiled = self.compiled.is_some();
+        let entries = &self.core.entries;
+        let result = loop {
+            if compiled && entries.get(m.ip().0) == Some(&true) {
+                break Ok(None);
+            }
+            match m.step() {
+                Ok(None) => {}
+                Ok(Some(RunOutcome::Output(v))) => m.io_mut().write(v),
+                outcome => break outcome,
+            }
+        };
+        self.core.memory = m.memory().to_vec();
+        self.core.ip = m.ip().0;
+        self.core.relbase = m.relbase().0;
+        self.terminated = m.is_terminated();
+        result
+    }
+}
+
+fn literal(value: Integer) -> String {
+    if value == Integer::MIN {
+        "Integer::MIN".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// How compiled code gets at one parameter of an instruction.
+struct Operand {
+    /// An expression for the parameter's value.
+    read: String,
+    /// An expression for the address it names, or `None` for an immediate.
+    address: Option<String>,
+}
+
+/// Operands for the parameters of `instruction` at `ip`, and statements to
+/// run before them. Fixed parameters are built into the code; the others are
+/// loaded from memory first, as `Machine` decodes them.
+fn operands(instruction: Instruction, ip: usize, fixed: bool) -> (String, Vec<Operand>) {
+    let mut decode = String::new();
+    let mut operands = Vec::new();
+    for (i, arg) in instruction.args().into_iter().enumerate() {
+        let (cell, p) = (ip + 1 + i, format!("p{}", i));
+        let (read, address) = match arg {
+            Arg::Value(value) if fixed => (literal(value.0), None),
+            Arg::Address(address) if fixed => (
+                format!("core.get({})", address.0),
+                Some(address.0.to_string()),
+            ),
+            Arg::Offset(offset) if fixed => {
+                let address = format!("core.relative({}, {})?", offset.0, ip);
+                (format!("core.get({})", address), Some(address))
+            }
+            Arg::Value(_) => {
+                write!(decode, "let {} = core.get({}); ", p, cell).unwrap();
+                (p, None)
+            }
+            Arg::Address(_) => {
+                write!(decode, "let {} = core.position({}, {})?; ", p, cell, ip).unwrap();
+                (format!("core.get({})", p), Some(p))
+            }
+            Arg::Offset(_) => {
+                write!(decode, "let {} = core.get({}); ", p, cell).unwrap();
+                let address = format!("core.relative({}, {})?", p, ip);
+                (format!("core.get({})", address), Some(address))
+            }
+        };
+        operands.push(Operand { read, address });
+    }
+    (decode, operands)
+}
+
+/// Statements writing `value` through `out` for the instruction at `ip`,
+/// which goes on to `next`.
+fn write_back(out: &Operand, value: &str, ip: usize, next: usize) -> String {
+    match &out.address {
+        Some(address) => format!(
+            "if core.set({}, {}, {})? {{ core.ip = {}; return Ok(Exit::Modified); }}",
+            address, value, ip, next
+        ),
+        None => format!(
+            "return Err(Error::WriteToImmediate {{ ip: Address({}) }});",
+            ip
+        ),
+    }
+}
+
+/// The body of the arm for `instruction` at `ip`.
+fn statements(instruction: Instruction, ip: usize, fixed: bool) -> String {
+    let next = ip + instruction.size();
+    let (decode, p) = operands(instruction, ip, fixed);
+    let body = match instruction.code() {
+        InstructionCode::Add | InstructionCode::Mult => {
+            let op = match instruction.code() {
+                InstructionCode::Add => "add",
+                _ => "mul",
+            };
+            format!(
+                "let a = {}; let b = {}; let v = intcode::aot::{}(a, b, {})?; {}",
+                p[0].read,
+                p[1].read,
+                op,
+                ip,
+                write_back(&p[2], "v", ip, next)
+            )
+        }
+        InstructionCode::LessThan | InstructionCode::Equals => {
+            let op = match instruction.code() {
+                InstructionCode::LessThan => "<",
+                _ => "==",
+            };
+            format!(
+                "let a = {}; let b = {}; let v = (a {} b) as Integer; {}",
+                p[0].read,
+                p[1].read,
+                op,
+                write_back(&p[2], "v", ip, next)
+            )
+        }
+        InstructionCode::Input => format!(
+            "let v = match io.read() {{ Some(v) => v.0, None => return Ok(Exit::NeedsInput) }}; {}",
+            write_back(&p[0], "v", ip, next)
+        ),
+        InstructionCode::Output => format!("io.write(Value({}));", p[0].read),
+        InstructionCode::JumpIfTrue | InstructionCode::JumpIfFalse => {
+            let test = match instruction.code() {
+                InstructionCode::JumpIfTrue => "!=",
+                _ => "==",
+            };
+            let target = match instruction.args()[1] {
+                Arg::Value(value) if fixed && value.0 >= 0 => value.0.to_string(),
+                _ => format!("intcode::aot::target({}, {})?", p[1].read, ip),
+            };
+            format!(
+                "let c = {}; if c {} 0 {{ core.ip = {}; continue; }}",
+                p[0].read, test, target
+            )
+        }
+        InstructionCode::RelBaseAdjsust => {
+            format!("core.adjust_relbase({}, {})?;", p[0].read, ip)
+        }
+        InstructionCode::Terminate => {
+            return format!("core.ip = {}; return Ok(Exit::Halted);", next);
+        }
+    };
+    format!("{}{} core.ip = {};", decode, body, next)
+}
+
+/// Rust source for a module named `name` running `program`. The module has
+/// `PROGRAM`, `CODE`, the compiled `run` and a `new` that loads the program
+/// into a `CompiledMachine`; it refers to this crate as `intcode`.
+pub fn compile(program: &Memory, name: &str) -> String {
+    let decoder = Machine::with_io(program, BufferIo::new());
+    let mut code = Vec::new();
+    let mut arms = String::new();
+    for (&ip, site) in &analyze(program).instructions {
+        // Instructions that fail to decode are left to the interpreter,
+        // which reports the fault.
+        let instruction = match decoder.instruction_at(Address(ip)) {
+            Ok(instruction) => instruction,
+            Err(_) => continue,
+        };
+        // Parameters the program may rewrite are read as it runs, and only
+        // the opcode is guarded.
+        let fixed = site.instruction == Some(instruction);
+        code.push((ip, if fixed { instruction.size() } else { 1 }));
+        writeln!(arms, "                // {}", instruction).unwrap();
+        writeln!(
+            arms,
+            "                {} => {{ {} }}",
+            ip,
+            statements(instruction, ip, fixed)
+        )
+        .unwrap();
+    }
+
+    let mut source = String::new();
+    writeln!(
+        source,
+        "// Compiled from an Intcode program by intcode::aot::compile."
+    )
+    .unwrap();
+    writeln!(source, "#[allow(clippy::all, unused, unreachable_code)]").unwrap();
+    writeln!(source, "pub mod {} {{", name).unwrap();
+    writeln!(
+        source,
+        "    use intcode::aot::{{CompiledMachine, Core, Exit}};"
+    )
+    .unwrap();
+    writeln!(
+        source,
+        "    use intcode::{{Address, Error, IntcodeIo, Integer, Value}};"
+    )
+    .unwrap();
+    writeln!(source).unwrap();
+    let cells: Vec<String> = program.iter().map(|v| literal(*v)).collect();
+    writeln!(
+        source,
+        "    pub const PROGRAM: &[Integer] = &[{}];",
+        cells.join(", ")
+    )
+    .unwrap();
+    let spans: Vec<String> = code
+        .iter()
+        .map(|(ip, size)| format!("({}, {})", ip, size))
+        .collect();
+    writeln!(
+        source,
+        "    pub const CODE: &[(usize, usize)] = &[{}];",
+        spans.join(", ")
+    )
+    .unwrap();
+    writeln!(source).unwrap();
+    writeln!(
+        source,
+        "    pub fn new<IO: IntcodeIo>(io: IO) -> CompiledMachine<IO> {{"
+    )
+    .unwrap();
+    writeln!(
+        source,
+        "        CompiledMachine::new(PROGRAM, CODE, run, io)"
+    )
+    .unwrap();
+    writeln!(source, "    }}").unwrap();
+    writeln!(source).unwrap();
+    writeln!(
+        source,
+        "    pub fn run(core: &mut Core, io: &mut dyn IntcodeIo) -> Result<Exit, Error> {{"
+    )
+    .unwrap();
+    writeln!(source, "        loop {{").unwrap();
+    writeln!(source, "            match core.ip {{").unwrap();
+    source.push_str(&arms);
+    writeln!(source, "                _ => return Ok(Exit::Interpret),").unwrap();
+    writeln!(source, "            }}").unwrap();
+    writeln!(source, "        }}").unwrap();
+    writeln!(source, "    }}").unwrap();
+    writeln!(source, "}}").unwrap();
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compiles_reachable_instructions() {
+        // in [9]; out [9]; jt #1, #0; hlt; 0 ... with a bad opcode after hlt
+        let program = [3, 9, 4, 9, 1105, 1, 0, 99, 77, 0];
+        let source = compile(&program, "echo");
+        assert!(source.contains("pub mod echo {"));
+        assert!(source.contains("pub const CODE: &[(usize, usize)] = &[(0, 2), (2, 2), (4, 3)];"));
+        assert!(source
+            .contains("4 => { let c = 1; if c != 0 { core.ip = 0; continue; } core.ip = 7; }"));
+        // The halt is unreachable, and nothing is compiled at 8.
+        assert!(!source.contains("7 => "));
+        assert!(!source.contains("8 => "));
+    }
+
+    /// Echoes an input, leaving the jump back at 4 to the interpreter.
+    fn echo(core: &mut Core, io: &mut dyn IntcodeIo) -> Result<Exit, Error> {
+        loop {
+            match core.ip {
+                0 => {
+                    let v = match io.read() {
+                        Some(v) => v.0,
+                        None => return Ok(Exit::NeedsInput),
+                    };
+                    if core.set(7, v, 0)? {
+                        core.ip = 2;
+                        return Ok(Exit::Modified);
+                    }
+                    core.ip = 2;
+                }
+                2 => {
+                    io.write(Value(core.get(7)));
+                    core.ip = 4;
+                }
+                _ => return Ok(Exit::Interpret),
+            }
+        }
+    }
+
+    #[test]
+    fn falls_back_to_the_interpreter() {
+        // loop: in [7]; out [7]; jt #1, #loop; 0
+        let program = [3, 7, 4, 7, 1105, 1, 0, 0];
+        let code = [(0, 2), (2, 2)];
+        let mut m = CompiledMachine::new(&program, &code, echo, BufferIo::new());
+        assert_eq!(m.run(), Ok(RunOutcome::NeedsInput));
+        m.io_mut().input.extend(vec![Value(7), Value(8)]);
+        assert_eq!(m.run(), Ok(RunOutcome::NeedsInput));
+        assert_eq!(m.io().output, vec![Value(7), Value(8)]);
+        assert_eq!(m.ip(), Address(0));
+        assert!(m.is_compiled());
+
+        // Poking data leaves the compiled code alone; poking code does not.
+        m.poke(Address(7), Value(3)).unwrap();
+        m.poke(Address(0), Value(3)).unwrap();
+        assert!(m.is_compiled());
+        m.poke(Address(2), Value(104)).unwrap();
+        assert!(!m.is_compiled());
+
+        // With the cell at 7 counted as code, the input rewrites it.
+        let code = [(0, 2), (2, 2), (7, 1)];
+        let mut m = CompiledMachine::new(&program, &code, echo, BufferIo::new());
+        m.io_mut().input.extend(vec![Value(7), Value(8)]);
+        assert_eq!(m.run(), Ok(RunOutcome::NeedsInput));
+        assert!(!m.is_compiled());
+        assert_eq!(m.io().output, vec![Value(7), Value(8)]);
+
+        // The memory limit holds for pokes, compiled code and the
+        // interpreter alike.
+        let exceeded = |ip| Error::MemoryLimitExceeded {
+            address: Address(7),
+            ip: Address(ip),
+        };
+        let mut m = CompiledMachine::new(&program, &[(0, 2), (2, 2)], echo, BufferIo::new());
+        m.set_memory_limit(Some(7));
+        assert_eq!(m.poke(Address(7), Value(1)), Err(exceeded(0)));
+        m.io_mut().input.push_back(Value(1));
+        assert_eq!(m.run(), Err(exceeded(0)));
+        assert!(m.is_compiled());
+        m.poke(Address(2), Value(3)).unwrap();
+        assert!(!m.is_compiled());
+        m.io_mut().input.push_back(Value(1));
+        assert_eq!(m.run(), Err(exceeded(0)));
+    }
+}
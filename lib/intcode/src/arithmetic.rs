@@ -0,0 +1,221 @@
+//! Integer widths for `add` and `mul`.
+//!
+//! `Machine` and `FastMachine` compute with `Integer`, an `i64`. By default
+//! an `add` or `mul` whose result does not fit fails with
+//! `Error::ArithmeticOverflow`; `Arithmetic::Wrapping` wraps it around
+//! instead, in debug and release builds alike. Programs that need more room
+//! can run on a `WideMachine` over any `Word`: `i64`, `i128`, or with the
+//! `bignum` feature, an unbounded `num_bigint::BigInt`.
+
+use crate::Integer;
+use std::convert::TryFrom;
+use std::fmt::{Debug, Display};
+
+/// What `add` and `mul` do when their result does not fit an `Integer`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Arithmetic {
+    /// Fail with `Error::ArithmeticOverflow`.
+    #[default]
+    Checked,
+    /// Wrap around in two's complement.
+    Wrapping,
+}
+
+impl Arithmetic {
+    /// `a + b`, or `None` if it overflows a checked machine.
+    pub fn add(self, a: Integer, b: Integer) -> Option<Integer> {
+        match self {
+            Arithmetic::Checked => a.checked_add(b),
+            Arithmetic::Wrapping => Some(a.wrapping_add(b)),
+        }
+    }
+
+    /// `a * b`, or `None` if it overflows a checked machine.
+    pub fn mul(self, a: Integer, b: Integer) -> Option<Integer> {
+        match self {
+            Arithmetic::Checked => a.checked_mul(b),
+            Arithmetic::Wrapping => Some(a.wrapping_mul(b)),
+        }
+    }
+}
+
+/// An integer type a `WideMachine` can compute with.
+pub trait Word: Clone + PartialEq + PartialOrd + Debug + Display + From<Integer> {
+    /// The value as an `Integer`, if it fits.
+    fn to_integer(&self) -> Option<Integer>;
+
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+
+    /// The value clamped to the `Integer` range, for error reports.
+    fn saturate(&self) -> Integer {
+        self.to_integer().unwrap_or_else(|| {
+            if *self < Self::from(0) {
+                Integer::MIN
+            } else {
+                Integer::MAX
+            }
+        })
+    }
+}
+
+impl Word for i64 {
+    fn to_integer(&self) -> Option<Integer> {
+        Some(*self)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        i64::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        i64::checked_mul(*self, *other)
+    }
+}
+
+impl Word for i128 {
+    fn to_integer(&self) -> Option<Integer> {
+        Integer::try_from(*self).ok()
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        i128::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        i128::checked_mul(*self, *other)
+    }
+}
+
+/// Never overflows.
+#[cfg(feature = "bignum")]
+impl Word for num_bigint::BigInt {
+    fn to_integer(&self) -> Option<Integer> {
+        num_traits::ToPrimitive::to_i64(self)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Address, BufferIo, Error, FastMachine, Machine, Program, RunOutcome, Value, WideMachine,
+    };
+
+    // mul #2^62, #2, [5]; hlt; 0
+    const DOUBLE: [Integer; 6] = [1102, 1 << 62, 2, 5, 99, 0];
+
+    #[test]
+    fn checked_overflow_is_an_error() {
+        // add #1, #1, [10]; add [9], #1, [9]; hlt; MAX; 0
+        let program = [1101, 1, 1, 10, 1001, 9, 1, 9, 99, Integer::MAX, 0];
+        let mut m = Machine::with_io(&program, BufferIo::new());
+        assert_eq!(m.run(), Err(Error::ArithmeticOverflow { ip: Address(4) }));
+        assert_eq!(m.ip(), Address(4));
+
+        let mut fast = FastMachine::new(&program);
+        assert_eq!(
+            fast.run(&mut BufferIo::new()),
+            Err(Error::ArithmeticOverflow { ip: Address(4) })
+        );
+
+        let mut m = Machine::with_io(&DOUBLE, BufferIo::new());
+        assert_eq!(m.run(), Err(Error::ArithmeticOverflow { ip: Address(0) }));
+    }
+
+    #[test]
+    fn wrapping_wraps() {
+        let mut m = Machine::with_io(&DOUBLE, BufferIo::new());
+        m.set_arithmetic(Arithmetic::Wrapping);
+        assert_eq!(m.run(), Ok(RunOutcome::Halted));
+        assert_eq!(m.peek(Address(5)), Value(Integer::MIN));
+
+        let mut fast = FastMachine::new(&DOUBLE);
+        fast.set_arithmetic(Arithmetic::Wrapping);
+        assert_eq!(fast.run(&mut BufferIo::new()), Ok(RunOutcome::Halted));
+        assert_eq!(fast.peek(Address(5)), Value(Integer::MIN));
+
+        assert_eq!(
+            Arithmetic::Wrapping.add(Integer::MAX, 1),
+            Some(Integer::MIN)
+        );
+        assert_eq!(Arithmetic::Checked.mul(1 << 32, 1 << 31), None);
+    }
+
+    #[test]
+    fn saturates() {
+        assert_eq!(Word::saturate(&(-1i128 << 100)), Integer::MIN);
+        assert_eq!(Word::saturate(&(1i128 << 100)), Integer::MAX);
+        assert_eq!(Word::saturate(&-5i128), -5);
+    }
+
+    /// Day 9's BOOST program checks the interpreter with large values; it
+    /// must report the same keycode whatever the arithmetic.
+    #[test]
+    fn boost_in_every_mode() {
+        let path = format!(
+            "{}/../../day/09/SensorBoost/input.txt",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let program = Program::from_path(path).unwrap();
+
+        let mut outputs = Vec::new();
+        for &arithmetic in &[Arithmetic::Checked, Arithmetic::Wrapping] {
+            let mut m = Machine::with_io(&program, BufferIo::with_input(vec![Value(1)]));
+            m.set_arithmetic(arithmetic);
+            assert_eq!(m.run(), Ok(RunOutcome::Halted));
+            outputs.push(
+                m.io()
+                    .output
+                    .iter()
+                    .map(|v| v.0 as i128)
+                    .collect::<Vec<_>>(),
+            );
+
+            let mut fast = FastMachine::new(&program);
+            fast.set_arithmetic(arithmetic);
+            let mut io = BufferIo::with_input(vec![Value(1)]);
+            assert_eq!(fast.run(&mut io), Ok(RunOutcome::Halted));
+            outputs.push(io.output.iter().map(|v| v.0 as i128).collect());
+        }
+
+        let mut wide = WideMachine::<i128>::new(&program);
+        let mut output = Vec::new();
+        assert_eq!(
+            wide.run(&mut vec![1].into(), &mut output),
+            Ok(RunOutcome::Halted)
+        );
+        outputs.push(output);
+
+        #[cfg(feature = "bignum")]
+        {
+            use num_bigint::BigInt;
+            let mut big = WideMachine::<BigInt>::new(&program);
+            let mut output = Vec::new();
+            assert_eq!(
+                big.run(&mut vec![BigInt::from(1)].into(), &mut output),
+                Ok(RunOutcome::Halted)
+            );
+            outputs.push(
+                output
+                    .iter()
+                    .map(|v| v.to_integer().unwrap() as i128)
+                    .collect(),
+            );
+        }
+
+        assert_eq!(outputs[0].len(), 1, "BOOST reported failing opcodes");
+        for output in &outputs {
+            assert_eq!(output, &outputs[0]);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! Line-oriented I/O for ASCII-capable programs.
+//!
+//! `AsciiMachine` sends text as character codes followed by a newline, and
+//! reads output back a line at a time. Output values outside the ASCII range
+//! are not characters; they come back as `AsciiOutput::Value`, which is how
+//! these programs report their final answer.
+
+use crate::{BufferIo, Error, Integer, Machine, RunOutcome, Value};
+
+const NEWLINE: Integer = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AsciiOutput {
+    /// A line of text, without its newline. Text left over when the program
+    /// stops for input or halts is returned as a line too, so a prompt
+    /// without a trailing newline is not lost.
+    Line(String),
+    /// An output value that is not an ASCII character.
+    Value(Value),
+}
+
+#[derive(Debug, Clone)]
+pub struct AsciiMachine {
+    machine: Machine<BufferIo>,
+    partial: String,
+    /// A non-character value that ended `partial`, returned by the next
+    /// `read_line`.
+    held: Option<Value>,
+}
+
+impl AsciiMachine {
+    pub fn new(program: &[Integer]) -> Self {
+        Self::from_machine(Machine::with_io(program, BufferIo::new()))
+    }
+
+    /// Wraps an existing machine. Output already in its buffer is read
+    /// before anything the program produces next.
+    pub fn from_machine(machine: Machine<BufferIo>) -> Self {
+        Self {
+            machine,
+            partial: String::new(),
+            held: None,
+        }
+    }
+
+    pub fn machine(&self) -> &Machine<BufferIo> {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut Machine<BufferIo> {
+        &mut self.machine
+    }
+
+    pub fn into_machine(mut self) -> Machine<BufferIo> {
+        if let Some(v) = self.held {
+            self.machine.io_mut().output.push_front(v);
+        }
+        self.machine
+    }
+
+    /// Queues `line` as input, one value per character, then a newline.
+    /// Characters are sent as their code points; the program decides what
+    /// to make of anything outside ASCII.
+    pub fn send_line(&mut self, line: &str) {
+        let input = &mut self.machine.io_mut().input;
+        input.extend(line.chars().map(|c| Value(c as Integer)));
+        input.push_back(Value(NEWLINE));
+    }
+
+    fn next_value(&mut self) -> Result<Option<Value>, Error> {
+        if let Some(v) = self.machine.io_mut().output.pop_front() {
+            return Ok(Some(v));
+        }
+        match self.machine.run_until_output()? {
+            RunOutcome::Output(v) => Ok(Some(v)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Runs until a full line or a non-character value is output. Text
+    /// before a non-character value comes back as a line first. Returns
+    /// `None` once the program stops (needs input, halts or runs out of
+    /// budget) with no output left.
+    pub fn read_line(&mut self) -> Result<Option<AsciiOutput>, Error> {
+        if let Some(v) = self.held.take() {
+            return Ok(Some(AsciiOutput::Value(v)));
+        }
+        loop {
+            match self.next_value()? {
+                Some(Value(NEWLINE)) => {
+                    return Ok(Some(AsciiOutput::Line(std::mem::take(&mut self.partial))));
+                }
+                Some(Value(v)) if (0..128).contains(&v) => self.partial.push(v as u8 as char),
+                Some(v) if self.partial.is_empty() => return Ok(Some(AsciiOutput::Value(v))),
+                Some(v) => {
+                    self.held = Some(v);
+                    return Ok(Some(AsciiOutput::Line(std::mem::take(&mut self.partial))));
+                }
+                None if self.partial.is_empty() => return Ok(None),
+                None => return Ok(Some(AsciiOutput::Line(std::mem::take(&mut self.partial)))),
+            }
+        }
+    }
+
+    /// Reads everything the program outputs until it stops, typically
+    /// because it is waiting at a prompt for the next line of input.
+    pub fn read_until_prompt(&mut self) -> Result<Vec<AsciiOutput>, Error> {
+        let mut lines = Vec::new();
+        while let Some(line) = self.read_line()? {
+            lines.push(line);
+        }
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    fn line(s: &str) -> AsciiOutput {
+        AsciiOutput::Line(s.to_string())
+    }
+
+    #[test]
+    fn prompts_and_answers() {
+        // Prints "Hi\nN?", reads a line and echoes its first character,
+        // then outputs 1000.
+        let program = assemble(
+            "
+                    out  #72
+                    out  #105
+                    out  #10
+                    out  #78
+                    out  #63
+                    in   [c]
+                    out  [c]
+                    out  #10
+                    out  #1000
+                    hlt
+            c:      .data 0
+            ",
+        )
+        .unwrap();
+
+        let mut m = AsciiMachine::new(&program);
+        assert_eq!(m.read_until_prompt(), Ok(vec![line("Hi"), line("N?")]));
+        assert!(!m.machine().is_terminated());
+
+        m.send_line("xyz");
+        assert_eq!(m.read_line(), Ok(Some(line("x"))));
+        assert_eq!(m.read_line(), Ok(Some(AsciiOutput::Value(Value(1000)))));
+        assert_eq!(m.read_line(), Ok(None));
+        assert!(m.machine().is_terminated());
+    }
+
+    #[test]
+    fn negative_and_large_values_are_not_characters() {
+        let mut m = AsciiMachine::new(&[104, 65, 104, -1, 104, 200, 104, 10, 99]);
+        assert_eq!(
+            m.read_until_prompt(),
+            Ok(vec![
+                line("A"),
+                AsciiOutput::Value(Value(-1)),
+                AsciiOutput::Value(Value(200)),
+                line(""),
+            ])
+        );
+    }
+
+    #[test]
+    fn faults_are_returned() {
+        let mut m = AsciiMachine::new(&[104, 65, 42]);
+        assert!(m.read_line().is_err());
+    }
+}
@@ -0,0 +1,560 @@
+//! A small text assembler for Intcode programs.
+//!
+//! Each line holds an optional `label:`, then an instruction or directive,
+//! then an optional `;` comment:
+//!
+//! ```text
+//! ; echo input until a zero is read
+//! loop:   in   [value]
+//!         jf   [value], #done
+//!         out  [value]
+//!         jt   #1, #loop
+//! done:   hlt
+//! value:  .data 0
+//! ```
+//!
+//! Mnemonics follow the `Instruction` variants: `add`, `mul`, `in`, `out`,
+//! `jt`, `jf`, `lt`, `eq`, `rb` and `hlt`. Operands are written `[addr]` for
+//! position mode, `#imm` for immediate mode and `rb+off` / `rb-off` for
+//! relative mode. Anywhere a number is expected a label may be used instead,
+//! optionally with a `+n` / `-n` offset.
+//!
+//! Directives are `.data v, v, ...` to emit raw values and `.zero n` to emit
+//! `n` zeros.
+
+use crate::{Address, Arg, Instruction, InstructionCode, Integer, ParameterMode, Value};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssembleErrorKind {
+    UnknownMnemonic(String),
+    UnknownDirective(String),
+    OperandCount { expected: usize, found: usize },
+    InvalidOperand(String),
+    InvalidNumber(String),
+    InvalidLabel(String),
+    DuplicateLabel(String),
+    UndefinedLabel(String),
+    ImmediateDestination,
+    NegativeAddress(Integer),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: AssembleErrorKind,
+}
+
+impl std::fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: ", self.line, self.column)?;
+        match &self.kind {
+            AssembleErrorKind::UnknownMnemonic(m) => write!(f, "unknown mnemonic '{}'", m),
+            AssembleErrorKind::UnknownDirective(d) => write!(f, "unknown directive '{}'", d),
+            AssembleErrorKind::OperandCount { expected, found } => {
+                write!(f, "expected {} operands, found {}", expected, found)
+            }
+            AssembleErrorKind::InvalidOperand(o) => write!(f, "invalid operand '{}'", o),
+            AssembleErrorKind::InvalidNumber(n) => write!(f, "invalid number '{}'", n),
+            AssembleErrorKind::InvalidLabel(l) => write!(f, "invalid label '{}'", l),
+            AssembleErrorKind::DuplicateLabel(l) => write!(f, "label '{}' is already defined", l),
+            AssembleErrorKind::UndefinedLabel(l) => write!(f, "label '{}' is not defined", l),
+            AssembleErrorKind::ImmediateDestination => {
+                write!(f, "destination operand cannot be immediate")
+            }
+            AssembleErrorKind::NegativeAddress(a) => write!(f, "negative address {}", a),
+        }
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(Integer),
+    Label { name: String, offset: Integer },
+}
+
+#[derive(Debug, Clone)]
+struct Operand {
+    mode: ParameterMode,
+    expr: Expr,
+    negate: bool,
+    column: usize,
+}
+
+#[derive(Debug)]
+enum Statement {
+    Instruction {
+        code: InstructionCode,
+        operands: Vec<Operand>,
+    },
+    Data(Vec<(Expr, usize)>),
+    Zero(usize),
+}
+
+impl Statement {
+    fn size(&self) -> usize {
+        match self {
+            Statement::Instruction { code, .. } => 1 + code.arity(),
+            Statement::Data(values) => values.len(),
+            Statement::Zero(n) => *n,
+        }
+    }
+}
+
+struct Line<'a> {
+    number: usize,
+    text: &'a str,
+}
+
+impl<'a> Line<'a> {
+    fn error(&self, byte: usize, kind: AssembleErrorKind) -> AssembleError {
+        AssembleError {
+            line: self.number,
+            column: self.text[..byte].chars().count() + 1,
+            kind,
+        }
+    }
+}
+
+/// Assembles `source` into a program ready for `Machine::new`.
+pub fn assemble(source: &str) -> Result<Vec<Integer>, AssembleError> {
+    let mut labels: HashMap<String, Integer> = HashMap::new();
+    let mut statements = Vec::new();
+    let mut address = 0;
+
+    for (index, text) in source.lines().enumerate() {
+        let line = Line {
+            number: index + 1,
+            text,
+        };
+        let (label, statement) = parse_line(&line)?;
+
+        if let Some((name, byte)) = label {
+            if labels.insert(name.to_string(), address as Integer).is_some() {
+                return Err(line.error(byte, AssembleErrorKind::DuplicateLabel(name.to_string())));
+            }
+        }
+
+        if let Some(statement) = statement {
+            address += statement.size();
+            statements.push((line, statement));
+        }
+    }
+
+    let mut program = Vec::with_capacity(address);
+    for (line, statement) in statements {
+        match statement {
+            Statement::Instruction { code, operands } => {
+                let mut args = Vec::with_capacity(operands.len());
+                for operand in operands {
+                    args.push(resolve_operand(&line, &labels, &operand)?);
+                }
+                let instruction = Instruction::from_args(code, &args).unwrap();
+                program.extend(instruction.encode());
+            }
+            Statement::Data(values) => {
+                for (expr, byte) in values {
+                    program.push(resolve(&line, &labels, &expr, byte)?);
+                }
+            }
+            Statement::Zero(n) => {
+                program.resize(program.len() + n, 0);
+            }
+        }
+    }
+
+    Ok(program)
+}
+
+fn resolve(
+    line: &Line,
+    labels: &HashMap<String, Integer>,
+    expr: &Expr,
+    byte: usize,
+) -> Result<Integer, AssembleError> {
+    match expr {
+        Expr::Number(n) => Ok(*n),
+        Expr::Label { name, offset } => match labels.get(name) {
+            Some(address) => Ok(address + offset),
+            None => Err(line.error(byte, AssembleErrorKind::UndefinedLabel(name.clone()))),
+        },
+    }
+}
+
+fn resolve_operand(
+    line: &Line,
+    labels: &HashMap<String, Integer>,
+    operand: &Operand,
+) -> Result<Arg, AssembleError> {
+    let mut value = resolve(line, labels, &operand.expr, operand.column)?;
+    if operand.negate {
+        value = -value;
+    }
+
+    match operand.mode {
+        ParameterMode::Position => {
+            if value < 0 {
+                return Err(line.error(operand.column, AssembleErrorKind::NegativeAddress(value)));
+            }
+            Ok(Arg::Address(Address(value as usize)))
+        }
+        ParameterMode::Immediate => Ok(Arg::Value(Value(value))),
+        ParameterMode::Relative => Ok(Arg::Offset(Value(value))),
+    }
+}
+
+/// Splits a line into its optional label (with byte offset) and statement.
+#[allow(clippy::type_complexity)]
+fn parse_line<'a>(
+    line: &Line<'a>,
+) -> Result<(Option<(&'a str, usize)>, Option<Statement>), AssembleError> {
+    let text = line.text;
+    let code_end = text.find(';').unwrap_or(text.len());
+    let mut start = skip_whitespace(text, 0, code_end);
+
+    let mut label = None;
+    if let Some(colon) = text[start..code_end].find(':') {
+        let name = text[start..start + colon].trim_end();
+        if !is_identifier(name) {
+            return Err(line.error(start, AssembleErrorKind::InvalidLabel(name.to_string())));
+        }
+        label = Some((name, start));
+        start = skip_whitespace(text, start + colon + 1, code_end);
+    }
+
+    if start == code_end {
+        return Ok((label, None));
+    }
+
+    let word_end = text[start..code_end]
+        .find(char::is_whitespace)
+        .map(|i| start + i)
+        .unwrap_or(code_end);
+    let word = &text[start..word_end];
+    let fields = split_fields(text, word_end, code_end);
+
+    let statement = if let Some(directive) = word.strip_prefix('.') {
+        match directive {
+            "data" => {
+                let mut values = Vec::with_capacity(fields.len());
+                for (byte, field) in fields {
+                    values.push((parse_expr(line, field, byte)?, byte));
+                }
+                Statement::Data(values)
+            }
+            "zero" => {
+                if fields.len() != 1 {
+                    return Err(line.error(
+                        start,
+                        AssembleErrorKind::OperandCount {
+                            expected: 1,
+                            found: fields.len(),
+                        },
+                    ));
+                }
+                let (byte, field) = fields[0];
+                match field.parse::<usize>() {
+                    Ok(n) => Statement::Zero(n),
+                    Err(_) => {
+                        return Err(line.error(
+                            byte,
+                            AssembleErrorKind::InvalidNumber(field.to_string()),
+                        ))
+                    }
+                }
+            }
+            _ => {
+                return Err(line.error(
+                    start,
+                    AssembleErrorKind::UnknownDirective(word.to_string()),
+                ))
+            }
+        }
+    } else {
+        let code = InstructionCode::from_mnemonic(word).ok_or_else(|| {
+            line.error(start, AssembleErrorKind::UnknownMnemonic(word.to_string()))
+        })?;
+
+        if fields.len() != code.arity() {
+            return Err(line.error(
+                start,
+                AssembleErrorKind::OperandCount {
+                    expected: code.arity(),
+                    found: fields.len(),
+                },
+            ));
+        }
+
+        let mut operands = Vec::with_capacity(fields.len());
+        for (index, (byte, field)) in fields.into_iter().enumerate() {
+            let operand = parse_operand(line, field, byte)?;
+            if code.writes_parameter(index) && operand.mode == ParameterMode::Immediate {
+                return Err(line.error(byte, AssembleErrorKind::ImmediateDestination));
+            }
+            operands.push(operand);
+        }
+        Statement::Instruction { code, operands }
+    };
+
+    Ok((label, Some(statement)))
+}
+
+fn skip_whitespace(text: &str, from: usize, end: usize) -> usize {
+    text[from..end]
+        .find(|c: char| !c.is_whitespace())
+        .map(|i| from + i)
+        .unwrap_or(end)
+}
+
+/// Comma separated fields between `from` and `end`, trimmed, with the byte
+/// offset of each field's first character.
+fn split_fields(text: &str, from: usize, end: usize) -> Vec<(usize, &str)> {
+    let mut fields = Vec::new();
+    if text[from..end].trim().is_empty() {
+        return fields;
+    }
+
+    let mut field_start = from;
+    for (i, c) in text[from..end].char_indices() {
+        if c == ',' {
+            fields.push(trim_field(text, field_start, from + i));
+            field_start = from + i + 1;
+        }
+    }
+    fields.push(trim_field(text, field_start, end));
+    fields
+}
+
+fn trim_field(text: &str, start: usize, end: usize) -> (usize, &str) {
+    let start = skip_whitespace(text, start, end);
+    (start, text[start..end].trim_end())
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    name != "rb" && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_number(line: &Line, text: &str, byte: usize) -> Result<Integer, AssembleError> {
+    text.parse::<Integer>()
+        .map_err(|_| line.error(byte, AssembleErrorKind::InvalidNumber(text.to_string())))
+}
+
+fn parse_expr(line: &Line, text: &str, byte: usize) -> Result<Expr, AssembleError> {
+    if text.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+') {
+        return Ok(Expr::Number(parse_number(line, text, byte)?));
+    }
+
+    let (name, offset) = match text.find(['+', '-']) {
+        Some(i) => {
+            let offset_text = text[i..].trim_start_matches('+');
+            (text[..i].trim_end(), parse_number(line, offset_text, byte + i)?)
+        }
+        None => (text, 0),
+    };
+
+    if !is_identifier(name) {
+        return Err(line.error(byte, AssembleErrorKind::InvalidLabel(name.to_string())));
+    }
+
+    Ok(Expr::Label {
+        name: name.to_string(),
+        offset,
+    })
+}
+
+fn parse_operand(line: &Line, text: &str, byte: usize) -> Result<Operand, AssembleError> {
+    let invalid = || line.error(byte, AssembleErrorKind::InvalidOperand(text.to_string()));
+
+    if let Some(inner) = text.strip_prefix('[') {
+        let inner = inner.strip_suffix(']').ok_or_else(invalid)?;
+        let inner_byte = skip_whitespace(line.text, byte + 1, byte + 1 + inner.len());
+        return Ok(Operand {
+            mode: ParameterMode::Position,
+            expr: parse_expr(line, inner.trim(), inner_byte)?,
+            negate: false,
+            column: inner_byte,
+        });
+    }
+
+    if let Some(inner) = text.strip_prefix('#') {
+        return Ok(Operand {
+            mode: ParameterMode::Immediate,
+            expr: parse_expr(line, inner, byte + 1)?,
+            negate: false,
+            column: byte + 1,
+        });
+    }
+
+    if let Some(rest) = text.strip_prefix("rb") {
+        let rest_byte = skip_whitespace(line.text, byte + 2, byte + text.len());
+        let rest = rest.trim_start();
+        if rest.is_empty() {
+            return Ok(Operand {
+                mode: ParameterMode::Relative,
+                expr: Expr::Number(0),
+                negate: false,
+                column: byte,
+            });
+        }
+
+        let (negate, inner) = match rest.as_bytes()[0] {
+            b'+' => (false, &rest[1..]),
+            b'-' => (true, &rest[1..]),
+            _ => return Err(invalid()),
+        };
+        let inner_byte = skip_whitespace(line.text, rest_byte + 1, byte + text.len());
+        return Ok(Operand {
+            mode: ParameterMode::Relative,
+            expr: parse_expr(line, inner.trim_start(), inner_byte)?,
+            negate,
+            column: inner_byte,
+        });
+    }
+
+    Err(invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Machine;
+
+    #[test]
+    fn encodes_modes() {
+        let program = assemble("add [4], #-3, rb+7\nrb rb-2\nhlt").unwrap();
+        assert_eq!(program, vec![21001, 4, -3, 7, 209, -2, 99]);
+    }
+
+    #[test]
+    fn resolves_labels_and_data() {
+        let source = "
+            ; echo input until a zero is read
+            loop:   in   [value]
+                    jf   [value], #done
+                    out  [value]
+                    jt   #1, #loop
+            done:   hlt
+            value:  .data 0
+            table:  .data value, table+1, -5
+                    .zero 2
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(
+            program,
+            vec![3, 11, 1006, 11, 10, 4, 11, 1105, 1, 0, 99, 0, 11, 13, -5, 0, 0]
+        );
+
+        let mut m = Machine::new(&program);
+        for v in &[4, 7, 0] {
+            m.input().send(Value(*v)).unwrap();
+        }
+        m.run().unwrap();
+        let output: Vec<Value> = m.output().as_ref().unwrap().try_iter().collect();
+        assert_eq!(output, vec![Value(4), Value(7)]);
+    }
+
+    #[test]
+    fn round_trips_through_decoder() {
+        let source = "
+            add [1], #2, [3]
+            mul rb+1, rb-1, rb
+            in  rb+5
+            out #-12
+            jt  [1], #0
+            jf  #0, rb+3
+            lt  #1, #2, [5]
+            eq  [0], rb+2, rb+9
+            rb  #-4
+            hlt
+        ";
+        let program = assemble(source).unwrap();
+
+        let expected = vec![
+            Instruction::Add {
+                arg1: Arg::Address(Address(1)),
+                arg2: Arg::Value(Value(2)),
+                out: Arg::Address(Address(3)),
+            },
+            Instruction::Mult {
+                arg1: Arg::Offset(Value(1)),
+                arg2: Arg::Offset(Value(-1)),
+                out: Arg::Offset(Value(0)),
+            },
+            Instruction::Input {
+                out: Arg::Offset(Value(5)),
+            },
+            Instruction::Output {
+                arg1: Arg::Value(Value(-12)),
+            },
+            Instruction::JumpIfTrue {
+                cond: Arg::Address(Address(1)),
+                dest: Arg::Value(Value(0)),
+            },
+            Instruction::JumpIfFalse {
+                cond: Arg::Value(Value(0)),
+                dest: Arg::Offset(Value(3)),
+            },
+            Instruction::LessThan {
+                c1: Arg::Value(Value(1)),
+                c2: Arg::Value(Value(2)),
+                out: Arg::Address(Address(5)),
+            },
+            Instruction::Equals {
+                c1: Arg::Address(Address(0)),
+                c2: Arg::Offset(Value(2)),
+                out: Arg::Offset(Value(9)),
+            },
+            Instruction::RelBaseAdjust {
+                amount: Arg::Value(Value(-4)),
+            },
+            Instruction::Terminate,
+        ];
+
+        let mut m = Machine::new(&program);
+        for instruction in &expected {
+            assert_eq!(m.pop_instruction().unwrap(), *instruction);
+        }
+        assert_eq!(m.ip.0, program.len());
+
+        let reencoded: Vec<Integer> = expected.iter().flat_map(|i| i.encode()).collect();
+        assert_eq!(reencoded, program);
+    }
+
+    #[test]
+    fn reports_positions() {
+        let err = assemble("hlt\n  foo [1]").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 3));
+        assert_eq!(err.kind, AssembleErrorKind::UnknownMnemonic("foo".to_string()));
+
+        let err = assemble("add [1], #2, #3").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 14));
+        assert_eq!(err.kind, AssembleErrorKind::ImmediateDestination);
+
+        let err = assemble("out [1]\njt #1, #nowhere").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 9));
+        assert_eq!(err.kind, AssembleErrorKind::UndefinedLabel("nowhere".to_string()));
+
+        let err = assemble("a: hlt\na: hlt").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 1));
+
+        let err = assemble("out [1], [2]").unwrap_err();
+        assert_eq!(
+            err.kind,
+            AssembleErrorKind::OperandCount {
+                expected: 1,
+                found: 2
+            }
+        );
+
+        let err = assemble("out 12").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 5));
+    }
+}
@@ -0,0 +1,144 @@
+//! Machines that run as futures.
+//!
+//! An `AsyncMachine` takes its input from a `Stream` and sends its output to
+//! a `Sink`. Where a synchronous run would return `RunOutcome::NeedsInput`,
+//! it flushes its output and awaits the next input value instead, so many
+//! machines can feed each other on a single-threaded executor.
+//!
+//! Only available with the `async` feature.
+
+use crate::{BufferIo, Error, Integer, Machine, RunOutcome, Value};
+use futures::{Sink, SinkExt, Stream, StreamExt};
+
+#[derive(Debug, PartialEq)]
+pub enum AsyncError<E> {
+    Machine(Error),
+    /// The output sink failed.
+    Output(E),
+}
+
+impl<E> From<Error> for AsyncError<E> {
+    fn from(e: Error) -> Self {
+        AsyncError::Machine(e)
+    }
+}
+
+pub struct AsyncMachine<I, O> {
+    machine: Machine<BufferIo>,
+    input: I,
+    output: O,
+}
+
+impl<I, O> AsyncMachine<I, O>
+where
+    I: Stream<Item = Value> + Unpin,
+    O: Sink<Value> + Unpin,
+{
+    pub fn new(program: &[Integer], input: I, output: O) -> Self {
+        Self::from_machine(Machine::with_io(program, BufferIo::new()), input, output)
+    }
+
+    /// Wraps an existing machine. Anything already queued in its input
+    /// buffer is read before the stream.
+    pub fn from_machine(machine: Machine<BufferIo>, input: I, output: O) -> Self {
+        Self {
+            machine,
+            input,
+            output,
+        }
+    }
+
+    pub fn machine(&self) -> &Machine<BufferIo> {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut Machine<BufferIo> {
+        &mut self.machine
+    }
+
+    pub fn into_parts(self) -> (Machine<BufferIo>, I, O) {
+        (self.machine, self.input, self.output)
+    }
+
+    /// Runs until the program halts, returning `Halted`. If the input
+    /// stream ends while the program is waiting for input, returns
+    /// `NeedsInput`; a budget on the machine can also stop it early.
+    pub async fn run(&mut self) -> Result<RunOutcome, AsyncError<O::Error>> {
+        loop {
+            match self.machine.step()? {
+                None => {}
+                Some(RunOutcome::Output(v)) => {
+                    self.output.feed(v).await.map_err(AsyncError::Output)?;
+                }
+                Some(RunOutcome::NeedsInput) => {
+                    // Peers may be waiting on our output before they produce
+                    // the input we need.
+                    self.output.flush().await.map_err(AsyncError::Output)?;
+                    match self.input.next().await {
+                        Some(v) => self.machine.io_mut().input.push_back(v),
+                        None => return Ok(RunOutcome::NeedsInput),
+                    }
+                }
+                Some(outcome) => {
+                    self.output.flush().await.map_err(AsyncError::Output)?;
+                    return Ok(outcome);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::channel::mpsc;
+    use futures::executor::block_on;
+    use futures::future::join_all;
+
+    #[test]
+    fn echoes_stream_to_sink() {
+        // loop: in [9]; out [9]; jt #1, #loop
+        let program = [3, 9, 4, 9, 1105, 1, 0, 99, 0, 0];
+        let input = futures::stream::iter(vec![Value(1), Value(2), Value(3)]);
+        let (tx, rx) = mpsc::unbounded();
+
+        let mut m = AsyncMachine::new(&program, input, tx);
+        assert_eq!(block_on(m.run()), Ok(RunOutcome::NeedsInput));
+        drop(m);
+        assert_eq!(
+            block_on(rx.collect::<Vec<_>>()),
+            vec![Value(1), Value(2), Value(3)]
+        );
+    }
+
+    #[test]
+    fn amplifier_ring_on_one_thread() {
+        let program = [
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+        let phases = [9, 8, 7, 6, 5];
+
+        let channels: Vec<_> = (0..5).map(|_| mpsc::unbounded::<Value>()).collect();
+        let (senders, receivers): (Vec<_>, Vec<_>) = channels.into_iter().unzip();
+
+        for (tx, phase) in senders.iter().zip(phases.iter()) {
+            tx.unbounded_send(Value(*phase)).unwrap();
+        }
+        senders[0].unbounded_send(Value(0)).unwrap();
+
+        // Machine i reads channel i and writes channel i + 1, wrapping.
+        let mut machines: Vec<_> = receivers
+            .into_iter()
+            .enumerate()
+            .map(|(i, rx)| AsyncMachine::new(&program, rx, senders[(i + 1) % 5].clone()))
+            .collect();
+
+        let results = block_on(join_all(machines.iter_mut().map(|m| m.run())));
+        assert!(results.iter().all(|r| *r == Ok(RunOutcome::Halted)));
+
+        // The last machine's final output is left unread in channel 0.
+        let (_, mut rx, _) = machines.remove(0).into_parts();
+        assert_eq!(rx.try_recv(), Ok(Value(139629729)));
+    }
+}
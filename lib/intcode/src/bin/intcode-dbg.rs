@@ -0,0 +1,371 @@
+//! Interactive Intcode debugger.
+//!
+//! Usage: intcode-dbg [program-file]   (defaults to input.txt)
+//!
+//! Type `help` at the prompt for the list of commands.
+
+use intcode::{Address, Error, Integer, Machine, Program, RunOutcome, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+commands:
+  s, step [n]          execute n instructions (default 1)
+  c, continue          run until a breakpoint, watchpoint, input wait or halt
+  o, next-output       run until the program produces an output
+  b, break <addr>      stop before executing the instruction at addr
+  d, delete <addr>     remove a breakpoint
+  w, watch <addr>      stop after the value at addr changes
+  u, unwatch <addr>    remove a watchpoint
+  i, info              show ip, relbase, state, breakpoints and watchpoints
+  x <addr> [n]         examine n memory cells starting at addr
+  l, list [addr] [n]   decode n instructions starting at addr (default ip)
+  p, poke <addr> <v>   write v to memory at addr
+  in <v> [v...]        queue input values
+  q, quit              exit";
+
+/// The most cells `x` shows, or instructions `list` decodes, at once.
+const MAX_COUNT: usize = 4096;
+
+#[derive(Debug, PartialEq)]
+enum Stop {
+    Stepped,
+    Breakpoint(Address),
+    Watchpoint {
+        address: Address,
+        old: Integer,
+        new: Integer,
+    },
+    Output,
+    NeedsInput,
+    Halted,
+    Fault(Error),
+}
+
+struct Debugger<W: Write> {
+    machine: Machine,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeMap<usize, Integer>,
+    out: W,
+}
+
+fn parse<T: std::str::FromStr>(word: Option<&str>, what: &str) -> Result<T, String> {
+    let word = word.ok_or_else(|| format!("missing {}", what))?;
+    word.parse()
+        .map_err(|_| format!("invalid {} '{}'", what, word))
+}
+
+/// The count in `word`, `default` if there is none.
+fn parse_count(word: Option<&str>, default: usize) -> Result<usize, String> {
+    let count = word.map_or(Ok(default), |w| parse(Some(w), "count"))?;
+    if count > MAX_COUNT {
+        return Err(format!("count {} is over the limit of {}", count, MAX_COUNT));
+    }
+    Ok(count)
+}
+
+impl<W: Write> Debugger<W> {
+    fn new(program: &[Integer], out: W) -> Self {
+        Self {
+            machine: Machine::new(program),
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+            out,
+        }
+    }
+
+    /// Executes one instruction, reporting outputs and watchpoint hits, and
+    /// the instruction itself if `echo` is set.
+    fn step(&mut self, echo: bool) -> io::Result<Option<Stop>> {
+        let ip = self.machine.ip();
+        if let (true, Ok(instruction)) = (echo, self.machine.instruction_at(ip)) {
+            writeln!(self.out, "  {:05}  {}", ip.0, instruction)?;
+        }
+
+        let mut stop = None;
+        match self.machine.step() {
+            Ok(None) => {}
+            Ok(Some(RunOutcome::Output(v))) => {
+                writeln!(self.out, "output: {}", v.0)?;
+                stop = Some(Stop::Output);
+            }
+            Ok(Some(RunOutcome::NeedsInput)) => return Ok(Some(Stop::NeedsInput)),
+            Ok(Some(RunOutcome::Halted)) => return Ok(Some(Stop::Halted)),
+            // The debugger never sets a budget, so nothing else can stop a step.
+            Ok(Some(_)) => return Ok(Some(Stop::Stepped)),
+            Err(e) => return Ok(Some(Stop::Fault(e))),
+        }
+
+        for (address, old) in self.watchpoints.iter_mut() {
+            let new = self.machine.peek(Address(*address)).0;
+            if new != *old {
+                stop = Some(Stop::Watchpoint {
+                    address: Address(*address),
+                    old: *old,
+                    new,
+                });
+                *old = new;
+            }
+        }
+
+        Ok(stop)
+    }
+
+    /// Steps until `until` accepts a stop reason. Breakpoints are checked
+    /// before every instruction except the first, so `continue` can leave
+    /// the breakpoint it is sitting on. Only explicit steps are echoed.
+    fn resume<F: Fn(&Stop) -> bool>(&mut self, limit: Option<usize>, until: F) -> io::Result<Stop> {
+        let echo = limit.is_some();
+        let mut count = 0;
+        loop {
+            if count > 0 && self.breakpoints.contains(&self.machine.ip().0) {
+                return Ok(Stop::Breakpoint(self.machine.ip()));
+            }
+            if limit == Some(count) {
+                return Ok(Stop::Stepped);
+            }
+
+            count += 1;
+            if let Some(stop) = self.step(echo)? {
+                if until(&stop) {
+                    return Ok(stop);
+                }
+            }
+        }
+    }
+
+    fn report(&mut self, stop: &Stop) -> io::Result<()> {
+        match stop {
+            Stop::Stepped | Stop::Output => Ok(()),
+            Stop::Breakpoint(a) => writeln!(self.out, "breakpoint at {}", a.0),
+            Stop::Watchpoint { address, old, new } => {
+                writeln!(self.out, "watch [{}]: {} -> {}", address.0, old, new)
+            }
+            Stop::NeedsInput => writeln!(self.out, "waiting for input"),
+            Stop::Halted => writeln!(self.out, "halted"),
+            Stop::Fault(e) => writeln!(self.out, "fault: {}", e),
+        }
+    }
+
+    fn info(&mut self) -> io::Result<()> {
+        let state = if self.machine.is_terminated() {
+            "halted"
+        } else {
+            "running"
+        };
+        writeln!(
+            self.out,
+            "ip={} relbase={} state={}",
+            self.machine.ip().0,
+            self.machine.relbase().0,
+            state
+        )?;
+        writeln!(self.out, "breakpoints: {:?}", self.breakpoints)?;
+        writeln!(self.out, "watchpoints: {:?}", self.watchpoints)
+    }
+
+    fn list(&mut self, start: usize, count: usize) -> io::Result<()> {
+        let mut address = Address(start);
+        for _ in 0..count {
+            let marker = if address == self.machine.ip() { "=>" } else { "  " };
+            match self.machine.instruction_at(address) {
+                Ok(instruction) => {
+                    writeln!(self.out, "{} {:05}  {}", marker, address.0, instruction)?;
+                    address.0 += instruction.size();
+                }
+                Err(_) => {
+                    let v = self.machine.peek(address).0;
+                    writeln!(self.out, "{} {:05}  .data {}", marker, address.0, v)?;
+                    address.0 += 1;
+                }
+            }
+            if address.0 >= self.machine.memory().len() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs one command line. Returns `Ok(false)` when the user quits.
+    fn command(&mut self, line: &str) -> Result<bool, String> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(c) => c,
+            None => return Ok(true),
+        };
+
+        let io_err = |e: io::Error| e.to_string();
+        match command {
+            "s" | "step" => {
+                let n = words.next().map_or(Ok(1), |w| parse(Some(w), "count"))?;
+                let stop = self
+                    .resume(Some(n), |s| *s != Stop::Output)
+                    .map_err(io_err)?;
+                self.report(&stop).map_err(io_err)?;
+            }
+            "c" | "continue" => {
+                let stop = self
+                    .resume(None, |s| *s != Stop::Output)
+                    .map_err(io_err)?;
+                self.report(&stop).map_err(io_err)?;
+            }
+            "o" | "next-output" => {
+                let stop = self.resume(None, |_| true).map_err(io_err)?;
+                self.report(&stop).map_err(io_err)?;
+            }
+            "b" | "break" => {
+                self.breakpoints.insert(parse(words.next(), "address")?);
+            }
+            "d" | "delete" => {
+                let address: usize = parse(words.next(), "address")?;
+                if !self.breakpoints.remove(&address) {
+                    return Err(format!("no breakpoint at {}", address));
+                }
+            }
+            "w" | "watch" => {
+                let address: usize = parse(words.next(), "address")?;
+                let value = self.machine.peek(Address(address)).0;
+                self.watchpoints.insert(address, value);
+            }
+            "u" | "unwatch" => {
+                let address: usize = parse(words.next(), "address")?;
+                if self.watchpoints.remove(&address).is_none() {
+                    return Err(format!("no watchpoint at {}", address));
+                }
+            }
+            "i" | "info" => self.info().map_err(io_err)?,
+            "x" => {
+                let start: usize = parse(words.next(), "address")?;
+                let count = parse_count(words.next(), 1)?;
+                let end = start
+                    .checked_add(count)
+                    .ok_or_else(|| format!("range {}+{} is out of bounds", start, count))?;
+                let values: Vec<String> = (start..end)
+                    .map(|a| self.machine.peek(Address(a)).0.to_string())
+                    .collect();
+                writeln!(self.out, "{:05}: {}", start, values.join(" ")).map_err(io_err)?;
+            }
+            "l" | "list" => {
+                let start = words
+                    .next()
+                    .map_or(Ok(self.machine.ip().0), |w| parse(Some(w), "address"))?;
+                let count = parse_count(words.next(), 10)?;
+                self.list(start, count).map_err(io_err)?;
+            }
+            "p" | "poke" => {
+                let address: usize = parse(words.next(), "address")?;
+                let value: Integer = parse(words.next(), "value")?;
+                self.machine
+                    .poke(Address(address), Value(value))
+                    .map_err(|e| e.to_string())?;
+                if let Some(old) = self.watchpoints.get_mut(&address) {
+                    *old = value;
+                }
+            }
+            "in" => {
+                let mut count = 0;
+                for word in words {
+                    let value: Integer = parse(Some(word), "value")?;
+                    self.machine.input().send(Value(value)).unwrap();
+                    count += 1;
+                }
+                if count == 0 {
+                    return Err("missing value".to_string());
+                }
+            }
+            "h" | "help" => writeln!(self.out, "{}", HELP).map_err(io_err)?,
+            "q" | "quit" => return Ok(false),
+            _ => return Err(format!("unknown command '{}', try 'help'", command)),
+        }
+        Ok(true)
+    }
+}
+
+fn main() {
+    let path = std::env::args().nth(1).unwrap_or_else(|| "input.txt".to_string());
+    let program = match Program::from_path(&path) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+
+    let stdout = io::stdout();
+    let mut debugger = Debugger::new(&program, stdout.lock());
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    loop {
+        print!("(dbg) ");
+        io::stdout().flush().unwrap();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        match debugger.command(&line) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(message) => eprintln!("{}", message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // in [11]; loop: add [11], #1, [11]; out [11]; jt #1, #loop
+    const COUNTER: [Integer; 12] = [3, 11, 1001, 11, 1, 11, 4, 11, 1105, 1, 2, 0];
+
+    fn run(debugger: &mut Debugger<Vec<u8>>, commands: &[&str]) -> String {
+        debugger.out.clear();
+        for c in commands {
+            debugger.command(c).unwrap();
+        }
+        String::from_utf8(debugger.out.clone()).unwrap()
+    }
+
+    #[test]
+    fn stops_for_input_and_outputs() {
+        let mut d = Debugger::new(&COUNTER, Vec::new());
+        assert_eq!(run(&mut d, &["c"]), "waiting for input\n");
+
+        let text = run(&mut d, &["in 5", "o"]);
+        assert!(text.ends_with("output: 6\n"), "{}", text);
+        assert_eq!(d.machine.ip(), Address(8));
+    }
+
+    #[test]
+    fn breakpoints_and_watchpoints() {
+        let mut d = Debugger::new(&COUNTER, Vec::new());
+        run(&mut d, &["in 1", "b 6"]);
+        assert!(run(&mut d, &["c"]).ends_with("breakpoint at 6\n"));
+
+        run(&mut d, &["d 6", "w 11"]);
+        let text = run(&mut d, &["c"]);
+        assert!(text.ends_with("watch [11]: 2 -> 3\n"), "{}", text);
+    }
+
+    #[test]
+    fn step_poke_and_examine() {
+        let mut d = Debugger::new(&COUNTER, Vec::new());
+        run(&mut d, &["in 1", "s 2"]);
+        assert_eq!(d.machine.ip(), Address(6));
+
+        assert_eq!(run(&mut d, &["p 11 40", "x 10 2"]), "00010: 2 40\n");
+        assert_eq!(run(&mut d, &["s"]), "  00006  out [11]\noutput: 40\n");
+        assert!(d.command(&format!("x {} 2", usize::MAX)).is_err());
+        assert_eq!(
+            d.command("x 0 1000000000000"),
+            Err("count 1000000000000 is over the limit of 4096".to_string())
+        );
+        assert!(d.command("l 0 5000").is_err());
+        assert_eq!(run(&mut d, &["x 0 4096"]).split(' ').count(), 4097);
+        assert!(d.command("bogus").is_err());
+        assert_eq!(d.command("q"), Ok(false));
+
+        let mut d = Debugger::new(&[42], Vec::new());
+        assert_eq!(run(&mut d, &["s"]), "fault: invalid instruction 42 at 0\n");
+    }
+}
@@ -0,0 +1,19 @@
+//! Prints an annotated listing of an Intcode program.
+//!
+//! Usage: intcode-disasm [program-file]   (defaults to input.txt)
+
+use intcode::disassembler::disassemble;
+use intcode::Program;
+
+fn main() {
+    let path = std::env::args().nth(1).unwrap_or_else(|| "input.txt".to_string());
+    let program = match Program::from_path(&path) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+
+    print!("{}", disassemble(&program));
+}
@@ -0,0 +1,419 @@
+//! Runs an Intcode program on the terminal.
+//!
+//! Usage: intcode-run [options] [program-file]   (defaults to input.txt)
+//!
+//! In ASCII mode (the default) each line typed is sent as text and output is
+//! printed as text; output values that are not characters are printed as
+//! numbers on their own line. In raw mode each line typed holds integers
+//! separated by commas or spaces, and each output is printed on its own line.
+//!
+//! A transcript is the input lines of a session, one per line. `--replay`
+//! feeds one to the program before reading stdin, and `--record` saves the
+//! session, replayed lines included, so it can be picked up again later.
+//!
+//! Memory is dense and limited to `DEFAULT_MEMORY_LIMIT` cells unless
+//! `--memory-limit` says otherwise, so a stray write fails with an error
+//! rather than exhausting memory. `--sparse` lifts the default limit, as
+//! sparse memory only allocates the pages that are written.
+
+use intcode::{
+    Address, AsciiMachine, AsciiOutput, BufferIo, Integer, Machine, MemoryKind, Program,
+    RunOutcome, Value,
+};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, Write};
+
+const USAGE: &str = "\
+usage: intcode-run [options] [program-file]
+options:
+  --raw                read and print integers instead of ASCII text
+  --poke <addr>=<v>    write v to memory at addr before starting
+  --memory-limit <n>   fail writes at or beyond address n
+  --sparse             use sparse memory, with no limit by default
+  --replay <file>      send the lines of a transcript before reading stdin
+  --record <file>      save every input line sent to a transcript";
+
+/// The memory limit in cells when none is given and memory is dense.
+const DEFAULT_MEMORY_LIMIT: usize = 1 << 24;
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    path: String,
+    raw: bool,
+    pokes: Vec<(Address, Value)>,
+    memory_limit: Option<usize>,
+    sparse: bool,
+    replay: Option<String>,
+    record: Option<String>,
+}
+
+fn parse_poke(arg: &str) -> Result<(Address, Value), String> {
+    let invalid = || format!("invalid poke '{}', expected <addr>=<v>", arg);
+    let mut parts = arg.splitn(2, '=');
+    let address = parts
+        .next()
+        .and_then(|a| a.trim().parse().ok())
+        .ok_or_else(invalid)?;
+    let value = parts
+        .next()
+        .and_then(|v| v.trim().parse().ok())
+        .ok_or_else(invalid)?;
+    Ok((Address(address), Value(value)))
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options {
+        path: "input.txt".to_string(),
+        raw: false,
+        pokes: Vec::new(),
+        memory_limit: None,
+        sparse: false,
+        replay: None,
+        record: None,
+    };
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("{} needs a value", name));
+        match arg.as_str() {
+            "--raw" => options.raw = true,
+            "--poke" => options.pokes.push(parse_poke(&value("--poke")?)?),
+            "--memory-limit" => {
+                let limit = value("--memory-limit")?;
+                let limit = limit
+                    .parse()
+                    .map_err(|_| format!("invalid memory limit '{}'", limit))?;
+                options.memory_limit = Some(limit);
+            }
+            "--sparse" => options.sparse = true,
+            "--replay" => options.replay = Some(value("--replay")?),
+            "--record" => options.record = Some(value("--record")?),
+            _ if arg.starts_with("--") => return Err(format!("unknown option '{}'", arg)),
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+    if let Some(path) = path {
+        options.path = path;
+    }
+    Ok(options)
+}
+
+/// Input lines: the replayed transcript first, then the terminal. Lines
+/// are written to the recording, if any, once the session accepts them.
+struct Input<R> {
+    replay: VecDeque<String>,
+    terminal: io::Lines<R>,
+    record: Option<Box<dyn Write>>,
+}
+
+impl<R: BufRead> Input<R> {
+    /// Returns the next line, and whether it came from the transcript.
+    fn next_line(&mut self) -> io::Result<Option<(String, bool)>> {
+        match self.replay.pop_front() {
+            Some(line) => Ok(Some((line, true))),
+            None => Ok(self.terminal.next().transpose()?.map(|line| (line, false))),
+        }
+    }
+
+    fn record(&mut self, line: &str) -> io::Result<()> {
+        if let Some(record) = &mut self.record {
+            writeln!(record, "{}", line)?;
+            record.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn run_ascii<R: BufRead, W: Write>(
+    machine: Machine<BufferIo>,
+    input: &mut Input<R>,
+    out: &mut W,
+) -> Result<(), String> {
+    let mut machine = AsciiMachine::from_machine(machine);
+    loop {
+        for output in machine.read_until_prompt().map_err(|e| e.to_string())? {
+            match output {
+                AsciiOutput::Line(line) => writeln!(out, "{}", line),
+                AsciiOutput::Value(v) => writeln!(out, "{}", v.0),
+            }
+            .map_err(|e| e.to_string())?;
+        }
+        if machine.machine().is_terminated() {
+            return Ok(());
+        }
+
+        let (line, replayed) = match input.next_line().map_err(|e| e.to_string())? {
+            Some(next) => next,
+            None => return Ok(()),
+        };
+        if replayed {
+            writeln!(out, "{}", line).map_err(|e| e.to_string())?;
+        }
+        input.record(&line).map_err(|e| e.to_string())?;
+        machine.send_line(&line);
+    }
+}
+
+fn parse_values(line: &str) -> Result<Vec<Value>, String> {
+    line.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            word.parse::<Integer>()
+                .map(Value)
+                .map_err(|_| format!("invalid value '{}'", word))
+        })
+        .collect()
+}
+
+fn run_raw<R: BufRead, W: Write>(
+    mut machine: Machine<BufferIo>,
+    input: &mut Input<R>,
+    out: &mut W,
+) -> Result<(), String> {
+    loop {
+        let outcome = machine.run().map_err(|e| e.to_string())?;
+        for v in machine.io_mut().output.drain(..) {
+            writeln!(out, "{}", v.0).map_err(|e| e.to_string())?;
+        }
+        if outcome != RunOutcome::NeedsInput {
+            return Ok(());
+        }
+
+        // A line that doesn't parse is reported and skipped, not recorded.
+        let values = loop {
+            let (line, replayed) = match input.next_line().map_err(|e| e.to_string())? {
+                Some(next) => next,
+                None => return Ok(()),
+            };
+            if replayed {
+                writeln!(out, "{}", line).map_err(|e| e.to_string())?;
+            }
+            match parse_values(&line) {
+                Ok(values) => {
+                    input.record(&line).map_err(|e| e.to_string())?;
+                    break values;
+                }
+                Err(message) => eprintln!("{}", message),
+            }
+        };
+        machine.io_mut().input.extend(values);
+    }
+}
+
+/// Loads `program` with the memory options and pokes in `options`.
+fn load(program: &[Integer], options: &Options) -> Result<Machine<BufferIo>, String> {
+    let mut machine = Machine::with_io(program, BufferIo::new());
+    let limit = match options.memory_limit {
+        Some(limit) => Some(limit),
+        None if options.sparse => None,
+        None => Some(DEFAULT_MEMORY_LIMIT),
+    };
+    machine.set_memory_limit(limit);
+    if options.sparse {
+        machine
+            .set_memory_kind(MemoryKind::Sparse)
+            .map_err(|e| e.to_string())?;
+    }
+    for (address, value) in &options.pokes {
+        machine
+            .poke(*address, *value)
+            .map_err(|_| format!("cannot poke {}: beyond the memory limit", address.0))?;
+    }
+    Ok(machine)
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let program =
+        Program::from_path(&options.path).map_err(|e| format!("{}: {}", options.path, e))?;
+    let machine = load(&program, options)?;
+
+    let replay = match &options.replay {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path, e))?
+            .lines()
+            .map(str::to_string)
+            .collect(),
+        None => VecDeque::new(),
+    };
+    let record = match &options.record {
+        Some(path) => {
+            let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+            Some(Box::new(file) as Box<dyn Write>)
+        }
+        None => None,
+    };
+
+    let stdin = io::stdin();
+    let mut input = Input {
+        replay,
+        terminal: stdin.lock().lines(),
+        record,
+    };
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if options.raw {
+        run_raw(machine, &mut input, &mut out)
+    } else {
+        run_ascii(machine, &mut input, &mut out)
+    }
+}
+
+fn main() {
+    let result = parse_args(std::env::args().skip(1)).and_then(|options| run(&options));
+    if let Err(message) = result {
+        eprintln!("{}", message);
+        if message.starts_with("unknown option") || message.starts_with("unexpected argument") {
+            eprintln!("{}", USAGE);
+        }
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn args(s: &str) -> impl Iterator<Item = String> + '_ {
+        s.split_whitespace().map(str::to_string)
+    }
+
+    /// A `Write` whose contents can still be read after it is boxed.
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn lines<'a>(replay: &[&str], terminal: &'a str, record: &Shared) -> Input<&'a [u8]> {
+        Input {
+            replay: replay.iter().map(|s| s.to_string()).collect(),
+            terminal: terminal.as_bytes().lines(),
+            record: Some(Box::new(record.clone())),
+        }
+    }
+
+    #[test]
+    fn parses_options() {
+        let options = parse_args(args(
+            "--raw --poke 0=2 --poke 5=-1 --memory-limit 100 --sparse --record out.txt prog.txt",
+        ))
+        .unwrap();
+        assert_eq!(
+            options,
+            Options {
+                path: "prog.txt".to_string(),
+                raw: true,
+                pokes: vec![(Address(0), Value(2)), (Address(5), Value(-1))],
+                memory_limit: Some(100),
+                sparse: true,
+                replay: None,
+                record: Some("out.txt".to_string()),
+            }
+        );
+        assert_eq!(parse_args(args("")).unwrap().path, "input.txt");
+        assert!(parse_args(args("--poke 0")).is_err());
+        assert!(parse_args(args("--poke")).is_err());
+        assert!(parse_args(args("--memory-limit -1")).is_err());
+        assert!(parse_args(args("--bogus")).is_err());
+        assert!(parse_args(args("a b")).is_err());
+    }
+
+    #[test]
+    fn limits_memory() {
+        let program = [99];
+        let far = "--poke 4000000000000=1";
+        let options = parse_args(args(far)).unwrap();
+        assert_eq!(
+            load(&program, &options).unwrap_err(),
+            "cannot poke 4000000000000: beyond the memory limit"
+        );
+        let options = parse_args(args("--memory-limit 10 --poke 10=1")).unwrap();
+        assert!(load(&program, &options).is_err());
+        let options = parse_args(args("--memory-limit 10 --poke 9=1")).unwrap();
+        assert_eq!(load(&program, &options).unwrap().memory().len(), 10);
+
+        let options = parse_args(args(&format!("--sparse {}", far))).unwrap();
+        let machine = load(&program, &options).unwrap();
+        assert_eq!(machine.peek(Address(4_000_000_000_000)), Value(1));
+
+        // The program's own writes are limited too: add #1, #1, [2^24]
+        let program = [1101, 1, 1, 1 << 24, 99];
+        let options = parse_args(args("--raw")).unwrap();
+        let mut input = lines(&[], "", &Shared::default());
+        let result = run_raw(
+            load(&program, &options).unwrap(),
+            &mut input,
+            &mut Vec::new(),
+        );
+        assert_eq!(
+            result,
+            Err("write to 16777216 beyond the memory limit at 0".to_string())
+        );
+    }
+
+    #[test]
+    fn ascii_session_is_replayed_and_recorded() {
+        // Echoes each character until it reads 'q', then prints 1000 and halts.
+        let program = intcode::assembler::assemble(
+            "
+            loop:   in   [c]
+                    eq   [c], #113, [q]
+                    jt   [q], #done
+                    out  [c]
+                    jt   #1, #loop
+            done:   out  #1000
+                    hlt
+            c:      .data 0
+            q:      .data 0
+            ",
+        )
+        .unwrap();
+        let machine = Machine::with_io(&program, BufferIo::new());
+
+        let record = Shared::default();
+        let mut input = lines(&["ab"], "cd\nq\nunused\n", &record);
+        let mut out = Vec::new();
+        run_ascii(machine, &mut input, &mut out).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "ab\nab\ncd\n1000\n");
+        assert_eq!(
+            String::from_utf8(record.0.borrow().clone()).unwrap(),
+            "ab\ncd\nq\n"
+        );
+    }
+
+    #[test]
+    fn raw_session() {
+        // Doubles every input: in [11]; mul [11], #2, [11]; out [11]; jt #1, #0
+        let program = [3, 11, 1002, 11, 2, 11, 4, 11, 1105, 1, 0, 0];
+        let mut machine = Machine::with_io(&program, BufferIo::new());
+        machine.poke(Address(4), Value(3)).unwrap();
+
+        let record = Shared::default();
+        let mut input = lines(&[], "1, 2\nx\n-5\n", &record);
+        let mut out = Vec::new();
+        run_raw(machine, &mut input, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "3\n6\n-15\n");
+        assert_eq!(
+            String::from_utf8(record.0.borrow().clone()).unwrap(),
+            "1, 2\n-5\n"
+        );
+
+        let mut input = lines(&[], "", &record);
+        let machine = Machine::with_io(&[42], BufferIo::new());
+        assert_eq!(
+            run_raw(machine, &mut input, &mut Vec::new()),
+            Err("invalid instruction 42 at 0".to_string())
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! Instruction decoding shared by the engines that do not go through
+//! `Instruction`, so that they fault on the same instructions, and at the
+//! same points, as `Machine`.
+
+use crate::arithmetic::Word;
+use crate::{Address, Error, Integer, Value};
+use std::convert::TryFrom;
+
+pub(crate) const ADD: u8 = 1;
+pub(crate) const MULT: u8 = 2;
+pub(crate) const INPUT: u8 = 3;
+pub(crate) const OUTPUT: u8 = 4;
+pub(crate) const JUMP_IF_TRUE: u8 = 5;
+pub(crate) const JUMP_IF_FALSE: u8 = 6;
+pub(crate) const LESS_THAN: u8 = 7;
+pub(crate) const EQUALS: u8 = 8;
+pub(crate) const REL_BASE_ADJUST: u8 = 9;
+pub(crate) const TERMINATE: u8 = 99;
+
+/// The longest instruction: an opcode and three parameters.
+pub(crate) const MAX_SIZE: usize = 4;
+
+/// The opcode and parameter modes of an instruction, before its parameters
+/// are read.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Header {
+    pub opcode: u8,
+    pub arity: usize,
+    pub modes: [u8; 3],
+}
+
+pub(crate) fn invalid_instruction(value: Integer, ip: usize) -> Error {
+    Error::InvalidInstruction {
+        instruction_value: Value(value),
+        instruction_location: Address(ip),
+    }
+}
+
+pub(crate) fn invalid_address(address: Integer, ip: usize) -> Error {
+    Error::InvalidAddress {
+        invalid_address: Value(address),
+        ip: Address(ip),
+    }
+}
+
+/// Splits the instruction `value` at `ip` into its opcode and modes.
+#[inline]
+pub(crate) fn header(value: Integer, ip: usize) -> Result<Header, Error> {
+    let invalid = invalid_instruction(value, ip);
+    if value < 0 {
+        return Err(invalid);
+    }
+
+    let opcode = (value % 100) as u8;
+    let arity = match opcode {
+        ADD | MULT | LESS_THAN | EQUALS => 3,
+        JUMP_IF_TRUE | JUMP_IF_FALSE => 2,
+        INPUT | OUTPUT | REL_BASE_ADJUST => 1,
+        TERMINATE => 0,
+        _ => return Err(invalid),
+    };
+
+    // Digits beyond the three mode digits are ignored, as on `Machine`.
+    let mut modes = [0; 3];
+    let mut digits = value / 100;
+    for mode in &mut modes {
+        *mode = (digits % 10) as u8;
+        digits /= 10;
+        if *mode > 2 {
+            return Err(invalid);
+        }
+    }
+    Ok(Header {
+        opcode,
+        arity,
+        modes,
+    })
+}
+
+/// The address `offset` from `relbase`, for the instruction at `ip`.
+#[inline]
+pub(crate) fn relative(relbase: usize, offset: Integer, ip: usize) -> Result<usize, Error> {
+    match (relbase as Integer).checked_add(offset) {
+        Some(address) if address >= 0 => Ok(address as usize),
+        _ => Err(invalid_address(
+            (relbase as Integer).wrapping_add(offset),
+            ip,
+        )),
+    }
+}
+
+/// An instruction with its parameters, as cells of memory.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Decoded<W> {
+    pub opcode: u8,
+    pub modes: [u8; 3],
+    /// The parameters; those past the instruction's arity are zero.
+    pub params: [W; 3],
+    pub size: u8,
+}
+
+/// Decodes the instruction at `ip`. A parameter past the end of memory, or
+/// a negative one in position mode, fails here, before anything runs.
+#[inline]
+pub(crate) fn decode<W: Word>(memory: &[W], ip: usize) -> Result<Decoded<W>, Error> {
+    let cell = memory.get(ip).cloned().unwrap_or_else(|| W::from(0));
+    let value = cell
+        .to_integer()
+        .ok_or_else(|| invalid_instruction(cell.saturate(), ip))?;
+    let header = header(value, ip)?;
+
+    let mut params = [W::from(0), W::from(0), W::from(0)];
+    for (i, param) in params.iter_mut().enumerate().take(header.arity) {
+        let cell = memory
+            .get(ip + 1 + i)
+            .ok_or_else(|| invalid_instruction(value, ip))?;
+        if header.modes[i] == 0 && *cell < W::from(0) {
+            return Err(invalid_address(cell.saturate(), ip));
+        }
+        *param = cell.clone();
+    }
+    Ok(Decoded {
+        opcode: header.opcode,
+        modes: header.modes,
+        params,
+        size: 1 + header.arity as u8,
+    })
+}
+
+impl<W: Word> Decoded<W> {
+    /// The address parameter `i` names, in position or relative mode.
+    #[inline(always)]
+    fn address(&self, i: usize, relbase: usize, ip: usize) -> Result<usize, Error> {
+        let param = &self.params[i];
+        let offset = param
+            .to_integer()
+            .ok_or_else(|| invalid_address(param.saturate(), ip))?;
+        if self.modes[i] == 0 {
+            return usize::try_from(offset).map_err(|_| invalid_address(offset, ip));
+        }
+        relative(relbase, offset, ip)
+    }
+
+    /// The value of parameter `i`; addresses past the end of `memory` read
+    /// as zero.
+    #[inline(always)]
+    pub fn read(&self, i: usize, relbase: usize, ip: usize, memory: &[W]) -> Result<W, Error> {
+        if self.modes[i] == 1 {
+            return Ok(self.params[i].clone());
+        }
+        let address = self.address(i, relbase, ip)?;
+        Ok(memory.get(address).cloned().unwrap_or_else(|| W::from(0)))
+    }
+
+    /// The address parameter `i` writes to.
+    #[inline(always)]
+    pub fn write_address(&self, i: usize, relbase: usize, ip: usize) -> Result<usize, Error> {
+        if self.modes[i] == 1 {
+            return Err(Error::WriteToImmediate { ip: Address(ip) });
+        }
+        self.address(i, relbase, ip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbolic::{explore, PathEnd};
+    use crate::wide::WideMachine;
+    use crate::{BufferIo, FastMachine, Machine};
+
+    /// Programs that fault, one for each way decoding or running can.
+    const FAULTS: [&[Integer]; 8] = [
+        // The second add runs off the end of memory.
+        &[1101, 1, 2, 5, 1101, 1, 2],
+        // Relative address -1.
+        &[204, -1, 99],
+        // Jump to -3.
+        &[1105, 1, -3],
+        // Write to an immediate.
+        &[11101, 1, 1, 1, 99],
+        // Relative base -1.
+        &[109, 2, 109, -3, 99],
+        // Position-mode parameter -2.
+        &[1101, 0, 0, 7, 4, -2, 99],
+        // Mode 3.
+        &[3101, 1, 1, 0, 99],
+        // 2^62 * 4 overflows.
+        &[1102, 1 << 62, 4, 5, 99],
+    ];
+
+    #[test]
+    fn engines_fault_alike() {
+        for program in FAULTS.iter() {
+            let expected = Machine::with_io(program, BufferIo::new()).run();
+            assert!(expected.is_err(), "{:?}", program);
+
+            let fast = FastMachine::new(program).run(&mut BufferIo::new());
+            assert_eq!(fast, expected, "{:?}", program);
+            let mut wide = WideMachine::<Integer>::new(program);
+            let result = wide.run(&mut Default::default(), &mut Vec::new());
+            assert_eq!(result, expected, "{:?}", program);
+            let summary = explore(program).unwrap();
+            assert_eq!(
+                summary.paths[0].end,
+                PathEnd::Fault(expected.unwrap_err()),
+                "{:?}",
+                program
+            );
+        }
+    }
+}
@@ -0,0 +1,324 @@
+//! Control-flow aware disassembler.
+//!
+//! Decoding starts at address 0 and follows every statically known jump
+//! target. Cells that are never reached are reported as data. The listing
+//! produced by the `Display` impl uses the same syntax as the assembler, so
+//! it can be edited and assembled again.
+
+use crate::{Address, Arg, Instruction, Integer, Machine, Memory};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Entry {
+    Code {
+        address: Address,
+        instruction: Instruction,
+    },
+    Data {
+        address: Address,
+        value: Integer,
+    },
+}
+
+impl Entry {
+    pub fn address(&self) -> Address {
+        match self {
+            Entry::Code { address, .. } | Entry::Data { address, .. } => *address,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Disassembly {
+    pub entries: Vec<Entry>,
+    /// Generated names for jump destinations, keyed by address.
+    pub labels: BTreeMap<usize, String>,
+    /// Jumps whose destination is not an immediate value.
+    pub indirect_jumps: Vec<Address>,
+    program: Vec<Integer>,
+}
+
+/// Decodes the instruction at `address`, or `None` if the cells there do not
+/// form a complete, valid instruction.
+fn decode(machine: &Machine, address: usize) -> Option<Instruction> {
+    machine.instruction_at(Address(address)).ok()
+}
+
+fn immediate(arg: Arg) -> Option<Integer> {
+    match arg {
+        Arg::Value(v) => Some(v.0),
+        _ => None,
+    }
+}
+
+pub fn disassemble(program: &Memory) -> Disassembly {
+    let machine = Machine::new(program);
+    let len = program.len();
+
+    let mut instructions: BTreeMap<usize, Instruction> = BTreeMap::new();
+    let mut covered = vec![false; len];
+    let mut targets = BTreeSet::new();
+    let mut indirect_jumps = Vec::new();
+    let mut work = vec![0];
+
+    while let Some(address) = work.pop() {
+        if address >= len || covered[address] {
+            continue;
+        }
+
+        let instruction = match decode(&machine, address) {
+            Some(i) => i,
+            None => continue,
+        };
+        let next = address + instruction.size();
+        if covered[address..next].iter().any(|c| *c) {
+            continue;
+        }
+        for c in &mut covered[address..next] {
+            *c = true;
+        }
+        instructions.insert(address, instruction);
+
+        let (taken_when, cond, dest) = match instruction {
+            Instruction::Terminate => continue,
+            Instruction::JumpIfTrue { cond, dest } => (true, cond, dest),
+            Instruction::JumpIfFalse { cond, dest } => (false, cond, dest),
+            _ => {
+                work.push(next);
+                continue;
+            }
+        };
+
+        let always = immediate(cond).map(|c| (c != 0) == taken_when);
+        if always != Some(true) {
+            work.push(next);
+        }
+        if always != Some(false) {
+            match immediate(dest) {
+                Some(target) if target >= 0 => {
+                    targets.insert(target as usize);
+                    work.push(target as usize);
+                }
+                Some(_) => {}
+                None => indirect_jumps.push(Address(address)),
+            }
+        }
+    }
+
+    let mut entries = Vec::new();
+    let mut address = 0;
+    while address < len {
+        match instructions.get(&address) {
+            Some(instruction) => {
+                entries.push(Entry::Code {
+                    address: Address(address),
+                    instruction: *instruction,
+                });
+                address += instruction.size();
+            }
+            None => {
+                entries.push(Entry::Data {
+                    address: Address(address),
+                    value: program[address],
+                });
+                address += 1;
+            }
+        }
+    }
+
+    // Only label destinations that begin an entry; a jump into the middle
+    // of an instruction keeps its numeric form.
+    let labels = entries
+        .iter()
+        .map(|e| e.address().0)
+        .filter(|a| targets.contains(a))
+        .map(|a| (a, format!("L{}", a)))
+        .collect();
+
+    indirect_jumps.sort_by_key(|a| a.0);
+
+    Disassembly {
+        entries,
+        labels,
+        indirect_jumps,
+        program: program.to_vec(),
+    }
+}
+
+impl Disassembly {
+    fn format_instruction(&self, instruction: &Instruction) -> String {
+        let args = instruction.args();
+        let mut text = format!("{:<4}", instruction.code().mnemonic());
+        for (i, arg) in args.iter().enumerate() {
+            if i > 0 {
+                text.push_str(", ");
+            }
+            let is_dest = i == 1
+                && matches!(
+                    instruction,
+                    Instruction::JumpIfTrue { .. } | Instruction::JumpIfFalse { .. }
+                );
+            match (is_dest, immediate(*arg)) {
+                (true, Some(target)) if target >= 0 && self.labels.contains_key(&(target as usize)) => {
+                    text.push_str(&format!("#{}", self.labels[&(target as usize)]));
+                }
+                _ => text.push_str(&arg.to_string()),
+            }
+        }
+        text.trim_end().to_string()
+    }
+
+    fn write_line(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        address: usize,
+        text: &str,
+        comment: &str,
+    ) -> fmt::Result {
+        let label = match self.labels.get(&address) {
+            Some(name) => format!("{}:", name),
+            None => String::new(),
+        };
+        writeln!(f, "{:<8}{:<32}; {:04}: {}", label, text, address, comment)
+    }
+}
+
+const DATA_PER_LINE: usize = 8;
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut i = 0;
+        while i < self.entries.len() {
+            match self.entries[i] {
+                Entry::Code {
+                    address,
+                    instruction,
+                } => {
+                    let raw: Vec<String> = self.program[address.0..address.0 + instruction.size()]
+                        .iter()
+                        .map(|v| v.to_string())
+                        .collect();
+                    let mut comment = raw.join(",");
+                    if self.indirect_jumps.contains(&address) {
+                        comment.push_str(" (indirect jump)");
+                    }
+                    self.write_line(f, address.0, &self.format_instruction(&instruction), &comment)?;
+                    i += 1;
+                }
+                Entry::Data { address, .. } => {
+                    // Group a run of unreached cells, breaking at labels.
+                    let mut values = Vec::new();
+                    while i < self.entries.len() && values.len() < DATA_PER_LINE {
+                        match self.entries[i] {
+                            Entry::Data { address: a, value } => {
+                                if !values.is_empty() && self.labels.contains_key(&a.0) {
+                                    break;
+                                }
+                                values.push(value.to_string());
+                                i += 1;
+                            }
+                            Entry::Code { .. } => break,
+                        }
+                    }
+                    let text = format!(".data {}", values.join(", "));
+                    self.write_line(f, address.0, &text, "never reached")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::Program;
+
+    const ECHO: &str = "
+        loop:   in   [value]
+                jf   [value], #done
+                out  [value]
+                jt   #1, #loop
+        done:   hlt
+        value:  .data 0
+                .data -3, 7
+    ";
+
+    #[test]
+    fn follows_jumps_and_marks_data() {
+        let program = assemble(ECHO).unwrap();
+        let d = disassemble(&program);
+
+        let code: Vec<usize> = d
+            .entries
+            .iter()
+            .filter_map(|e| match e {
+                Entry::Code { address, .. } => Some(address.0),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(code, vec![0, 2, 5, 7, 10]);
+
+        let data: Vec<usize> = d
+            .entries
+            .iter()
+            .filter_map(|e| match e {
+                Entry::Data { address, .. } => Some(address.0),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(data, vec![11, 12, 13]);
+
+        assert_eq!(d.labels.keys().copied().collect::<Vec<_>>(), vec![0, 10]);
+        assert!(d.indirect_jumps.is_empty());
+    }
+
+    #[test]
+    fn listing_reassembles() {
+        let program = assemble(ECHO).unwrap();
+        let listing = disassemble(&program).to_string();
+        assert!(listing.contains("jf  [11], #L10"));
+        assert!(listing.contains("never reached"));
+        assert_eq!(assemble(&listing).unwrap(), program);
+    }
+
+    #[test]
+    fn skips_code_after_unconditional_jump() {
+        // The `out` after the always-taken jump is never decoded as code.
+        let program = assemble("jf #0, #5\nout #1\nhlt").unwrap();
+        let d = disassemble(&program);
+        assert_eq!(
+            d.entries[1],
+            Entry::Data {
+                address: Address(3),
+                value: 104
+            }
+        );
+        assert_eq!(
+            d.entries[3],
+            Entry::Code {
+                address: Address(5),
+                instruction: Instruction::Terminate
+            }
+        );
+    }
+
+    #[test]
+    fn reports_indirect_jumps() {
+        let program = assemble("jt #1, [5]\nhlt\n.data 3").unwrap();
+        let d = disassemble(&program);
+        assert_eq!(d.indirect_jumps, vec![Address(0)]);
+    }
+
+    #[test]
+    fn day_inputs_reassemble() {
+        for day in &["09/SensorBoost", "13/CarePackage", "15/OxygenSystem", "17/ASCII"] {
+            let path = format!("{}/../../day/{}/input.txt", env!("CARGO_MANIFEST_DIR"), day);
+            let program = Program::from_path(path).unwrap();
+            let listing = disassemble(&program).to_string();
+            assert_eq!(assemble(&listing).unwrap(), &program[..], "day {}", day);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! Variable-length integer encoding shared by the binary file formats.
+//!
+//! Signed values are zigzag encoded and then written as LEB128, so small
+//! magnitudes of either sign take a single byte.
+
+use crate::Integer;
+
+pub(crate) fn write_unsigned(out: &mut Vec<u8>, mut n: u64) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+pub(crate) fn write_signed(out: &mut Vec<u8>, n: Integer) {
+    write_unsigned(out, ((n << 1) ^ (n >> 63)) as u64);
+}
+
+/// Reads values back from a byte slice, tracking the position for errors.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pub position: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    /// Consumes `n` raw bytes, or `None` if fewer remain.
+    pub fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        let slice = self.bytes.get(self.position..self.position + n)?;
+        self.position += n;
+        Some(slice)
+    }
+
+    /// `None` on a truncated or over-long varint.
+    pub fn unsigned(&mut self) -> Option<u64> {
+        let mut n = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = *self.bytes.get(self.position)?;
+            self.position += 1;
+            if shift == 63 && byte > 1 {
+                return None;
+            }
+            n |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Some(n);
+            }
+            shift += 7;
+            if shift > 63 {
+                return None;
+            }
+        }
+    }
+
+    pub fn signed(&mut self) -> Option<Integer> {
+        let n = self.unsigned()?;
+        Some((n >> 1) as Integer ^ -((n & 1) as Integer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let values = [0, 1, -1, 63, -64, 64, 1_000_000, Integer::MAX, Integer::MIN];
+        let mut bytes = Vec::new();
+        for v in &values {
+            write_signed(&mut bytes, *v);
+        }
+        assert_eq!(bytes[..3], [0, 2, 1]);
+
+        let mut reader = Reader::new(&bytes);
+        for v in &values {
+            assert_eq!(reader.signed(), Some(*v));
+        }
+        assert!(reader.is_empty());
+        assert_eq!(reader.signed(), None);
+    }
+
+    #[test]
+    fn rejects_overlong() {
+        assert_eq!(Reader::new(&[0xff; 11]).unsigned(), None);
+        assert_eq!(Reader::new(&[0x80]).unsigned(), None);
+    }
+}
@@ -0,0 +1,299 @@
+//! A faster engine for programs that just need to run.
+//!
+//! `FastMachine` decodes each instruction once and caches the result beside
+//! memory. A write into a cached instruction evicts it, so self-modifying
+//! programs still behave exactly as on `Machine`. I/O goes straight through a
+//! borrowed `IntcodeIo`, with no channels or allocation per value.
+//!
+//! It has no tracer, debugger hooks, counters or memory backends; use
+//! `Machine` when those are needed. Errors and outcomes are the same types
+//! and are reported at the same points as on `Machine`.
+
+use crate::decoding::{
+    self, decode, invalid_address, ADD, EQUALS, INPUT, JUMP_IF_FALSE, JUMP_IF_TRUE, LESS_THAN,
+    MAX_SIZE, MULT, OUTPUT, REL_BASE_ADJUST,
+};
+use crate::{Address, Arithmetic, Error, IntcodeIo, Integer, Memory, RunOutcome, Value};
+
+type Decoded = decoding::Decoded<Integer>;
+
+#[derive(Debug, Clone)]
+pub struct FastMachine {
+    memory: Vec<Integer>,
+    /// Decoded instruction starting at each address, filled on first use.
+    cache: Vec<Option<Decoded>>,
+    ip: usize,
+    relbase: usize,
+    terminated: bool,
+    arithmetic: Arithmetic,
+}
+
+impl FastMachine {
+    pub fn new(program: &Memory) -> Self {
+        Self {
+            memory: program.to_vec(),
+            cache: vec![None; program.len()],
+            ip: 0,
+            relbase: 0,
+            terminated: false,
+            arithmetic: Arithmetic::default(),
+        }
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn ip(&self) -> Address {
+        Address(self.ip)
+    }
+
+    pub fn relbase(&self) -> Address {
+        Address(self.relbase)
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    /// Chooses what `add` and `mul` do on overflow, as on `Machine`.
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.arithmetic = arithmetic;
+    }
+
+    /// Reads memory directly; addresses past the end read as zero.
+    pub fn peek(&self, address: Address) -> Value {
+        Value(self.memory.get(address.0).copied().unwrap_or(0))
+    }
+
+    /// Writes memory directly, growing it if needed.
+    pub fn poke(&mut self, address: Address, value: Value) {
+        self.write(address.0, value.0);
+    }
+
+    fn write(&mut self, address: usize, value: Integer) {
+        if address >= self.memory.len() {
+            self.memory.resize(address + 1, 0);
+            self.cache.resize(address + 1, None);
+        }
+        self.memory[address] = value;
+
+        // Any instruction covering `address` starts at most three cells before.
+        let first = address.saturating_sub(MAX_SIZE - 1);
+        for slot in &mut self.cache[first..=address] {
+            *slot = None;
+        }
+    }
+
+    fn fetch(&mut self) -> Result<Decoded, Error> {
+        if let Some(Some(decoded)) = self.cache.get(self.ip) {
+            return Ok(*decoded);
+        }
+        let decoded = decode(&self.memory, self.ip)?;
+        if self.ip < self.cache.len() {
+            self.cache[self.ip] = Some(decoded);
+        }
+        Ok(decoded)
+    }
+
+    #[inline(always)]
+    fn read(&self, d: &Decoded, i: usize) -> Result<Integer, Error> {
+        d.read(i, self.relbase, self.ip, &self.memory)
+    }
+
+    #[inline(always)]
+    fn store(&mut self, d: &Decoded, i: usize, value: Integer) -> Result<(), Error> {
+        let address = d.write_address(i, self.relbase, self.ip)?;
+        self.write(address, value);
+        Ok(())
+    }
+
+    fn jump(&mut self, target: Integer) -> Result<(), Error> {
+        if target < 0 {
+            return Err(invalid_address(target, self.ip));
+        }
+        self.ip = target as usize;
+        Ok(())
+    }
+
+    fn run_with<IO: IntcodeIo>(
+        &mut self,
+        io: &mut IO,
+        stop_on_output: bool,
+    ) -> Result<RunOutcome, Error> {
+        if self.terminated {
+            return Ok(RunOutcome::Halted);
+        }
+
+        loop {
+            let d = self.fetch()?;
+            let next = self.ip + d.size as usize;
+            match d.opcode {
+                ADD => {
+                    let v = self.arithmetic.add(self.read(&d, 0)?, self.read(&d, 1)?);
+                    let v = v.ok_or(Error::ArithmeticOverflow {
+                        ip: Address(self.ip),
+                    })?;
+                    self.store(&d, 2, v)?;
+                }
+                MULT => {
+                    let v = self.arithmetic.mul(self.read(&d, 0)?, self.read(&d, 1)?);
+                    let v = v.ok_or(Error::ArithmeticOverflow {
+                        ip: Address(self.ip),
+                    })?;
+                    self.store(&d, 2, v)?;
+                }
+                INPUT => match io.read() {
+                    Some(v) => self.store(&d, 0, v.0)?,
+                    None => return Ok(RunOutcome::NeedsInput),
+                },
+                OUTPUT => {
+                    let v = Value(self.read(&d, 0)?);
+                    self.ip = next;
+                    if stop_on_output {
+                        return Ok(RunOutcome::Output(v));
+                    }
+                    io.write(v);
+                    continue;
+                }
+                JUMP_IF_TRUE => {
+                    if self.read(&d, 0)? != 0 {
+                        let target = self.read(&d, 1)?;
+                        self.jump(target)?;
+                        continue;
+                    }
+                }
+                JUMP_IF_FALSE => {
+                    if self.read(&d, 0)? == 0 {
+                        let target = self.read(&d, 1)?;
+                        self.jump(target)?;
+                        continue;
+                    }
+                }
+                LESS_THAN => {
+                    let v = (self.read(&d, 0)? < self.read(&d, 1)?) as Integer;
+                    self.store(&d, 2, v)?;
+                }
+                EQUALS => {
+                    let v = (self.read(&d, 0)? == self.read(&d, 1)?) as Integer;
+                    self.store(&d, 2, v)?;
+                }
+                REL_BASE_ADJUST => {
+                    let offset = self.read(&d, 0)?;
+                    match (self.relbase as Integer).checked_add(offset) {
+                        Some(relbase) if relbase >= 0 => self.relbase = relbase as usize,
+                        _ => {
+                            return Err(Error::NegativeRelativeBase {
+                                relbase: Value((self.relbase as Integer).wrapping_add(offset)),
+                                ip: Address(self.ip),
+                            })
+                        }
+                    }
+                }
+                _ => {
+                    self.terminated = true;
+                    self.ip = next;
+                    return Ok(RunOutcome::Halted);
+                }
+            }
+            self.ip = next;
+        }
+    }
+
+    /// Runs until the program halts or needs input, reading from and writing
+    /// to `io`.
+    pub fn run<IO: IntcodeIo>(&mut self, io: &mut IO) -> Result<RunOutcome, Error> {
+        self.run_with(io, false)
+    }
+
+    /// Runs until the program produces an output, halts or needs input.
+    pub fn run_until_output<IO: IntcodeIo>(&mut self, io: &mut IO) -> Result<RunOutcome, Error> {
+        self.run_with(io, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::{BufferIo, IterIo, Machine, Program};
+
+    fn compare(program: &[Integer], input: &[Integer]) {
+        let values = || input.iter().copied().map(Value);
+        let mut slow = Machine::with_io(program, IterIo::new(values()));
+        let mut fast = FastMachine::new(program);
+        let mut io = IterIo::new(values());
+
+        let slow_result = slow.run();
+        assert_eq!(fast.run(&mut io), slow_result);
+        assert_eq!(io.output, slow.io().output);
+        assert_eq!(fast.ip(), slow.ip());
+        assert_eq!(fast.memory(), &slow.memory().to_vec()[..]);
+    }
+
+    #[test]
+    fn matches_machine_on_day_inputs() {
+        let days: [(&str, &[Integer]); 3] = [
+            ("05/intcode2", &[5]),
+            ("09/SensorBoost", &[2]),
+            ("19/TractorBeam", &[12, 20]),
+        ];
+        for (day, input) in days.iter() {
+            let path = format!("{}/../../day/{}/input.txt", env!("CARGO_MANIFEST_DIR"), day);
+            let program = Program::from_path(path).unwrap();
+            compare(&program, input);
+        }
+    }
+
+    #[test]
+    fn sees_self_modifying_writes() {
+        // Rewrites its own `out #1` into `out #2` after running it once.
+        let program = assemble(
+            "
+            again:  out  #1
+                    jt   [flag], #done
+                    add  #1, #0, [flag]
+                    add  #2, #0, [again+1]
+                    jt   #1, #again
+            done:   hlt
+            flag:   .data 0
+            ",
+        )
+        .unwrap();
+        compare(&program, &[]);
+
+        let mut fast = FastMachine::new(&program);
+        let mut io = BufferIo::new();
+        fast.run(&mut io).unwrap();
+        assert_eq!(io.output, vec![Value(1), Value(2)]);
+    }
+
+    #[test]
+    fn pauses_and_faults_like_machine() {
+        compare(&[3, 5, 4, 5, 99, 0], &[]);
+        compare(&[3, 5, 4, 5, 99, 0], &[7]);
+        compare(&[1101, 1, 2, 5, 1101, 1, 2], &[]);
+        compare(&[204, -1, 99], &[]);
+        compare(&[1105, 1, -3], &[]);
+        compare(&[11101, 1, 1, 1, 99], &[]);
+        compare(&[109, 2, 109, -3, 99], &[]);
+        compare(&[1101, 0, 0, 7, 4, -2, 99], &[]);
+    }
+
+    #[test]
+    fn run_until_output_resumes() {
+        let mut fast = FastMachine::new(&[104, 1, 104, 2, 99]);
+        let mut io = BufferIo::new();
+        assert_eq!(
+            fast.run_until_output(&mut io),
+            Ok(RunOutcome::Output(Value(1)))
+        );
+        assert_eq!(
+            fast.run_until_output(&mut io),
+            Ok(RunOutcome::Output(Value(2)))
+        );
+        assert_eq!(fast.run_until_output(&mut io), Ok(RunOutcome::Halted));
+        assert!(fast.is_terminated());
+        assert!(io.output.is_empty());
+    }
+}
@@ -0,0 +1,387 @@
+//! Input/output backends for `Machine`.
+//!
+//! A machine reads its `Input` instructions from, and writes its `Output`
+//! instructions to, an `IntcodeIo`. Returning `None` from `read` pauses the
+//! machine with `RunOutcome::NeedsInput` until more input is supplied.
+
+use crate::Value;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub trait IntcodeIo {
+    /// The next input value, or `None` if none is available yet.
+    fn read(&mut self) -> Option<Value>;
+    fn write(&mut self, value: Value);
+
+    /// Input that has been supplied but not yet read, for snapshots.
+    /// Backends that cannot inspect their input report none.
+    fn pending_input(&self) -> Vec<Value> {
+        Vec::new()
+    }
+
+    /// Replaces any unread input, for restoring snapshots.
+    fn set_pending_input(&mut self, input: Vec<Value>) {
+        let _ = input;
+    }
+}
+
+impl<T: IntcodeIo + ?Sized> IntcodeIo for &mut T {
+    fn read(&mut self) -> Option<Value> {
+        (**self).read()
+    }
+
+    fn write(&mut self, value: Value) {
+        (**self).write(value)
+    }
+
+    fn pending_input(&self) -> Vec<Value> {
+        (**self).pending_input()
+    }
+
+    fn set_pending_input(&mut self, input: Vec<Value>) {
+        (**self).set_pending_input(input)
+    }
+}
+
+impl<T: IntcodeIo + ?Sized> IntcodeIo for Box<T> {
+    fn read(&mut self) -> Option<Value> {
+        (**self).read()
+    }
+
+    fn write(&mut self, value: Value) {
+        (**self).write(value)
+    }
+
+    fn pending_input(&self) -> Vec<Value> {
+        (**self).pending_input()
+    }
+
+    fn set_pending_input(&mut self, input: Vec<Value>) {
+        (**self).set_pending_input(input)
+    }
+}
+
+/// In-memory queues, for driving a machine from the same thread.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BufferIo {
+    pub input: VecDeque<Value>,
+    pub output: VecDeque<Value>,
+}
+
+impl BufferIo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_input<I: IntoIterator<Item = Value>>(input: I) -> Self {
+        Self {
+            input: input.into_iter().collect(),
+            output: VecDeque::new(),
+        }
+    }
+}
+
+impl IntcodeIo for BufferIo {
+    fn read(&mut self) -> Option<Value> {
+        self.input.pop_front()
+    }
+
+    fn write(&mut self, value: Value) {
+        self.output.push_back(value);
+    }
+
+    fn pending_input(&self) -> Vec<Value> {
+        self.input.iter().copied().collect()
+    }
+
+    fn set_pending_input(&mut self, input: Vec<Value>) {
+        self.input = input.into();
+    }
+}
+
+/// Input and output handled by two closures.
+pub struct ClosureIo<R, W> {
+    read: R,
+    write: W,
+}
+
+impl<R, W> ClosureIo<R, W>
+where
+    R: FnMut() -> Option<Value>,
+    W: FnMut(Value),
+{
+    pub fn new(read: R, write: W) -> Self {
+        Self { read, write }
+    }
+}
+
+impl<R, W> IntcodeIo for ClosureIo<R, W>
+where
+    R: FnMut() -> Option<Value>,
+    W: FnMut(Value),
+{
+    fn read(&mut self) -> Option<Value> {
+        (self.read)()
+    }
+
+    fn write(&mut self, value: Value) {
+        (self.write)(value)
+    }
+}
+
+/// Input drawn from an iterator; output collected into a `Vec`.
+#[derive(Debug, Clone)]
+pub struct IterIo<I> {
+    input: I,
+    pub output: Vec<Value>,
+}
+
+impl<I: Iterator<Item = Value>> IterIo<I> {
+    pub fn new<T: IntoIterator<IntoIter = I>>(input: T) -> Self {
+        Self {
+            input: input.into_iter(),
+            output: Vec::new(),
+        }
+    }
+}
+
+impl<I: Iterator<Item = Value>> IntcodeIo for IterIo<I> {
+    fn read(&mut self) -> Option<Value> {
+        self.input.next()
+    }
+
+    fn write(&mut self, value: Value) {
+        self.output.push(value);
+    }
+}
+
+/// How often a blocked read checks its cancel flag.
+const CANCEL_POLL: Duration = Duration::from_millis(10);
+
+/// The original `mpsc` channel pair. Input is sent through `input()` and
+/// output received from `output()`.
+///
+/// By default a read with no input waiting returns `None` at once. With
+/// `set_block_for_input` the read waits for a value instead, which suits a
+/// machine running on its own thread.
+#[derive(Debug)]
+pub struct ChannelIo {
+    input_tx: Sender<Value>,
+    input: Receiver<Value>,
+    output: Sender<Value>,
+    output_rx: Option<Receiver<Value>>,
+    block_for_input: bool,
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+impl Default for ChannelIo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChannelIo {
+    pub fn new() -> Self {
+        let (tx0, rx0) = channel();
+        let (tx1, rx1) = channel();
+        Self {
+            input_tx: tx0,
+            input: rx0,
+            output: tx1,
+            output_rx: Some(rx1),
+            block_for_input: false,
+            cancel: None,
+        }
+    }
+
+    /// Uses existing channels: input arrives on `input`, whose sender is
+    /// also returned by `input()`, and output is sent to `output`. There is
+    /// no output receiver to hand out.
+    pub fn with_channels(input: (Sender<Value>, Receiver<Value>), output: Sender<Value>) -> Self {
+        Self {
+            input_tx: input.0,
+            input: input.1,
+            output,
+            output_rx: None,
+            block_for_input: false,
+            cancel: None,
+        }
+    }
+
+    pub fn set_block_for_input(&mut self, block: bool) {
+        self.block_for_input = block;
+    }
+
+    /// Makes a blocked read give up and return `None` once `cancel` is set.
+    pub fn set_cancel(&mut self, cancel: Option<Arc<AtomicBool>>) {
+        self.cancel = cancel;
+    }
+
+    pub fn input(&self) -> &Sender<Value> {
+        &self.input_tx
+    }
+
+    pub fn output(&mut self) -> &mut Option<Receiver<Value>> {
+        &mut self.output_rx
+    }
+}
+
+/// The clone has channels of its own, holding the input still waiting
+/// here, and its output receiver is available from `output()`. Output
+/// already sent stays with the original; the cancel flag is shared.
+impl Clone for ChannelIo {
+    fn clone(&self) -> Self {
+        let mut io = Self::new();
+        io.set_pending_input(self.pending_input());
+        io.block_for_input = self.block_for_input;
+        io.cancel = self.cancel.clone();
+        io
+    }
+}
+
+impl IntcodeIo for ChannelIo {
+    fn read(&mut self) -> Option<Value> {
+        if !self.block_for_input {
+            return self.input.try_recv().ok();
+        }
+        let cancel = match &self.cancel {
+            Some(cancel) => cancel,
+            None => return self.input.recv().ok(),
+        };
+        loop {
+            match self.input.recv_timeout(CANCEL_POLL) {
+                Ok(v) => return Some(v),
+                Err(RecvTimeoutError::Timeout) if !cancel.load(Ordering::SeqCst) => {}
+                Err(_) => return None,
+            }
+        }
+    }
+
+    fn write(&mut self, value: Value) {
+        // The caller may have dropped the receiver; the value has nowhere to go.
+        let _ = self.output.send(value);
+    }
+
+    /// Drains the input channel and sends the values back in order. Values
+    /// sent from another thread while this runs may be reordered.
+    fn pending_input(&self) -> Vec<Value> {
+        let pending: Vec<Value> = self.input.try_iter().collect();
+        for v in &pending {
+            self.input_tx.send(*v).unwrap();
+        }
+        pending
+    }
+
+    fn set_pending_input(&mut self, input: Vec<Value>) {
+        while self.input.try_recv().is_ok() {}
+        for v in input {
+            self.input_tx.send(v).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Integer, Machine, RunOutcome};
+
+    // Doubles every input: in [11]; mul [11], #2, [11]; out [11]; jt #1, #0
+    const DOUBLER: [Integer; 12] = [3, 11, 1002, 11, 2, 11, 4, 11, 1105, 1, 0, 0];
+
+    #[test]
+    fn buffer_io() {
+        let mut m = Machine::with_io(&DOUBLER, BufferIo::with_input(vec![Value(3), Value(5)]));
+        assert_eq!(m.run(), Ok(RunOutcome::NeedsInput));
+        assert_eq!(m.io().output, vec![Value(6), Value(10)]);
+
+        m.io_mut().input.push_back(Value(-1));
+        assert_eq!(m.run(), Ok(RunOutcome::NeedsInput));
+        assert_eq!(m.into_io().output.back(), Some(&Value(-2)));
+    }
+
+    #[test]
+    fn iter_io() {
+        let mut m = Machine::with_io(&DOUBLER, IterIo::new((1..4).map(Value)));
+        assert_eq!(m.run(), Ok(RunOutcome::NeedsInput));
+        assert_eq!(m.io().output, vec![Value(2), Value(4), Value(6)]);
+    }
+
+    #[test]
+    fn closure_io() {
+        let mut next = 10;
+        let mut seen = Vec::new();
+        {
+            let io = ClosureIo::new(
+                || {
+                    next -= 4;
+                    if next > 0 {
+                        Some(Value(next))
+                    } else {
+                        None
+                    }
+                },
+                |v| seen.push(v),
+            );
+            let mut m = Machine::with_io(&DOUBLER, io);
+            assert_eq!(m.run(), Ok(RunOutcome::NeedsInput));
+        }
+        assert_eq!(seen, vec![Value(12), Value(4)]);
+    }
+
+    #[test]
+    fn channel_machines_fork() {
+        let mut m = Machine::new(&DOUBLER);
+        m.input().send(Value(3)).unwrap();
+        assert_eq!(m.run(), Ok(RunOutcome::NeedsInput));
+        m.input().send(Value(5)).unwrap();
+
+        let mut fork = m.clone();
+        m.input().send(Value(7)).unwrap();
+        fork.input().send(Value(-1)).unwrap();
+        assert_eq!(m.run(), Ok(RunOutcome::NeedsInput));
+        assert_eq!(fork.run(), Ok(RunOutcome::NeedsInput));
+
+        let outputs = |m: &mut Machine<ChannelIo>| -> Vec<Value> {
+            m.output().as_ref().unwrap().try_iter().collect()
+        };
+        assert_eq!(outputs(&mut m), vec![Value(6), Value(10), Value(14)]);
+        assert_eq!(outputs(&mut fork), vec![Value(10), Value(-2)]);
+    }
+
+    #[test]
+    fn amplifier_ring_without_channels() {
+        let program = [
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+        let phases = [9, 8, 7, 6, 5];
+
+        let mut machines: Vec<Machine<BufferIo>> = phases
+            .iter()
+            .map(|p| Machine::with_io(&program, BufferIo::with_input(vec![Value(*p)])))
+            .collect();
+        machines[0].io_mut().input.push_back(Value(0));
+
+        let mut last = None;
+        while !machines[4].is_terminated() {
+            for i in 0..5 {
+                match machines[i].run() {
+                    Ok(_) => {}
+                    Err(e) => panic!("machine {} error {:?}", i, e),
+                }
+                let outputs: Vec<Value> = machines[i].io_mut().output.drain(..).collect();
+                for v in outputs {
+                    if i == 4 {
+                        last = Some(v);
+                    }
+                    machines[(i + 1) % 5].io_mut().input.push_back(v);
+                }
+            }
+        }
+
+        assert_eq!(last, Some(Value(139629729)));
+    }
+}
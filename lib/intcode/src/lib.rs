@@ -4,9 +4,205 @@ mod tests {
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    use super::*;
+
+    // in [11]; loop: add [11], #1, [11]; out [11]; jt #1, #loop
+    const COUNTER: [Integer; 12] = [3, 11, 1001, 11, 1, 11, 4, 11, 1105, 1, 2, 0];
+
+    #[test]
+    fn run_outcomes() {
+        let mut m = Machine::with_io(&[3, 5, 4, 5, 99, 0], BufferIo::new());
+        assert_eq!(m.run(), Ok(RunOutcome::NeedsInput));
+        assert_eq!(m.ip(), Address(0));
+
+        m.io_mut().input.push_back(Value(7));
+        assert_eq!(m.run(), Ok(RunOutcome::Halted));
+        assert_eq!(m.io().output, vec![Value(7)]);
+        assert_eq!(m.run(), Ok(RunOutcome::Halted));
+    }
+
+    #[test]
+    fn run_until_output_and_run_for() {
+        let mut m = Machine::with_io(&COUNTER, BufferIo::with_input(vec![Value(1)]));
+        assert_eq!(m.run_until_output(), Ok(RunOutcome::Output(Value(2))));
+        assert_eq!(m.run_until_output(), Ok(RunOutcome::Output(Value(3))));
+        assert!(m.io().output.is_empty());
+
+        assert_eq!(m.run_for(3), Ok(RunOutcome::StepLimit));
+        assert_eq!(m.ip(), Address(8));
+        assert_eq!(m.io().output, vec![Value(4)]);
+    }
+
+    #[test]
+    fn memory_limit_and_sparse_memory() {
+        // rb #10^12; add #3, #4, rb+5; out rb+5; hlt
+        let program = [109, 1_000_000_000_000, 21101, 3, 4, 5, 204, 5, 99];
+
+        let mut m = Machine::with_io(&program, BufferIo::new());
+        m.set_memory_limit(Some(1 << 20));
+        assert_eq!(
+            m.run(),
+            Err(Error::MemoryLimitExceeded {
+                address: Address(1_000_000_000_005),
+                ip: Address(2)
+            })
+        );
+        assert_eq!(
+            m.poke(Address(1 << 20), Value(1)),
+            Err(Error::MemoryLimitExceeded {
+                address: Address(1 << 20),
+                ip: Address(2)
+            })
+        );
+        assert_eq!(m.poke(Address((1 << 20) - 1), Value(1)), Ok(()));
+        assert_eq!(m.memory().len(), 1 << 20);
+
+        let mut m = Machine::with_io(&program, BufferIo::new());
+        m.set_memory_kind(MemoryKind::Sparse).unwrap();
+        assert_eq!(m.run(), Ok(RunOutcome::Halted));
+        assert_eq!(m.io().output, vec![Value(7)]);
+        assert_eq!(m.memory().len(), 1_000_000_000_006);
+        assert!(m.set_memory_kind(MemoryKind::Dense).is_err());
+        assert_eq!(m.memory().kind(), MemoryKind::Sparse);
+    }
+
+    #[test]
+    fn counters_and_budget() {
+        let mut m = Machine::with_io(&COUNTER, BufferIo::with_input(vec![Value(0)]));
+        m.set_budget(Some(10));
+        assert_eq!(m.run(), Ok(RunOutcome::BudgetExhausted));
+        assert_eq!(m.run(), Ok(RunOutcome::BudgetExhausted));
+        assert_eq!(m.instruction_count(), 10);
+        assert_eq!(m.opcode_count(InstructionCode::Input), 1);
+        assert_eq!(m.opcode_count(InstructionCode::Add), 3);
+        assert_eq!(m.io().output, vec![Value(1), Value(2), Value(3)]);
+
+        m.set_budget(Some(3));
+        assert_eq!(m.run(), Ok(RunOutcome::BudgetExhausted));
+        assert_eq!(m.io().output.back(), Some(&Value(4)));
+        assert_eq!(m.budget(), Some(0));
+
+        m.reset_counters();
+        m.set_budget(None);
+        assert_eq!(m.run_for(5), Ok(RunOutcome::StepLimit));
+        assert_eq!(m.instruction_count(), 5);
+    }
+
+    fn fault(program: &[Integer]) -> (Error, Address) {
+        let mut m = Machine::with_io(program, BufferIo::with_input(vec![Value(1)]));
+        let e = m.run().unwrap_err();
+        (e, m.ip())
+    }
+
+    #[test]
+    fn faults_are_errors() {
+        assert_eq!(
+            fault(&[1101, 1, 2, 5, 1101, 1, 2]),
+            (
+                Error::InvalidInstruction {
+                    instruction_value: Value(1101),
+                    instruction_location: Address(4)
+                },
+                Address(4)
+            )
+        );
+        assert_eq!(
+            fault(&[1101, 0, 0, 5, -7, 0]).0,
+            Error::InvalidInstruction {
+                instruction_value: Value(-7),
+                instruction_location: Address(4)
+            }
+        );
+        assert_eq!(
+            fault(&[1101, 0, 0, 7, 4, -2, 99]),
+            (
+                Error::InvalidAddress {
+                    invalid_address: Value(-2),
+                    ip: Address(4)
+                },
+                Address(4)
+            )
+        );
+        assert_eq!(
+            fault(&[204, -1, 99]).0,
+            Error::InvalidAddress {
+                invalid_address: Value(-1),
+                ip: Address(0)
+            }
+        );
+        assert_eq!(
+            fault(&[1105, 1, -3]).0,
+            Error::InvalidAddress {
+                invalid_address: Value(-3),
+                ip: Address(0)
+            }
+        );
+        assert_eq!(
+            fault(&[11101, 1, 1, 1, 99]).0,
+            Error::WriteToImmediate { ip: Address(0) }
+        );
+        assert_eq!(
+            fault(&[109, 2, 109, -3, 99]),
+            (
+                Error::NegativeRelativeBase {
+                    relbase: Value(-1),
+                    ip: Address(2)
+                },
+                Address(2)
+            )
+        );
+    }
 }
 
-use std::convert::TryInto;
+use std::convert::TryFrom;
+
+pub mod aot;
+pub mod analysis;
+pub mod arithmetic;
+pub mod ascii;
+pub mod assembler;
+#[cfg(feature = "async")]
+pub mod async_machine;
+mod decoding;
+pub mod disassembler;
+mod encoding;
+pub mod fast;
+pub mod io;
+pub mod memory;
+pub mod memory_map;
+pub mod network;
+pub mod opcodes;
+pub mod profile;
+pub mod program;
+pub mod snapshot;
+pub mod symbolic;
+pub mod threaded;
+pub mod trace;
+pub mod wide;
+
+#[cfg(feature = "async")]
+pub use async_machine::AsyncMachine;
+pub use analysis::{analyze, Analysis};
+pub use aot::CompiledMachine;
+pub use arithmetic::{Arithmetic, Word};
+pub use ascii::{AsciiMachine, AsciiOutput};
+pub use fast::FastMachine;
+pub use io::{BufferIo, ChannelIo, ClosureIo, IntcodeIo, IterIo};
+pub use memory::{MemoryBackend, MemoryKind, MemoryTooLarge};
+pub use memory_map::MemoryMap;
+pub use network::{Network, NodeId};
+pub use opcodes::OpcodeRegistry;
+pub use profile::Profile;
+pub use program::Program;
+pub use snapshot::Snapshot;
+pub use symbolic::{explore, Expr, Summary};
+pub use threaded::ThreadedRunner;
+pub use wide::WideMachine;
+
+use opcodes::CustomOpcode;
+use std::sync::Arc;
+use trace::{TraceRecord, Tracer};
 
 #[macro_use]
 extern crate num_derive;
@@ -29,7 +225,7 @@ pub enum Arg {
     Offset(Value),
 }
 
-#[derive(FromPrimitive)]
+#[derive(FromPrimitive, Debug, Copy, Clone, PartialEq)]
 pub enum ParameterMode {
     Position = 0,
     Immediate = 1,
@@ -50,7 +246,7 @@ pub enum Instruction {
     Terminate,
 }
 
-#[derive(FromPrimitive)]
+#[derive(FromPrimitive, Debug, Copy, Clone, PartialEq)]
 pub enum InstructionCode {
     Add = 1,
     Mult = 2,
@@ -64,37 +260,297 @@ pub enum InstructionCode {
     Terminate = 99,
 }
 
+pub const INSTRUCTION_CODES: [InstructionCode; 10] = [
+    InstructionCode::Add,
+    InstructionCode::Mult,
+    InstructionCode::Input,
+    InstructionCode::Output,
+    InstructionCode::JumpIfTrue,
+    InstructionCode::JumpIfFalse,
+    InstructionCode::LessThan,
+    InstructionCode::Equals,
+    InstructionCode::RelBaseAdjsust,
+    InstructionCode::Terminate,
+];
+
+impl InstructionCode {
+    /// Short assembler name for the opcode.
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            InstructionCode::Add => "add",
+            InstructionCode::Mult => "mul",
+            InstructionCode::Input => "in",
+            InstructionCode::Output => "out",
+            InstructionCode::JumpIfTrue => "jt",
+            InstructionCode::JumpIfFalse => "jf",
+            InstructionCode::LessThan => "lt",
+            InstructionCode::Equals => "eq",
+            InstructionCode::RelBaseAdjsust => "rb",
+            InstructionCode::Terminate => "hlt",
+        }
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+        INSTRUCTION_CODES
+            .iter()
+            .copied()
+            .find(|code| code.mnemonic() == mnemonic)
+    }
+
+    /// Number of parameters that follow the opcode in memory.
+    pub fn arity(self) -> usize {
+        match self {
+            InstructionCode::Add
+            | InstructionCode::Mult
+            | InstructionCode::LessThan
+            | InstructionCode::Equals => 3,
+            InstructionCode::JumpIfTrue | InstructionCode::JumpIfFalse => 2,
+            InstructionCode::Input | InstructionCode::Output | InstructionCode::RelBaseAdjsust => 1,
+            InstructionCode::Terminate => 0,
+        }
+    }
+
+    /// Position of the opcode in `INSTRUCTION_CODES`.
+    pub fn index(self) -> usize {
+        match self {
+            InstructionCode::Add => 0,
+            InstructionCode::Mult => 1,
+            InstructionCode::Input => 2,
+            InstructionCode::Output => 3,
+            InstructionCode::JumpIfTrue => 4,
+            InstructionCode::JumpIfFalse => 5,
+            InstructionCode::LessThan => 6,
+            InstructionCode::Equals => 7,
+            InstructionCode::RelBaseAdjsust => 8,
+            InstructionCode::Terminate => 9,
+        }
+    }
+
+    /// Whether the parameter at `index` is written to rather than read.
+    pub fn writes_parameter(self, index: usize) -> bool {
+        match self {
+            InstructionCode::Add
+            | InstructionCode::Mult
+            | InstructionCode::LessThan
+            | InstructionCode::Equals => index == 2,
+            InstructionCode::Input => index == 0,
+            _ => false,
+        }
+    }
+}
+
+impl Arg {
+    pub fn mode(&self) -> ParameterMode {
+        match self {
+            Arg::Address(_) => ParameterMode::Position,
+            Arg::Value(_) => ParameterMode::Immediate,
+            Arg::Offset(_) => ParameterMode::Relative,
+        }
+    }
+
+    /// The raw integer stored in memory for this parameter.
+    pub fn raw(&self) -> Integer {
+        match self {
+            Arg::Address(address) => address.0 as Integer,
+            Arg::Value(value) | Arg::Offset(value) => value.0,
+        }
+    }
+}
+
+impl std::fmt::Display for Arg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Arg::Address(address) => write!(f, "[{}]", address.0),
+            Arg::Value(value) => write!(f, "#{}", value.0),
+            Arg::Offset(offset) if offset.0 < 0 => write!(f, "rb{}", offset.0),
+            Arg::Offset(offset) => write!(f, "rb+{}", offset.0),
+        }
+    }
+}
+
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code().mnemonic())?;
+        for (i, arg) in self.args().iter().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            write!(f, "{}{}", separator, arg)?;
+        }
+        Ok(())
+    }
+}
+
+impl Instruction {
+    pub fn code(&self) -> InstructionCode {
+        match self {
+            Instruction::Add { .. } => InstructionCode::Add,
+            Instruction::Mult { .. } => InstructionCode::Mult,
+            Instruction::Input { .. } => InstructionCode::Input,
+            Instruction::Output { .. } => InstructionCode::Output,
+            Instruction::JumpIfTrue { .. } => InstructionCode::JumpIfTrue,
+            Instruction::JumpIfFalse { .. } => InstructionCode::JumpIfFalse,
+            Instruction::LessThan { .. } => InstructionCode::LessThan,
+            Instruction::Equals { .. } => InstructionCode::Equals,
+            Instruction::RelBaseAdjust { .. } => InstructionCode::RelBaseAdjsust,
+            Instruction::Terminate => InstructionCode::Terminate,
+        }
+    }
+
+    /// Parameters in the order they appear in memory.
+    pub fn args(&self) -> Vec<Arg> {
+        match *self {
+            Instruction::Add { arg1, arg2, out } | Instruction::Mult { arg1, arg2, out } => {
+                vec![arg1, arg2, out]
+            }
+            Instruction::LessThan { c1, c2, out } | Instruction::Equals { c1, c2, out } => {
+                vec![c1, c2, out]
+            }
+            Instruction::Input { out } => vec![out],
+            Instruction::Output { arg1 } => vec![arg1],
+            Instruction::JumpIfTrue { cond, dest } | Instruction::JumpIfFalse { cond, dest } => {
+                vec![cond, dest]
+            }
+            Instruction::RelBaseAdjust { amount } => vec![amount],
+            Instruction::Terminate => vec![],
+        }
+    }
+
+    /// Builds an instruction from an opcode and its parameters in memory order.
+    pub fn from_args(code: InstructionCode, args: &[Arg]) -> Option<Self> {
+        if args.len() != code.arity() {
+            return None;
+        }
+        Some(match code {
+            InstructionCode::Add => Instruction::Add { arg1: args[0], arg2: args[1], out: args[2] },
+            InstructionCode::Mult => Instruction::Mult { arg1: args[0], arg2: args[1], out: args[2] },
+            InstructionCode::Input => Instruction::Input { out: args[0] },
+            InstructionCode::Output => Instruction::Output { arg1: args[0] },
+            InstructionCode::JumpIfTrue => Instruction::JumpIfTrue { cond: args[0], dest: args[1] },
+            InstructionCode::JumpIfFalse => Instruction::JumpIfFalse { cond: args[0], dest: args[1] },
+            InstructionCode::LessThan => Instruction::LessThan { c1: args[0], c2: args[1], out: args[2] },
+            InstructionCode::Equals => Instruction::Equals { c1: args[0], c2: args[1], out: args[2] },
+            InstructionCode::RelBaseAdjsust => Instruction::RelBaseAdjust { amount: args[0] },
+            InstructionCode::Terminate => Instruction::Terminate,
+        })
+    }
+
+    /// Memory representation of the instruction: the mode-prefixed opcode
+    /// followed by its parameters.
+    pub fn encode(&self) -> Vec<Integer> {
+        let args = self.args();
+        let mut opcode = self.code() as Integer;
+        let mut scale = 100;
+        for arg in &args {
+            opcode += arg.mode() as Integer * scale;
+            scale *= 10;
+        }
+
+        let mut words = vec![opcode];
+        words.extend(args.iter().map(|a| a.raw()));
+        words
+    }
+
+    /// Number of memory cells the instruction occupies.
+    pub fn size(&self) -> usize {
+        1 + self.code().arity()
+    }
+}
+
+/// A fault in the running program. Every variant records the address of the
+/// faulting instruction; after a fault the machine's ip is left pointing at it.
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Error {
+    /// The cell at `instruction_location` is not a valid opcode, or the
+    /// instruction's parameters run past the end of memory.
     InvalidInstruction {
         instruction_value: Value,
         instruction_location: Address,
     },
+    /// An operand or jump target resolved to a negative address.
     InvalidAddress {
-        invalid_address: Address,
-        address_location: Address,
+        invalid_address: Value,
+        ip: Address,
     },
-    InputNotAvailable,
-    Terminated,
+    /// The instruction's output parameter is in immediate mode.
+    WriteToImmediate {
+        ip: Address,
+    },
+    /// A relative base adjustment would move the base below zero.
+    NegativeRelativeBase {
+        relbase: Value,
+        ip: Address,
+    },
+    /// A write to `address` is beyond the machine's memory limit.
+    MemoryLimitExceeded {
+        address: Address,
+        ip: Address,
+    },
+    /// An `add` or `mul` overflowed `Integer` under `Arithmetic::Checked`.
+    ArithmeticOverflow {
+        ip: Address,
+    },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidInstruction {
+                instruction_value,
+                instruction_location,
+            } => write!(
+                f,
+                "invalid instruction {} at {}",
+                instruction_value.0, instruction_location.0
+            ),
+            Error::InvalidAddress { invalid_address, ip } => {
+                write!(f, "invalid address {} at {}", invalid_address.0, ip.0)
+            }
+            Error::WriteToImmediate { ip } => write!(f, "write to immediate at {}", ip.0),
+            Error::NegativeRelativeBase { relbase, ip } => {
+                write!(f, "negative relative base {} at {}", relbase.0, ip.0)
+            }
+            Error::MemoryLimitExceeded { address, ip } => {
+                write!(f, "write to {} beyond the memory limit at {}", address.0, ip.0)
+            }
+            Error::ArithmeticOverflow { ip } => write!(f, "arithmetic overflow at {}", ip.0),
+        }
+    }
 }
 
-#[derive(Debug)]
+impl std::error::Error for Error {}
+
+/// Why a call to `Machine::run` (or one of its variants) returned.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RunOutcome {
+    /// The program executed a `Terminate` instruction.
+    Halted,
+    /// The program is paused on an `Input` instruction; supply input and run
+    /// again to resume it.
+    NeedsInput,
+    /// The program produced a value. Outputs reported this way are handed to
+    /// the caller instead of the machine's I/O.
+    Output(Value),
+    /// The requested number of instructions was executed without stopping.
+    StepLimit,
+    /// The machine's step budget ran out before the next instruction. Raise
+    /// it with `set_budget` and run again to resume.
+    BudgetExhausted,
+}
+
+#[derive(Debug, Copy, Clone)]
 enum MachineState {
     DecodeInstruction,
-    ExecuteInstruction(Instruction),
     Terminated,
 }
 
-    fn usize_add(a : usize,  b: Integer) -> usize {
-        let signed : isize = a.try_into().unwrap();
-        let offset : isize = b.try_into().unwrap();
-        (signed + offset).try_into().unwrap()
-    }
+/// `a + b` as an address, or `None` if the sum is negative or overflows.
+fn usize_add(a: usize, b: Integer) -> Option<usize> {
+    let sum = Integer::try_from(a).ok()?.checked_add(b)?;
+    usize::try_from(sum).ok()
+}
 
 
 
 fn get_digits(n: Integer) -> [Integer; 6] {
-    let n: Integer = n.try_into().unwrap();
     let a = n % 100;
     let n = n / 100;
     let b = n % 10;
@@ -109,75 +565,326 @@ fn get_digits(n: Integer) -> [Integer; 6] {
     [f, e, d, c, b, a]
 }
 
-use std::sync::mpsc::{Sender, Receiver, channel};
+use std::sync::mpsc::{Receiver, Sender};
 
-#[derive(Debug)]
-pub struct Machine {
-    memory: Vec<Integer>,
+pub struct Machine<IO = ChannelIo> {
+    memory: MemoryBackend,
+    memory_limit: Option<usize>,
     ip: Address,
     relbase: Address,
     state: MachineState,
-    input_tx: Sender<Value>,
-    input: Receiver<Value>,
-    output: Sender<Value>,
-    output_rx: Option<Receiver<Value>>,
-    block_for_input: bool,
+    io: IO,
+    tracer: Option<Box<dyn Tracer>>,
+    trace_record: Option<TraceRecord>,
+    /// Address of the instruction being executed, for error reports.
+    instruction_ip: Address,
+    instruction_count: u64,
+    opcode_counts: [u64; INSTRUCTION_CODES.len()],
+    budget: Option<u64>,
+    memory_map: Option<MemoryMap>,
+    opcodes: Option<Arc<OpcodeRegistry<IO>>>,
+    profile: Profile,
+    arithmetic: Arithmetic,
 }
 
-impl Machine {
+impl<IO> std::fmt::Debug for Machine<IO> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Machine")
+            .field("memory", &self.memory)
+            .field("ip", &self.ip)
+            .field("relbase", &self.relbase)
+            .field("state", &self.state)
+            .field("tracing", &self.tracer.is_some())
+            .finish()
+    }
+}
 
+/// Clones everything except the tracer, which the clone starts without. A
+/// machine on `ChannelIo` gets channels of its own, holding a copy of the
+/// input still waiting.
+impl<IO: Clone> Clone for Machine<IO> {
+    fn clone(&self) -> Self {
+        Self {
+            memory: self.memory.clone(),
+            memory_limit: self.memory_limit,
+            ip: self.ip,
+            relbase: self.relbase,
+            state: self.state,
+            io: self.io.clone(),
+            tracer: None,
+            trace_record: None,
+            instruction_ip: self.instruction_ip,
+            instruction_count: self.instruction_count,
+            opcode_counts: self.opcode_counts,
+            budget: self.budget,
+            memory_map: self.memory_map.clone(),
+            opcodes: self.opcodes.clone(),
+            profile: self.profile,
+            arithmetic: self.arithmetic,
+        }
+    }
+}
+
+impl Machine<ChannelIo> {
     pub fn new(program: &Memory) -> Self {
-        let (tx0, rx0) = channel();
-        let (tx1, rx1) = channel();
+        Self::with_io(program, ChannelIo::new())
+    }
+
+    pub fn input(&self) -> &Sender<Value> {
+        self.io.input()
+    }
+
+    pub fn output(&mut self) -> &mut Option<Receiver<Value>> {
+        self.io.output()
+    }
+}
+
+impl<IO: IntcodeIo> Machine<IO> {
+    pub fn with_io(program: &Memory, io: IO) -> Self {
         Self {
-            memory: program.to_vec(),
+            memory: MemoryBackend::Dense(program.to_vec()),
+            memory_limit: None,
             ip: Address(0),
             relbase: Address(0),
             state: MachineState::DecodeInstruction,
-            input: rx0,
-            output: tx1,
-            block_for_input: false,
-            input_tx: tx0,
-            output_rx: Some(rx1),
+            io,
+            tracer: None,
+            trace_record: None,
+            instruction_ip: Address(0),
+            instruction_count: 0,
+            opcode_counts: [0; INSTRUCTION_CODES.len()],
+            budget: None,
+            memory_map: None,
+            opcodes: None,
+            profile: Profile::default(),
+            arithmetic: Arithmetic::default(),
         }
     }
 
-    pub fn input(&self) -> &Sender<Value> {
-        &self.input_tx
+    pub fn io(&self) -> &IO {
+        &self.io
     }
 
-    pub fn output(&mut self) -> &mut Option<Receiver<Value>> {
-        &mut self.output_rx
+    pub fn io_mut(&mut self) -> &mut IO {
+        &mut self.io
+    }
+
+    pub fn into_io(self) -> IO {
+        self.io
+    }
+
+    /// Installs a tracer that is handed a `TraceRecord` for every executed
+    /// instruction, replacing any previous tracer.
+    pub fn set_tracer<T: Tracer + 'static>(&mut self, tracer: T) {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer>> {
+        self.tracer.take()
     }
 
-    pub fn memory(&self) -> &Memory {
+    pub fn memory(&self) -> &MemoryBackend {
         &self.memory
     }
 
-    fn pop_address(&mut self) -> Result<Address, Error> {
-        let a = self.read_address(self.ip)?;
-        self.ip.0 += 1;
+    /// Moves memory to a different backend, keeping its contents. Fails,
+    /// leaving memory alone, if it is too large to hold densely.
+    pub fn set_memory_kind(&mut self, kind: MemoryKind) -> Result<(), MemoryTooLarge> {
+        if self.memory.kind() != kind {
+            self.memory = self.memory.to_kind(kind)?;
+        }
+        Ok(())
+    }
+
+    /// Makes writes at or beyond `limit`, by the program or by `poke`, fail
+    /// with `Error::MemoryLimitExceeded`. `None`, the default, allows any
+    /// address.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory_limit = limit;
+    }
+
+    /// Address of the next instruction to execute.
+    pub fn ip(&self) -> Address {
+        self.ip
+    }
+
+    pub fn relbase(&self) -> Address {
+        self.relbase
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        matches!(self.state, MachineState::Terminated)
+    }
+
+    /// Number of instructions executed so far. An `Input` that waits for
+    /// data is counted once, when it completes.
+    pub fn instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+
+    /// Number of times each opcode has executed.
+    pub fn opcode_count(&self, code: InstructionCode) -> u64 {
+        self.opcode_counts[code.index()]
+    }
+
+    pub fn reset_counters(&mut self) {
+        self.instruction_count = 0;
+        self.opcode_counts = [0; INSTRUCTION_CODES.len()];
+    }
+
+    /// Limits the number of further instructions the machine will execute;
+    /// once it runs out, every run returns `BudgetExhausted`. `None`, the
+    /// default, removes the limit.
+    pub fn set_budget(&mut self, steps: Option<u64>) {
+        self.budget = steps;
+    }
+
+    /// Instructions left in the budget, if one is set.
+    pub fn budget(&self) -> Option<u64> {
+        self.budget
+    }
+
+    /// Starts recording a `MemoryMap` of reads, writes and executed code,
+    /// if one is not already being recorded.
+    pub fn enable_memory_map(&mut self) {
+        if self.memory_map.is_none() {
+            self.memory_map = Some(MemoryMap::new());
+        }
+    }
+
+    pub fn memory_map(&self) -> Option<&MemoryMap> {
+        self.memory_map.as_ref()
+    }
+
+    /// Stops recording and returns the map.
+    pub fn take_memory_map(&mut self) -> Option<MemoryMap> {
+        self.memory_map.take()
+    }
+
+    /// Installs custom opcodes on top of the built-in instruction set, or
+    /// removes them with `None`.
+    pub fn set_opcodes(&mut self, opcodes: Option<Arc<OpcodeRegistry<IO>>>) {
+        self.opcodes = opcodes;
+    }
+
+    /// Restricts the built-in instructions to those of `profile`.
+    pub fn set_profile(&mut self, profile: Profile) {
+        self.profile = profile;
+    }
+
+    pub fn profile(&self) -> Profile {
+        self.profile
+    }
+
+    /// Chooses what `add` and `mul` do on overflow. The default,
+    /// `Arithmetic::Checked`, fails with `Error::ArithmeticOverflow`.
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.arithmetic = arithmetic;
+    }
+
+    pub fn arithmetic(&self) -> Arithmetic {
+        self.arithmetic
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            memory: self.memory.clone(),
+            ip: self.ip,
+            relbase: self.relbase,
+            terminated: self.is_terminated(),
+            input: self.io.pending_input(),
+        }
+    }
+
+    /// Returns the machine to a snapshotted state, replacing its unread
+    /// input. Output already produced, the tracer and the memory backend
+    /// and limit are left alone; the snapshot's memory is moved to this
+    /// machine's backend, which fails, changing nothing, if sparse memory is
+    /// too large to hold densely.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), MemoryTooLarge> {
+        self.memory = snapshot.memory.to_kind(self.memory.kind())?;
+        self.ip = snapshot.ip;
+        self.relbase = snapshot.relbase;
+        self.state = if snapshot.terminated {
+            MachineState::Terminated
+        } else {
+            MachineState::DecodeInstruction
+        };
+        self.io.set_pending_input(snapshot.input.clone());
+        Ok(())
+    }
+
+    /// Decodes the instruction at `address` without executing it.
+    pub fn instruction_at(&self, address: Address) -> Result<Instruction, Error> {
+        let mut ip = address;
+        self.pop_instruction_at(&mut ip)
+    }
+
+    /// Reads memory directly; addresses past the end read as zero.
+    pub fn peek(&self, address: Address) -> Value {
+        Value(self.memory.get(address.0))
+    }
+
+    /// Writes memory directly, growing it if needed. A write at or beyond
+    /// the memory limit fails with `Error::MemoryLimitExceeded` at the
+    /// current ip, and leaves memory unchanged.
+    pub fn poke(&mut self, address: Address, value: Value) -> Result<(), Error> {
+        self.check_limit(address.0, self.ip)?;
+        self.memory.set(address.0, value.0);
+        Ok(())
+    }
+
+    pub fn set_ip(&mut self, ip: Address) {
+        self.ip = ip;
+    }
+
+    pub fn set_relbase(&mut self, relbase: Address) {
+        self.relbase = relbase;
+    }
+
+    /// Resolves a parameter of the executing instruction, as a read.
+    pub fn read_arg(&self, arg: Arg) -> Result<Value, Error> {
+        self.read_value(arg)
+    }
+
+    /// Writes through a parameter of the executing instruction, subject to
+    /// the memory limit.
+    pub fn write_arg(&mut self, arg: Arg, value: Value) -> Result<(), Error> {
+        self.set_value(arg, value)
+    }
+
+    fn pop_address(&self, ip: &mut Address, start: Address) -> Result<Address, Error> {
+        let a = self.read_address(*ip, start)?;
+        ip.0 += 1;
         Ok(a)
     }
 
-    fn pop_value(&mut self) -> Result<Value, Error> {
-        let v = Value(self.memory[self.ip.0]);
-        self.ip.0 += 1;
-        Ok(v)
+    fn pop_value(&self, ip: &mut Address, start: Address) -> Result<Value, Error> {
+        match self.memory.try_get(ip.0) {
+            Some(v) => {
+                ip.0 += 1;
+                Ok(Value(v))
+            }
+            None => Err(Error::InvalidInstruction {
+                instruction_value: self.peek(start),
+                instruction_location: start,
+            }),
+        }
     }
 
-    fn pop_argument(&mut self, mode: ParameterMode) -> Result<Arg, Error> {
+    fn pop_argument(&self, ip: &mut Address, start: Address, mode: ParameterMode) -> Result<Arg, Error> {
         match mode {
-            ParameterMode::Position => Ok(Arg::Address(self.pop_address()?)),
-            ParameterMode::Immediate => Ok(Arg::Value(self.pop_value()?)),
-            ParameterMode::Relative => Ok(Arg::Offset(self.pop_value()?))
+            ParameterMode::Position => Ok(Arg::Address(self.pop_address(ip, start)?)),
+            ParameterMode::Immediate => Ok(Arg::Value(self.pop_value(ip, start)?)),
+            ParameterMode::Relative => Ok(Arg::Offset(self.pop_value(ip, start)?))
         }
     }
 
     fn pop_instruction_code(
-        &mut self,
+        &self,
+        ip: &mut Address,
     ) -> Result<(ParameterMode, ParameterMode, ParameterMode, InstructionCode), Error> {
-        let numeric_value = self.memory[self.ip.0];
+        let numeric_value = self.peek(*ip).0;
 
         let digits = get_digits(numeric_value);
 
@@ -185,161 +892,192 @@ impl Machine {
 
         let e = Error::InvalidInstruction {
             instruction_value: Value(numeric_value),
-            instruction_location: self.ip,
+            instruction_location: *ip,
         };
 
-        let x = FromPrimitive::from_u64(numeric_opcode.try_into().unwrap()).ok_or(e)?;
+        let x = FromPrimitive::from_i64(numeric_opcode).ok_or(e)?;
 
-        self.ip.0 += 1;
+        ip.0 += 1;
 
-        return Ok((
+        Ok((
             FromPrimitive::from_i64(digits[2]).ok_or(e)?,
             FromPrimitive::from_i64(digits[3]).ok_or(e)?,
             FromPrimitive::from_i64(digits[4]).ok_or(e)?,
             x,
-        ));
+        ))
     }
 
-    fn pop_instruction(&mut self) -> Result<Instruction, Error> {
-        let (a3, a2, a1, i) = self.pop_instruction_code()?;
-        match i {
+    /// Decodes the instruction at `ip`, advancing `ip` past it. Instructions
+    /// outside the machine's profile are invalid.
+    fn pop_instruction_at(&self, ip: &mut Address) -> Result<Instruction, Error> {
+        let start = *ip;
+        let (a3, a2, a1, i) = self.pop_instruction_code(ip)?;
+        let instruction = match i {
             InstructionCode::Add => Ok(Instruction::Add {
-                arg1: self.pop_argument(a1)?,
-                arg2: self.pop_argument(a2)?,
-                out: self.pop_argument(a3)?,
+                arg1: self.pop_argument(ip, start, a1)?,
+                arg2: self.pop_argument(ip, start, a2)?,
+                out: self.pop_argument(ip, start, a3)?,
             }),
             InstructionCode::Mult => Ok(Instruction::Mult {
-                arg1: self.pop_argument(a1)?,
-                arg2: self.pop_argument(a2)?,
-                out: self.pop_argument(a3)?,
+                arg1: self.pop_argument(ip, start, a1)?,
+                arg2: self.pop_argument(ip, start, a2)?,
+                out: self.pop_argument(ip, start, a3)?,
             }),
             InstructionCode::Input => Ok(Instruction::Input {
-                out: self.pop_argument(a1)?,
+                out: self.pop_argument(ip, start, a1)?,
             }),
             InstructionCode::Output => Ok(Instruction::Output {
-                arg1: self.pop_argument(a1)?,
+                arg1: self.pop_argument(ip, start, a1)?,
             }),
             InstructionCode::JumpIfTrue => Ok(Instruction::JumpIfTrue {
-                cond: self.pop_argument(a1)?,
-                dest: self.pop_argument(a2)?,
+                cond: self.pop_argument(ip, start, a1)?,
+                dest: self.pop_argument(ip, start, a2)?,
             }),
             InstructionCode::JumpIfFalse => Ok(Instruction::JumpIfFalse {
-                cond: self.pop_argument(a1)?,
-                dest: self.pop_argument(a2)?,
+                cond: self.pop_argument(ip, start, a1)?,
+                dest: self.pop_argument(ip, start, a2)?,
             }),
             InstructionCode::LessThan => Ok(Instruction::LessThan {
-                c1: self.pop_argument(a1)?,
-                c2: self.pop_argument(a2)?,
-                out: self.pop_argument(a3)?,
+                c1: self.pop_argument(ip, start, a1)?,
+                c2: self.pop_argument(ip, start, a2)?,
+                out: self.pop_argument(ip, start, a3)?,
             }),
             InstructionCode::Equals => Ok(Instruction::Equals {
-                c1: self.pop_argument(a1)?,
-                c2: self.pop_argument(a2)?,
-                out: self.pop_argument(a3)?,
+                c1: self.pop_argument(ip, start, a1)?,
+                c2: self.pop_argument(ip, start, a2)?,
+                out: self.pop_argument(ip, start, a3)?,
             }),
             InstructionCode::RelBaseAdjsust => Ok(Instruction::RelBaseAdjust {
-                amount: self.pop_argument(a1)?,
+                amount: self.pop_argument(ip, start, a1)?,
             }),
             InstructionCode::Terminate => Ok(Instruction::Terminate),
+        }?;
+        if !self.profile.allows(&instruction) {
+            return Err(Error::InvalidInstruction {
+                instruction_value: self.peek(start),
+                instruction_location: start,
+            });
         }
+        Ok(instruction)
+    }
+
+    fn pop_instruction(&mut self) -> Result<Instruction, Error> {
+        let mut ip = self.ip;
+        let instruction = self.pop_instruction_at(&mut ip)?;
+        self.ip = ip;
+        Ok(instruction)
+    }
+
+    fn relative_address(&self, offset: Value) -> Result<usize, Error> {
+        usize_add(self.relbase.0, offset.0).ok_or(Error::InvalidAddress {
+            invalid_address: Value((self.relbase.0 as Integer).wrapping_add(offset.0)),
+            ip: self.instruction_ip,
+        })
     }
 
     fn read_value(&self, a: Arg) -> Result<Value, Error> {
-        let read_address;
-        match a {
+        let read_address = match a {
             Arg::Value(value) => {
                 return Ok(value);
             },
-            Arg::Address(address) => { read_address = address.0; },
-            
-            Arg::Offset(offset) => { read_address = usize_add(self.relbase.0, offset.0); }//Ok(Value(self.memory[address.0 + self.relbase.0]),)
-        }
+            Arg::Address(address) => address.0,
+            Arg::Offset(offset) => self.relative_address(offset)?,
+        };
 
-        if read_address >= self.memory.len() {
-            return Ok(Value(0));
-        }
-        else {
-            return Ok(Value(self.memory[read_address]));
+        Ok(self.peek(Address(read_address)))
+    }
+
+    /// Reads a position-mode parameter at `address` for the instruction at `start`.
+    fn read_address(&self, address: Address, start: Address) -> Result<Address, Error> {
+        let mut cell = address;
+        let m = self.pop_value(&mut cell, start)?;
+        match usize::try_from(m.0) {
+            Ok(a) => Ok(Address(a)),
+            Err(_) => Err(Error::InvalidAddress {
+                invalid_address: m,
+                ip: start,
+            }),
         }
     }
 
-    fn read_address(&self, address: Address) -> Result<Address, Error> {
-        let m = self.memory[address.0];
-        let a = Address(m.try_into().unwrap());
-        Ok(a)
+    fn check_limit(&self, address: usize, ip: Address) -> Result<(), Error> {
+        match self.memory_limit {
+            Some(limit) if address >= limit => Err(Error::MemoryLimitExceeded {
+                address: Address(address),
+                ip,
+            }),
+            _ => Ok(()),
+        }
     }
 
     fn set_value(&mut self, arg: Arg, value: Value) -> Result<(), Error> {
-        let write_address;
-        match arg {
-            Arg::Address(address) => {
-                write_address = address.0;
-            },
-            Arg::Offset(offset) => {
-                write_address = usize_add(self.relbase.0, offset.0);
-            },
+        let write_address = match arg {
+            Arg::Address(address) => address.0,
+            Arg::Offset(offset) => self.relative_address(offset)?,
             Arg::Value(_) => {
-                panic!("Invalid set value");
+                return Err(Error::WriteToImmediate {
+                    ip: self.instruction_ip,
+                });
             }
-        }
+        };
 
-        if self.memory.len() <= write_address {
-            self.memory.resize(write_address + 1, 0);
-        }
+        self.check_limit(write_address, self.instruction_ip)?;
+        self.memory.set(write_address, value.0);
 
-        self.memory[write_address] = value.0;
+        if let Some(record) = &mut self.trace_record {
+            record.writes.push((Address(write_address), value));
+        }
 
         Ok(())
     }
 
+    /// Executes a decoded instruction. Returns the outcome if the instruction
+    /// stops ordinary execution: an output, a missing input or termination.
     pub fn execute_instruction(
         &mut self,
         instruction: Instruction,
-    ) -> Result<(), Error> {
+    ) -> Result<Option<RunOutcome>, Error> {
         match instruction {
             Instruction::Add { arg1, arg2, out } => {
-                self.set_value(
-                    out,
-                    Value(self.read_value(arg1)?.0 + self.read_value(arg2)?.0),
-                )?;
+                let sum = self
+                    .arithmetic
+                    .add(self.read_value(arg1)?.0, self.read_value(arg2)?.0)
+                    .ok_or(Error::ArithmeticOverflow { ip: self.instruction_ip })?;
+                self.set_value(out, Value(sum))?;
             }
             Instruction::Mult { arg1, arg2, out } => {
-                self.set_value(
-                    out,
-                    Value(self.read_value(arg1)?.0 * self.read_value(arg2)?.0),
-                )?;
+                let product = self
+                    .arithmetic
+                    .mul(self.read_value(arg1)?.0, self.read_value(arg2)?.0)
+                    .ok_or(Error::ArithmeticOverflow { ip: self.instruction_ip })?;
+                self.set_value(out, Value(product))?;
             }
             Instruction::Input { out } => {
+                let input = match self.io.read() {
+                    Some(input) => input,
+                    None => return Ok(Some(RunOutcome::NeedsInput)),
+                };
 
-                let input;
-                if self.block_for_input {
-                    input = self.input.recv().unwrap();
-                } else {
-                    let result = self.input.try_recv();
-
-                    if let Err(recv_err) = result {
-                        if recv_err == std::sync::mpsc::TryRecvError::Empty {
-                            return Err(Error::InputNotAvailable);
-                        }
-                    }
-
-                    input = result.unwrap();
+                if let Some(record) = &mut self.trace_record {
+                    record.input = Some(input);
                 }
-                
                 self.set_value(out, input)?;
             }
             Instruction::Output { arg1 } => {
                 let v = self.read_value(arg1)?;
-                self.output.send(v).unwrap();
+                if let Some(record) = &mut self.trace_record {
+                    record.output = Some(v);
+                }
+                return Ok(Some(RunOutcome::Output(v)));
             }
             Instruction::JumpIfTrue { cond, dest } => {
                 if self.read_value(cond)?.0 != 0 {
-                    self.ip.0 = self.read_value(dest)?.0.try_into().unwrap();
+                    self.jump(dest)?;
                 }
             }
             Instruction::JumpIfFalse { cond, dest } => {
                 if self.read_value(cond)?.0 == 0 {
-                    self.ip.0 = self.read_value(dest)?.0.try_into().unwrap();
+                    self.jump(dest)?;
                 }
             }
             Instruction::LessThan { c1, c2, out } => {
@@ -357,46 +1095,228 @@ impl Machine {
                 }
             },
             Instruction::RelBaseAdjust { amount } => {
-                let offset = self.read_value(amount)?.0;
-                if offset < 0 {
-                    self.relbase.0 -= (-offset) as usize;
-                }
-                else {
-                    self.relbase.0 += offset as usize;
-                }
+                let offset = self.read_value(amount)?;
+                self.relbase.0 = usize_add(self.relbase.0, offset.0).ok_or(Error::NegativeRelativeBase {
+                    relbase: Value((self.relbase.0 as Integer).wrapping_add(offset.0)),
+                    ip: self.instruction_ip,
+                })?;
             },
             Instruction::Terminate => {
                 self.state = MachineState::Terminated;
-                return Err(Error::Terminated);
+                return Ok(Some(RunOutcome::Halted));
             }
         }
+        Ok(None)
+    }
+
+    fn jump(&mut self, dest: Arg) -> Result<(), Error> {
+        let target = self.read_value(dest)?;
+        self.ip.0 = usize::try_from(target.0).map_err(|_| Error::InvalidAddress {
+            invalid_address: target,
+            ip: self.instruction_ip,
+        })?;
         Ok(())
     }
 
-    pub fn run(&mut self) -> Result<(), Error> {
-        loop {
-            // println!("{:p} {:?},{:?} {:?}", self, self.ip, self.relbase, self.state);
-            //println!("  {:?}", self.memory);
-            match self.state {
-                MachineState::DecodeInstruction => {
-                    self.state = MachineState::ExecuteInstruction(self.pop_instruction()?);
-                }
-                MachineState::ExecuteInstruction(i) => {
-                    match self.execute_instruction(i) {
-                        Err(e) if e == Error::Terminated => {
-                            return Ok(());
-                        },
-                        Err(e) => {
-                            return Err(e);
-                        },
-                        Ok(_) => { },
-                    }
-                    self.state = MachineState::DecodeInstruction;
+    fn begin_trace(&mut self, ip: Address, instruction: Instruction) -> Result<(), Error> {
+        let code = instruction.code();
+        let mut reads = Vec::new();
+        for (i, arg) in instruction.args().into_iter().enumerate() {
+            if !code.writes_parameter(i) {
+                reads.push(self.read_value(arg)?);
+            }
+        }
+
+        self.trace_record = Some(TraceRecord {
+            ip,
+            relbase: self.relbase,
+            instruction,
+            reads,
+            writes: Vec::new(),
+            input: None,
+            output: None,
+        });
+        Ok(())
+    }
+
+    /// Memory cells an instruction with `args` reads and writes, for the
+    /// memory map.
+    /// Addresses that cannot be resolved are skipped; executing the
+    /// instruction will fail on them anyway.
+    fn accesses<F>(&self, args: &[Arg], writes: F) -> (Vec<usize>, Vec<usize>)
+    where
+        F: Fn(usize) -> bool,
+    {
+        let mut reads = Vec::new();
+        let mut written = Vec::new();
+        for (i, arg) in args.iter().enumerate() {
+            let address = match *arg {
+                Arg::Address(address) => address.0,
+                Arg::Offset(offset) => match self.relative_address(offset) {
+                    Ok(address) => address,
+                    Err(_) => continue,
                 },
-                MachineState::Terminated => {
-                    return Err(Error::Terminated);
+                Arg::Value(_) => continue,
+            };
+            if writes(i) {
+                written.push(address);
+            } else {
+                reads.push(address);
+            }
+        }
+        (reads, written)
+    }
+
+    /// Records an executed instruction in the memory map, if enabled.
+    fn record_accesses(
+        &mut self,
+        start: Address,
+        size: usize,
+        accesses: Option<(Vec<usize>, Vec<usize>)>,
+    ) {
+        if let (Some(map), Some((reads, written))) = (&mut self.memory_map, accesses) {
+            let memory = &self.memory;
+            let writes: Vec<_> = written.into_iter().map(|a| (a, Value(memory.get(a)))).collect();
+            map.record(start, size, &reads, &writes);
+        }
+    }
+
+    /// Decodes and executes a custom instruction at `start`.
+    fn step_custom(
+        &mut self,
+        start: Address,
+        custom: Arc<CustomOpcode<IO>>,
+    ) -> Result<Option<RunOutcome>, Error> {
+        let value = self.peek(start).0;
+        let digits = get_digits(value);
+        let mut ip = Address(start.0 + 1);
+        let mut args = Vec::with_capacity(custom.arity());
+        for i in 0..custom.arity() {
+            let mode = FromPrimitive::from_i64(digits[4 - i]).ok_or(Error::InvalidInstruction {
+                instruction_value: Value(value),
+                instruction_location: start,
+            })?;
+            args.push(self.pop_argument(&mut ip, start, mode)?);
+        }
+        self.ip = ip;
+        self.instruction_ip = start;
+
+        let accesses = if self.memory_map.is_some() {
+            Some(self.accesses(&args, |i| custom.writes_parameter(i)))
+        } else {
+            None
+        };
+
+        let result = (custom.execute)(self, &args);
+        match result {
+            // Only the ip is rewound; callbacks check for input before
+            // changing anything else (see `opcodes`).
+            Ok(Some(RunOutcome::NeedsInput)) | Err(_) => {
+                self.ip = start;
+            }
+            Ok(outcome) => {
+                if outcome == Some(RunOutcome::Halted) {
+                    self.state = MachineState::Terminated;
                 }
+                self.instruction_count += 1;
+                if let Some(budget) = &mut self.budget {
+                    *budget -= 1;
+                }
+                self.record_accesses(start, ip.0 - start.0, accesses);
+            }
+        }
+        result
+    }
+
+    /// Decodes and executes a single instruction. Returns `None` if execution
+    /// can simply continue. On `NeedsInput` the ip is left pointing at the
+    /// `Input` instruction so it is retried on the next step; an `Output` is
+    /// handed to the caller rather than written to the I/O.
+    pub fn step(&mut self) -> Result<Option<RunOutcome>, Error> {
+        if let MachineState::Terminated = self.state {
+            return Ok(Some(RunOutcome::Halted));
+        }
+        if self.budget == Some(0) {
+            return Ok(Some(RunOutcome::BudgetExhausted));
+        }
+
+        let start = self.ip;
+        let custom = match &self.opcodes {
+            Some(opcodes) => opcodes.lookup(self.peek(start).0),
+            None => None,
+        };
+        if let Some(custom) = custom {
+            return self.step_custom(start, custom);
+        }
+
+        let instruction = self.pop_instruction()?;
+        self.instruction_ip = start;
+
+        if self.tracer.is_some() {
+            if let Err(e) = self.begin_trace(start, instruction) {
+                self.ip = start;
+                return Err(e);
+            }
+        }
+
+        let accesses = if self.memory_map.is_some() {
+            let code = instruction.code();
+            Some(self.accesses(&instruction.args(), |i| code.writes_parameter(i)))
+        } else {
+            None
+        };
+
+        let result = self.execute_instruction(instruction);
+
+        let record = self.trace_record.take();
+        match result {
+            Ok(Some(RunOutcome::NeedsInput)) | Err(_) => {
+                self.ip = start;
+            }
+            Ok(_) => {
+                self.instruction_count += 1;
+                self.opcode_counts[instruction.code().index()] += 1;
+                if let Some(budget) = &mut self.budget {
+                    *budget -= 1;
+                }
+                if let (Some(tracer), Some(record)) = (&mut self.tracer, record) {
+                    tracer.trace(&record);
+                }
+                self.record_accesses(start, instruction.size(), accesses);
             }
         }
+        result
+    }
+
+    fn run_with(&mut self, stop_on_output: bool, limit: Option<u64>) -> Result<RunOutcome, Error> {
+        let mut steps = 0;
+        loop {
+            if limit == Some(steps) {
+                return Ok(RunOutcome::StepLimit);
+            }
+            steps += 1;
+
+            match self.step()? {
+                None => {}
+                Some(RunOutcome::Output(v)) if !stop_on_output => self.io.write(v),
+                Some(outcome) => return Ok(outcome),
+            }
+        }
+    }
+
+    /// Runs until the program halts or needs input. Outputs are written to
+    /// the machine's I/O.
+    pub fn run(&mut self) -> Result<RunOutcome, Error> {
+        self.run_with(false, None)
+    }
+
+    /// Runs until the program produces an output, halts or needs input.
+    pub fn run_until_output(&mut self) -> Result<RunOutcome, Error> {
+        self.run_with(true, None)
+    }
+
+    /// Like `run`, but stops with `StepLimit` after `steps` instructions.
+    pub fn run_for(&mut self, steps: u64) -> Result<RunOutcome, Error> {
+        self.run_with(false, Some(steps))
     }
 }
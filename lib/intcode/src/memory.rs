@@ -0,0 +1,260 @@
+//! Storage for a machine's memory.
+//!
+//! Intcode memory is unbounded and reads as zero past anything written. The
+//! default `Dense` backend is a `Vec` that grows to cover the highest written
+//! address, which is fastest for ordinary programs. `Sparse` keeps fixed-size
+//! pages in a map instead, so writing to a huge address costs one page.
+//! Zeros never allocate a page, and moving sparse memory to the dense
+//! backend is refused beyond `MAX_DENSE_LEN` cells.
+
+use crate::Integer;
+use std::collections::HashMap;
+use std::fmt;
+
+const PAGE_BITS: usize = 10;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+
+/// Most cells `MemoryBackend::to_kind` will allocate for dense memory.
+pub const MAX_DENSE_LEN: usize = 1 << 28;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MemoryKind {
+    Dense,
+    Sparse,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MemoryTooLarge {
+    pub len: usize,
+}
+
+impl fmt::Display for MemoryTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} cells of memory is too many to hold densely (limit {})",
+            self.len, MAX_DENSE_LEN
+        )
+    }
+}
+
+impl std::error::Error for MemoryTooLarge {}
+
+#[derive(Debug, Clone, Default)]
+pub struct SparseMemory {
+    pages: HashMap<usize, Box<[Integer]>>,
+    len: usize,
+}
+
+impl SparseMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, address: usize) -> Integer {
+        match self.pages.get(&(address >> PAGE_BITS)) {
+            Some(page) => page[address & (PAGE_SIZE - 1)],
+            None => 0,
+        }
+    }
+
+    /// Writes `value`, allocating its page unless it is zero.
+    pub fn set(&mut self, address: usize, value: Integer) {
+        let key = address >> PAGE_BITS;
+        if value != 0 || self.pages.contains_key(&key) {
+            let page = self
+                .pages
+                .entry(key)
+                .or_insert_with(|| vec![0; PAGE_SIZE].into_boxed_slice());
+            page[address & (PAGE_SIZE - 1)] = value;
+        }
+        self.len = self.len.max(address + 1);
+    }
+
+    /// Number of allocated pages.
+    pub fn pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// The non-zero cells as `(address, value)`, in address order.
+    pub fn cells(&self) -> impl Iterator<Item = (usize, Integer)> + '_ {
+        let mut keys: Vec<usize> = self.pages.keys().copied().collect();
+        keys.sort_unstable();
+        keys.into_iter().flat_map(move |key| {
+            let base = key << PAGE_BITS;
+            self.pages[&key]
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| **v != 0)
+                .map(move |(offset, v)| (base + offset, *v))
+        })
+    }
+}
+
+/// Pages that have gone back to all zeros don't make two memories differ.
+impl PartialEq for SparseMemory {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.cells().eq(other.cells())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MemoryBackend {
+    Dense(Vec<Integer>),
+    Sparse(SparseMemory),
+}
+
+impl MemoryBackend {
+    pub fn new(kind: MemoryKind, contents: &[Integer]) -> Self {
+        match kind {
+            MemoryKind::Dense => MemoryBackend::Dense(contents.to_vec()),
+            MemoryKind::Sparse => {
+                let mut sparse = SparseMemory::new();
+                for (address, value) in contents.iter().enumerate() {
+                    sparse.set(address, *value);
+                }
+                MemoryBackend::Sparse(sparse)
+            }
+        }
+    }
+
+    /// The same contents in a `kind` backend. Dense memory holds every cell
+    /// up to `len()`, so this fails rather than allocate more than
+    /// `MAX_DENSE_LEN` of them.
+    pub fn to_kind(&self, kind: MemoryKind) -> Result<MemoryBackend, MemoryTooLarge> {
+        match (self, kind) {
+            (MemoryBackend::Dense(v), MemoryKind::Sparse) => {
+                Ok(MemoryBackend::new(MemoryKind::Sparse, v))
+            }
+            (MemoryBackend::Sparse(s), MemoryKind::Dense) => {
+                if s.len > MAX_DENSE_LEN {
+                    return Err(MemoryTooLarge { len: s.len });
+                }
+                let mut v = vec![0; s.len];
+                for (address, value) in s.cells() {
+                    v[address] = value;
+                }
+                Ok(MemoryBackend::Dense(v))
+            }
+            _ => Ok(self.clone()),
+        }
+    }
+
+    pub fn kind(&self) -> MemoryKind {
+        match self {
+            MemoryBackend::Dense(_) => MemoryKind::Dense,
+            MemoryBackend::Sparse(_) => MemoryKind::Sparse,
+        }
+    }
+
+    /// The value at `address`; zero if it has never been written.
+    pub fn get(&self, address: usize) -> Integer {
+        match self {
+            MemoryBackend::Dense(v) => v.get(address).copied().unwrap_or(0),
+            MemoryBackend::Sparse(s) => s.get(address),
+        }
+    }
+
+    /// Like `get`, but `None` past the end of memory.
+    pub fn try_get(&self, address: usize) -> Option<Integer> {
+        if address < self.len() {
+            Some(self.get(address))
+        } else {
+            None
+        }
+    }
+
+    pub fn set(&mut self, address: usize, value: Integer) {
+        match self {
+            MemoryBackend::Dense(v) => {
+                if v.len() <= address {
+                    v.resize(address + 1, 0);
+                }
+                v[address] = value;
+            }
+            MemoryBackend::Sparse(s) => s.set(address, value),
+        }
+    }
+
+    /// One past the highest address loaded or written.
+    pub fn len(&self) -> usize {
+        match self {
+            MemoryBackend::Dense(v) => v.len(),
+            MemoryBackend::Sparse(s) => s.len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Memory contents up to `len()`. For sparse memory this allocates every
+    /// cell, including the unwritten ones; see `to_kind` for a checked form.
+    pub fn to_vec(&self) -> Vec<Integer> {
+        match self {
+            MemoryBackend::Dense(v) => v.clone(),
+            MemoryBackend::Sparse(s) => (0..s.len).map(|a| s.get(a)).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backends_agree() {
+        let mut dense = MemoryBackend::new(MemoryKind::Dense, &[1, 2, 3]);
+        let mut sparse = MemoryBackend::new(MemoryKind::Sparse, &[1, 2, 3]);
+        for m in [&mut dense, &mut sparse].iter_mut() {
+            m.set(2000, -4);
+            m.set(1, 7);
+        }
+        assert_eq!(dense.to_vec(), sparse.to_vec());
+        assert_eq!(sparse.len(), 2001);
+        assert_eq!(sparse.get(1), 7);
+        assert_eq!(sparse.get(5000), 0);
+        assert_eq!(sparse.try_get(2001), None);
+    }
+
+    #[test]
+    fn sparse_allocates_pages_on_write() {
+        let mut s = SparseMemory::new();
+        s.set(1_000_000_000_000, 5);
+        assert_eq!(s.pages(), 1);
+        assert_eq!(s.get(1_000_000_000_000), 5);
+        assert_eq!(s.get(999_999_999_999), 0);
+
+        s.set(2_000_000_000_000, 0);
+        assert_eq!(s.pages(), 1);
+        assert_eq!(s.len, 2_000_000_000_001);
+        assert_eq!(s.cells().collect::<Vec<_>>(), [(1_000_000_000_000, 5)]);
+
+        let zeros = MemoryBackend::new(MemoryKind::Sparse, &[0; 5000]);
+        assert_eq!(zeros.len(), 5000);
+        assert_eq!(
+            zeros,
+            MemoryBackend::Sparse(SparseMemory {
+                pages: HashMap::new(),
+                len: 5000
+            })
+        );
+    }
+
+    #[test]
+    fn converts_between_kinds() {
+        let mut sparse = MemoryBackend::new(MemoryKind::Sparse, &[1, 0, 3]);
+        sparse.set(1500, 9);
+        let dense = sparse.to_kind(MemoryKind::Dense).unwrap();
+        assert_eq!(dense.to_vec(), sparse.to_vec());
+        assert_eq!(dense.to_kind(MemoryKind::Sparse), Ok(sparse.clone()));
+
+        sparse.set(1_000_000_000_000, 1);
+        assert_eq!(
+            sparse.to_kind(MemoryKind::Dense),
+            Err(MemoryTooLarge {
+                len: 1_000_000_000_001
+            })
+        );
+    }
+}
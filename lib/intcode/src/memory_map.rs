@@ -0,0 +1,163 @@
+//! Per-address access counts and self-modifying code detection.
+//!
+//! With `Machine::enable_memory_map`, every executed instruction adds to the
+//! map: each cell of the instruction counts as executed, each memory
+//! parameter it reads counts as read, and the cell it writes counts as
+//! written. A write to any cell that has already been executed is recorded
+//! as a `CodeWrite`. Reads and writes by `peek` and `poke` are not counted.
+
+use crate::{Address, Value};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct AccessCounts {
+    pub reads: u64,
+    pub writes: u64,
+    pub executes: u64,
+}
+
+/// A program write into a cell that had already run as code.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CodeWrite {
+    /// Address of the writing instruction.
+    pub ip: Address,
+    pub address: Address,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemoryMap {
+    counts: BTreeMap<usize, AccessCounts>,
+    code_writes: Vec<CodeWrite>,
+}
+
+impl MemoryMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn counts(&self, address: Address) -> AccessCounts {
+        self.counts.get(&address.0).copied().unwrap_or_default()
+    }
+
+    /// Every address accessed at least once, in address order.
+    pub fn iter(&self) -> impl Iterator<Item = (Address, AccessCounts)> + '_ {
+        self.counts.iter().map(|(a, c)| (Address(*a), *c))
+    }
+
+    pub fn is_code(&self, address: Address) -> bool {
+        self.counts(address).executes > 0
+    }
+
+    pub fn is_data(&self, address: Address) -> bool {
+        let counts = self.counts(address);
+        counts.reads > 0 || counts.writes > 0
+    }
+
+    /// Writes into executed code, in the order they happened.
+    pub fn code_writes(&self) -> &[CodeWrite] {
+        &self.code_writes
+    }
+
+    /// Writes the map as CSV with an `address,reads,writes,executes` header
+    /// and one row per accessed address.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "address,reads,writes,executes")?;
+        for (address, c) in self.iter() {
+            writeln!(writer, "{},{},{},{}", address.0, c.reads, c.writes, c.executes)?;
+        }
+        Ok(())
+    }
+
+    /// Records one executed instruction of `size` cells at `ip`.
+    pub(crate) fn record(
+        &mut self,
+        ip: Address,
+        size: usize,
+        reads: &[usize],
+        writes: &[(usize, Value)],
+    ) {
+        for address in ip.0..ip.0 + size {
+            self.counts.entry(address).or_default().executes += 1;
+        }
+        for address in reads {
+            self.counts.entry(*address).or_default().reads += 1;
+        }
+        for &(address, value) in writes {
+            let counts = self.counts.entry(address).or_default();
+            counts.writes += 1;
+            if counts.executes > 0 {
+                self.code_writes.push(CodeWrite {
+                    ip,
+                    address: Address(address),
+                    value,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::{BufferIo, Machine};
+
+    #[test]
+    fn counts_accesses() {
+        // in [9]; add [9], #2, [10]; out [10]; hlt
+        let program = [3, 9, 1001, 9, 2, 10, 4, 10, 99, 0, 0];
+        let mut m = Machine::with_io(&program, BufferIo::with_input(vec![Value(5)]));
+        m.enable_memory_map();
+        m.run().unwrap();
+
+        let map = m.memory_map().unwrap();
+        let count = |a| map.counts(Address(a));
+        assert_eq!(count(0), AccessCounts { reads: 0, writes: 0, executes: 1 });
+        assert_eq!(count(9), AccessCounts { reads: 1, writes: 1, executes: 0 });
+        assert_eq!(count(10), AccessCounts { reads: 1, writes: 1, executes: 0 });
+        assert!(map.is_code(Address(5)) && !map.is_data(Address(5)));
+        assert!(map.code_writes().is_empty());
+
+        let mut csv = Vec::new();
+        map.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 12);
+        assert_eq!(lines[0], "address,reads,writes,executes");
+        assert_eq!(lines[10], "9,1,1,0");
+    }
+
+    #[test]
+    fn flags_writes_into_executed_code() {
+        // Rewrites its own `out #1` into `out #2` after running it once.
+        let program = assemble(
+            "
+            again:  out  #1
+                    jt   [flag], #done
+                    add  #1, #0, [flag]
+                    add  #2, #0, [again+1]
+                    jt   #1, #again
+            done:   hlt
+            flag:   .data 0
+            ",
+        )
+        .unwrap();
+        let mut m = Machine::with_io(&program, BufferIo::new());
+        m.enable_memory_map();
+        m.run().unwrap();
+
+        let map = m.take_memory_map().unwrap();
+        assert_eq!(
+            map.code_writes(),
+            &[CodeWrite {
+                ip: Address(9),
+                address: Address(1),
+                value: Value(2),
+            }]
+        );
+        assert_eq!(map.counts(Address(1)).executes, 2);
+        assert!(m.memory_map().is_none());
+    }
+}
@@ -0,0 +1,426 @@
+//! Several machines wired together.
+//!
+//! A `Network` is a set of nodes, each a program with its own input queue,
+//! and a routing rule per node for where its outputs go:
+//!
+//! * `Routing::Edges` sends every output value to each connected node, in
+//!   order. A node with no edges keeps its outputs (see `Network::outputs`).
+//! * `Routing::Packets` groups outputs into `(address, x, y)` triples and
+//!   delivers `x, y` to the node whose id is `address`. Packets addressed to
+//!   a node that does not exist are collected in `Network::undelivered`.
+//!
+//! `run` drives the nodes with the chosen `Scheduler` until every node has
+//! halted, the network deadlocks, or, for nodes given an idle input, the
+//! network goes idle.
+
+use crate::{BufferIo, Error, Integer, Machine, RunOutcome, Value};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub usize);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Routing {
+    Edges(Vec<NodeId>),
+    Packets,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Packet {
+    pub address: Integer,
+    pub x: Value,
+    pub y: Value,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Scheduler {
+    /// Each turn runs a node for at most this many instructions.
+    RoundRobin(u64),
+    /// Each turn runs a node until it needs input or halts.
+    UntilBlocked,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum NetworkOutcome {
+    AllHalted,
+    /// No node can make progress: each running node is waiting for input
+    /// that will never arrive or has exhausted its budget.
+    Deadlock,
+    /// Every running node is waiting on an empty input queue and nothing
+    /// was sent during the last round. Nodes with an idle input have just
+    /// been handed it; at least one node has one.
+    Idle,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NetworkError {
+    pub node: NodeId,
+    pub error: Error,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct NodeStats {
+    /// Turns in which the node was scheduled.
+    pub turns: u64,
+    pub instructions: u64,
+    /// Values delivered to the node, including its initial inputs but not
+    /// idle values.
+    pub inputs: u64,
+    pub outputs: u64,
+    /// Times the node was handed its idle value.
+    pub idle_inputs: u64,
+    pub last_output: Option<Value>,
+}
+
+struct Node {
+    machine: Machine<BufferIo>,
+    routing: Routing,
+    idle_input: Option<Value>,
+    /// Outputs with nowhere to go: edge outputs with no edges, or the start
+    /// of an incomplete packet.
+    outputs: Vec<Value>,
+    waiting: bool,
+    stats: NodeStats,
+}
+
+pub struct Network {
+    nodes: Vec<Node>,
+    scheduler: Scheduler,
+    undelivered: Vec<Packet>,
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Network {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            scheduler: Scheduler::UntilBlocked,
+            undelivered: Vec::new(),
+        }
+    }
+
+    /// Adds a node running `program` with `inputs` queued. Its outputs are
+    /// kept until it is connected or given packet routing.
+    pub fn add_node(&mut self, program: &[Integer], inputs: &[Integer]) -> NodeId {
+        let io = BufferIo::with_input(inputs.iter().copied().map(Value));
+        self.nodes.push(Node {
+            machine: Machine::with_io(program, io),
+            routing: Routing::Edges(Vec::new()),
+            idle_input: None,
+            outputs: Vec::new(),
+            waiting: false,
+            stats: NodeStats {
+                inputs: inputs.len() as u64,
+                ..NodeStats::default()
+            },
+        });
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Routes `from`'s outputs to `to` as well as any existing edges.
+    pub fn connect(&mut self, from: NodeId, to: NodeId) {
+        match &mut self.nodes[from.0].routing {
+            Routing::Edges(edges) => edges.push(to),
+            routing => *routing = Routing::Edges(vec![to]),
+        }
+    }
+
+    pub fn set_routing(&mut self, node: NodeId, routing: Routing) {
+        self.nodes[node.0].routing = routing;
+    }
+
+    /// Value handed to `node` whenever it needs input and its queue is
+    /// empty, instead of leaving it blocked.
+    pub fn set_idle_input(&mut self, node: NodeId, value: Option<Value>) {
+        self.nodes[node.0].idle_input = value;
+    }
+
+    pub fn set_scheduler(&mut self, scheduler: Scheduler) {
+        self.scheduler = scheduler;
+    }
+
+    pub fn machine(&self, node: NodeId) -> &Machine<BufferIo> {
+        &self.nodes[node.0].machine
+    }
+
+    pub fn machine_mut(&mut self, node: NodeId) -> &mut Machine<BufferIo> {
+        &mut self.nodes[node.0].machine
+    }
+
+    /// Queues a value for `node` from outside the network.
+    pub fn send(&mut self, node: NodeId, value: Value) {
+        let n = &mut self.nodes[node.0];
+        n.machine.io_mut().input.push_back(value);
+        n.stats.inputs += 1;
+    }
+
+    /// Outputs `node` produced that were not routed anywhere.
+    pub fn outputs(&self, node: NodeId) -> &[Value] {
+        &self.nodes[node.0].outputs
+    }
+
+    /// Packets sent to addresses with no node, oldest first.
+    pub fn undelivered(&self) -> &[Packet] {
+        &self.undelivered
+    }
+
+    pub fn take_undelivered(&mut self) -> Vec<Packet> {
+        std::mem::take(&mut self.undelivered)
+    }
+
+    pub fn stats(&self, node: NodeId) -> &NodeStats {
+        &self.nodes[node.0].stats
+    }
+
+    /// Sends `from`'s new outputs on their way. Returns the number of
+    /// values it produced.
+    fn route(&mut self, from: usize) -> usize {
+        let produced: Vec<Value> = self.nodes[from].machine.io_mut().output.drain(..).collect();
+        let count = produced.len();
+        if let Some(v) = produced.last() {
+            let stats = &mut self.nodes[from].stats;
+            stats.outputs += count as u64;
+            stats.last_output = Some(*v);
+        }
+
+        match self.nodes[from].routing.clone() {
+            Routing::Edges(edges) if edges.is_empty() => {
+                self.nodes[from].outputs.extend(produced);
+            }
+            Routing::Edges(edges) => {
+                for v in produced {
+                    for to in &edges {
+                        self.send(*to, v);
+                    }
+                }
+            }
+            Routing::Packets => {
+                let mut pending = std::mem::take(&mut self.nodes[from].outputs);
+                pending.extend(produced);
+                let mut packets = pending.chunks_exact(3);
+                for p in &mut packets {
+                    let to = p[0].0;
+                    if to >= 0 && (to as usize) < self.nodes.len() {
+                        self.send(NodeId(to as usize), p[1]);
+                        self.send(NodeId(to as usize), p[2]);
+                    } else {
+                        self.undelivered.push(Packet {
+                            address: to,
+                            x: p[1],
+                            y: p[2],
+                        });
+                    }
+                }
+                self.nodes[from].outputs = packets.remainder().to_vec();
+            }
+        }
+        count
+    }
+
+    /// Runs one turn of `node`. Returns whether it executed anything and
+    /// the number of values it produced.
+    fn turn(&mut self, i: usize) -> Result<(bool, usize), NetworkError> {
+        let scheduler = self.scheduler;
+        let node = &mut self.nodes[i];
+        let before = node.machine.instruction_count();
+        let result = match scheduler {
+            Scheduler::RoundRobin(steps) => node.machine.run_for(steps),
+            Scheduler::UntilBlocked => node.machine.run(),
+        };
+        node.stats.turns += 1;
+        node.stats.instructions += node.machine.instruction_count() - before;
+        let ran = node.machine.instruction_count() != before;
+
+        let outcome = result.map_err(|error| NetworkError {
+            node: NodeId(i),
+            error,
+        })?;
+        node.waiting = outcome == RunOutcome::NeedsInput;
+
+        let sent = self.route(i);
+        Ok((ran, sent))
+    }
+
+    /// Runs until every node halts, the network deadlocks or goes idle.
+    /// Running again after `Idle` continues from where it stopped, so a
+    /// caller can inject values with `send` and resume.
+    pub fn run(&mut self) -> Result<NetworkOutcome, NetworkError> {
+        loop {
+            let mut ran = false;
+            let mut sent = 0;
+            for i in 0..self.nodes.len() {
+                if self.nodes[i].machine.is_terminated() {
+                    continue;
+                }
+                let (r, s) = self.turn(i)?;
+                ran |= r;
+                sent += s;
+            }
+
+            let mut live = 0;
+            let mut starved = 0;
+            let mut fed = false;
+            for node in &mut self.nodes {
+                if node.machine.is_terminated() {
+                    continue;
+                }
+                live += 1;
+                if node.waiting && node.machine.io().input.is_empty() {
+                    starved += 1;
+                    if let Some(v) = node.idle_input {
+                        node.machine.io_mut().input.push_back(v);
+                        node.stats.idle_inputs += 1;
+                        fed = true;
+                    }
+                }
+            }
+
+            if live == 0 {
+                return Ok(NetworkOutcome::AllHalted);
+            }
+            // Nothing ran and nothing new will arrive, e.g. every node is
+            // blocked or out of budget.
+            if !ran && !fed {
+                return Ok(NetworkOutcome::Deadlock);
+            }
+            // Everyone is starved. Only an idle input can wake them, so
+            // without one this is a deadlock, not idleness.
+            if ran && sent == 0 && starved == live {
+                return Ok(if fed {
+                    NetworkOutcome::Idle
+                } else {
+                    NetworkOutcome::Deadlock
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    #[test]
+    fn amplifier_ring() {
+        let program = [
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+        for scheduler in &[Scheduler::UntilBlocked, Scheduler::RoundRobin(3)] {
+            let mut net = Network::new();
+            let nodes: Vec<NodeId> = [9, 8, 7, 6, 5]
+                .iter()
+                .map(|phase| net.add_node(&program, &[*phase]))
+                .collect();
+            for i in 0..5 {
+                net.connect(nodes[i], nodes[(i + 1) % 5]);
+            }
+            net.send(nodes[0], Value(0));
+            net.set_scheduler(*scheduler);
+
+            assert_eq!(net.run(), Ok(NetworkOutcome::AllHalted));
+            assert_eq!(net.stats(nodes[4]).last_output, Some(Value(139629729)));
+            assert_eq!(net.stats(nodes[4]).outputs, 5);
+            assert_eq!(net.stats(nodes[0]).inputs, 7);
+        }
+    }
+
+    #[test]
+    fn unconnected_outputs_are_kept() {
+        let mut net = Network::new();
+        let a = net.add_node(&[104, 1, 104, 2, 99], &[]);
+        assert_eq!(net.run(), Ok(NetworkOutcome::AllHalted));
+        assert_eq!(net.outputs(a), &[Value(1), Value(2)]);
+    }
+
+    #[test]
+    fn detects_deadlock() {
+        // Two nodes that each wait for the other before sending anything.
+        let program = [3, 7, 4, 7, 1105, 1, 0, 0];
+        let mut net = Network::new();
+        let a = net.add_node(&program, &[]);
+        let b = net.add_node(&program, &[]);
+        net.connect(a, b);
+        net.connect(b, a);
+        assert_eq!(net.run(), Ok(NetworkOutcome::Deadlock));
+
+        // Once started they pass the value back and forth until a budget
+        // stops them.
+        net.send(a, Value(5));
+        net.machine_mut(b).set_budget(Some(30));
+        assert_eq!(net.run(), Ok(NetworkOutcome::Deadlock));
+        assert_eq!(net.stats(b).instructions, 30);
+        assert_eq!(net.stats(a).last_output, Some(Value(5)));
+
+        // A node that drains its input and then starves, with no idle input
+        // to wake it, is deadlocked too.
+        let mut net = Network::new();
+        let sink = net.add_node(&[3, 5, 1105, 1, 0, 0], &[1, 2, 3]);
+        assert_eq!(net.run(), Ok(NetworkOutcome::Deadlock));
+        assert_eq!(net.stats(sink).inputs, 3);
+    }
+
+    #[test]
+    fn routes_packets_and_goes_idle() {
+        // Sends (1, 10, 20) and (255, 3, 4), then reads forever.
+        let sender = assemble(
+            "
+                    out  #1
+                    out  #10
+                    out  #20
+                    out  #255
+                    out  #3
+                    out  #4
+            loop:   in   [0]
+                    jt   #1, #loop
+            ",
+        )
+        .unwrap();
+        // Forwards every packet it receives to address 255.
+        let forwarder = assemble(
+            "
+            loop:   in   [x]
+                    eq   [x], #-1, [skip]
+                    jt   [skip], #loop
+                    in   [y]
+                    out  #255
+                    out  [x]
+                    out  [y]
+                    jt   #1, #loop
+            x:      .data 0
+            y:      .data 0
+            skip:   .data 0
+            ",
+        )
+        .unwrap();
+
+        let mut net = Network::new();
+        let a = net.add_node(&sender, &[]);
+        let b = net.add_node(&forwarder, &[]);
+        for n in &[a, b] {
+            net.set_routing(*n, Routing::Packets);
+            net.set_idle_input(*n, Some(Value(-1)));
+        }
+
+        assert_eq!(net.run(), Ok(NetworkOutcome::Idle));
+        let packet = |x, y| Packet {
+            address: 255,
+            x: Value(x),
+            y: Value(y),
+        };
+        assert_eq!(net.take_undelivered(), vec![packet(3, 4), packet(10, 20)]);
+        assert!(net.stats(a).idle_inputs > 0);
+        assert_eq!(net.stats(b).inputs, 2);
+
+        // Resuming after an injected packet forwards it and idles again.
+        net.send(b, Value(7));
+        net.send(b, Value(8));
+        assert_eq!(net.run(), Ok(NetworkOutcome::Idle));
+        assert_eq!(net.undelivered(), &[packet(7, 8)]);
+    }
+}
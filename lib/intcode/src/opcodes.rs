@@ -0,0 +1,279 @@
+//! Custom opcodes.
+//!
+//! An `OpcodeRegistry` adds opcodes beyond the built-in instruction set. Each
+//! is registered with its number, the number of parameters that follow it,
+//! which of those parameters it writes, and a callback that executes it.
+//! Install a registry with `Machine::set_opcodes`; a machine without one runs
+//! the built-in set only.
+//!
+//! The callback gets the machine and the decoded parameters, with the ip
+//! already past the instruction. It reads and writes parameters with
+//! `Machine::read_arg` and `Machine::write_arg`, jumps with `Machine::set_ip`,
+//! and returns what `execute_instruction` would: `None` to continue, or an
+//! outcome. Returning `NeedsInput` rewinds the ip so the instruction is
+//! retried, and returning `Halted` terminates the machine.
+//!
+//! Only the ip is rewound: memory writes, relative base changes and I/O done
+//! before returning `NeedsInput` stay, and happen again on the retry. A
+//! callback that reads input must do so, and return `NeedsInput` if there is
+//! none, before it changes anything else.
+//!
+//! Custom instructions are counted in `instruction_count` and the memory map,
+//! but are not traced and have no `Instruction` form, so the disassembler and
+//! `instruction_at` see them as invalid. `FastMachine` does not support them.
+
+use crate::{Arg, ChannelIo, Error, Integer, Machine, RunOutcome, INSTRUCTION_CODES};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Opcodes are the last two decimal digits of an instruction.
+const MAX_OPCODE: Integer = 99;
+/// Parameter modes are encoded for at most three parameters.
+const MAX_ARITY: usize = 3;
+
+pub type Execute<IO> =
+    dyn Fn(&mut Machine<IO>, &[Arg]) -> Result<Option<RunOutcome>, Error> + Send + Sync;
+
+pub struct CustomOpcode<IO> {
+    arity: usize,
+    writes: Vec<usize>,
+    pub(crate) execute: Box<Execute<IO>>,
+}
+
+impl<IO> CustomOpcode<IO> {
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    /// Whether the parameter at `index` is written to rather than read.
+    pub fn writes_parameter(&self, index: usize) -> bool {
+        self.writes.contains(&index)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegisterError {
+    /// The opcode is part of the built-in instruction set.
+    Builtin(Integer),
+    /// Opcodes must be between 1 and 99.
+    OutOfRange(Integer),
+    AlreadyRegistered(Integer),
+    TooManyParameters(usize),
+    /// A written parameter index is not below the arity.
+    InvalidWriteIndex(usize),
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegisterError::Builtin(op) => write!(f, "opcode {} is built in", op),
+            RegisterError::OutOfRange(op) => write!(f, "opcode {} is not between 1 and 99", op),
+            RegisterError::AlreadyRegistered(op) => {
+                write!(f, "opcode {} is already registered", op)
+            }
+            RegisterError::TooManyParameters(n) => {
+                write!(
+                    f,
+                    "{} parameters is more than the {} modes can describe",
+                    n, MAX_ARITY
+                )
+            }
+            RegisterError::InvalidWriteIndex(i) => write!(f, "no parameter {} to write", i),
+        }
+    }
+}
+
+impl std::error::Error for RegisterError {}
+
+pub struct OpcodeRegistry<IO = ChannelIo> {
+    opcodes: HashMap<Integer, Arc<CustomOpcode<IO>>>,
+}
+
+impl<IO> Default for OpcodeRegistry<IO> {
+    fn default() -> Self {
+        Self {
+            opcodes: HashMap::new(),
+        }
+    }
+}
+
+impl<IO> fmt::Debug for OpcodeRegistry<IO> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut opcodes: Vec<_> = self.opcodes.keys().collect();
+        opcodes.sort();
+        f.debug_struct("OpcodeRegistry")
+            .field("opcodes", &opcodes)
+            .finish()
+    }
+}
+
+impl<IO> OpcodeRegistry<IO> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `opcode`, taking `arity` parameters of which those at the
+    /// indices in `writes` are written. See the module docs for what
+    /// `execute` may do before returning `NeedsInput`.
+    pub fn register<F>(
+        &mut self,
+        opcode: Integer,
+        arity: usize,
+        writes: &[usize],
+        execute: F,
+    ) -> Result<(), RegisterError>
+    where
+        F: Fn(&mut Machine<IO>, &[Arg]) -> Result<Option<RunOutcome>, Error>
+            + Send
+            + Sync
+            + 'static,
+    {
+        if INSTRUCTION_CODES
+            .iter()
+            .any(|code| *code as Integer == opcode)
+        {
+            return Err(RegisterError::Builtin(opcode));
+        }
+        if !(1..=MAX_OPCODE).contains(&opcode) {
+            return Err(RegisterError::OutOfRange(opcode));
+        }
+        if self.opcodes.contains_key(&opcode) {
+            return Err(RegisterError::AlreadyRegistered(opcode));
+        }
+        if arity > MAX_ARITY {
+            return Err(RegisterError::TooManyParameters(arity));
+        }
+        if let Some(i) = writes.iter().find(|i| **i >= arity) {
+            return Err(RegisterError::InvalidWriteIndex(*i));
+        }
+
+        let custom = CustomOpcode {
+            arity,
+            writes: writes.to_vec(),
+            execute: Box::new(execute),
+        };
+        self.opcodes.insert(opcode, Arc::new(custom));
+        Ok(())
+    }
+
+    pub fn get(&self, opcode: Integer) -> Option<&CustomOpcode<IO>> {
+        self.opcodes.get(&opcode).map(|custom| &**custom)
+    }
+
+    /// The custom opcode an instruction value decodes to, if any.
+    pub(crate) fn lookup(&self, value: Integer) -> Option<Arc<CustomOpcode<IO>>> {
+        if value < 0 {
+            return None;
+        }
+        self.opcodes.get(&(value % 100)).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Address, BufferIo, Value};
+
+    fn registry() -> OpcodeRegistry<BufferIo> {
+        let mut registry = OpcodeRegistry::<BufferIo>::new();
+        // swp a, b: exchanges two cells.
+        registry
+            .register(20, 2, &[0, 1], |m, args| {
+                let a = m.read_arg(args[0])?;
+                let b = m.read_arg(args[1])?;
+                m.write_arg(args[0], b)?;
+                m.write_arg(args[1], a)?;
+                Ok(None)
+            })
+            .unwrap();
+        // djnz a, dest: decrements a and jumps to dest unless it reached zero.
+        registry
+            .register(21, 2, &[0], |m, args| {
+                let v = Value(m.read_arg(args[0])?.0 - 1);
+                m.write_arg(args[0], v)?;
+                if v.0 != 0 {
+                    let dest = m.read_arg(args[1])?;
+                    m.set_ip(Address(dest.0 as usize));
+                }
+                Ok(None)
+            })
+            .unwrap();
+        // inout: echoes one input value, waiting for it if needed.
+        registry
+            .register(22, 0, &[], |m, _| match m.io_mut().input.pop_front() {
+                Some(v) => Ok(Some(RunOutcome::Output(v))),
+                None => Ok(Some(RunOutcome::NeedsInput)),
+            })
+            .unwrap();
+        registry
+    }
+
+    #[test]
+    fn runs_custom_instructions() {
+        // loop: swp [12], [13]; djnz [14], #loop; out [12]; hlt
+        let program = [20, 12, 13, 1021, 14, 0, 4, 12, 99, 0, 0, 0, 5, 7, 3];
+        let mut m = Machine::with_io(&program, BufferIo::new());
+        m.set_opcodes(Some(Arc::new(registry())));
+        assert_eq!(m.run(), Ok(RunOutcome::Halted));
+
+        // Three swaps leave the cells swapped; djnz ran three times.
+        assert_eq!(m.io().output, vec![Value(7)]);
+        assert_eq!(m.peek(Address(14)), Value(0));
+        assert_eq!(m.instruction_count(), 8);
+    }
+
+    #[test]
+    fn waits_for_input_and_faults_like_builtins() {
+        let mut m = Machine::with_io(&[22, 99], BufferIo::new());
+        m.set_opcodes(Some(Arc::new(registry())));
+        assert_eq!(m.run(), Ok(RunOutcome::NeedsInput));
+        assert_eq!(m.ip(), Address(0));
+        m.io_mut().input.push_back(Value(9));
+        assert_eq!(m.run(), Ok(RunOutcome::Halted));
+        assert_eq!(m.io().output, vec![Value(9)]);
+
+        // Writing an immediate parameter, and a bad mode digit.
+        let mut m = Machine::with_io(&[1020, 1, 2, 99], BufferIo::new());
+        m.set_opcodes(Some(Arc::new(registry())));
+        assert_eq!(m.run(), Err(Error::WriteToImmediate { ip: Address(0) }));
+        let mut m = Machine::with_io(&[320, 1, 2, 99], BufferIo::new());
+        m.set_opcodes(Some(Arc::new(registry())));
+        assert!(matches!(m.run(), Err(Error::InvalidInstruction { .. })));
+    }
+
+    #[test]
+    fn builtins_stay_the_default() {
+        let program = [20, 0, 1, 99];
+        let mut m = Machine::with_io(&program, BufferIo::new());
+        assert!(matches!(m.run(), Err(Error::InvalidInstruction { .. })));
+
+        let mut registry = registry();
+        let noop = |_: &mut Machine<BufferIo>, _: &[Arg]| Ok(None);
+        assert_eq!(
+            registry.register(1, 0, &[], noop),
+            Err(RegisterError::Builtin(1))
+        );
+        assert_eq!(
+            registry.register(99, 0, &[], noop),
+            Err(RegisterError::Builtin(99))
+        );
+        assert_eq!(
+            registry.register(100, 0, &[], noop),
+            Err(RegisterError::OutOfRange(100))
+        );
+        assert_eq!(
+            registry.register(20, 0, &[], noop),
+            Err(RegisterError::AlreadyRegistered(20))
+        );
+        assert_eq!(
+            registry.register(30, 4, &[], noop),
+            Err(RegisterError::TooManyParameters(4))
+        );
+        assert_eq!(
+            registry.register(30, 1, &[1], noop),
+            Err(RegisterError::InvalidWriteIndex(1))
+        );
+        assert!(registry.get(20).unwrap().writes_parameter(1));
+    }
+}
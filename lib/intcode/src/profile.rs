@@ -0,0 +1,283 @@
+//! Instruction-set profiles.
+//!
+//! The instruction set grew over the puzzles: day 2 had only `add`, `mul`
+//! and `hlt` in position mode, day 5 added I/O, jumps, comparisons and
+//! immediate mode, and day 9 added `rb` and relative mode. A `Profile`
+//! restricts a `Machine` to one of those sets with `Machine::set_profile`.
+//! An instruction outside it, whether by opcode or by parameter mode, fails
+//! to decode with `Error::InvalidInstruction`, just as an unknown opcode
+//! does. The default profile is the full day 9 set.
+//!
+//! Profiles apply to the built-in instructions only; custom opcodes from an
+//! `OpcodeRegistry` are decoded whatever the profile.
+
+use crate::{Instruction, InstructionCode, ParameterMode, INSTRUCTION_CODES};
+
+/// Profiles are ordered by day, and each includes everything before it.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Profile {
+    Day2,
+    Day5,
+    #[default]
+    Day9,
+}
+
+impl Profile {
+    pub const ALL: [Profile; 3] = [Profile::Day2, Profile::Day5, Profile::Day9];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Profile::Day2 => "day2",
+            Profile::Day5 => "day5",
+            Profile::Day9 => "day9",
+        }
+    }
+
+    /// Accepts a profile's name, or just its day number.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let day = name.strip_prefix("day").unwrap_or(name);
+        Profile::ALL.iter().copied().find(|p| p.name()[3..] == *day)
+    }
+
+    /// The opcodes in the profile, in `INSTRUCTION_CODES` order.
+    pub fn opcodes(self) -> &'static [InstructionCode] {
+        match self {
+            Profile::Day2 => &[
+                InstructionCode::Add,
+                InstructionCode::Mult,
+                InstructionCode::Terminate,
+            ],
+            Profile::Day5 => &[
+                InstructionCode::Add,
+                InstructionCode::Mult,
+                InstructionCode::Input,
+                InstructionCode::Output,
+                InstructionCode::JumpIfTrue,
+                InstructionCode::JumpIfFalse,
+                InstructionCode::LessThan,
+                InstructionCode::Equals,
+                InstructionCode::Terminate,
+            ],
+            Profile::Day9 => &INSTRUCTION_CODES,
+        }
+    }
+
+    pub fn modes(self) -> &'static [ParameterMode] {
+        match self {
+            Profile::Day2 => &[ParameterMode::Position],
+            Profile::Day5 => &[ParameterMode::Position, ParameterMode::Immediate],
+            Profile::Day9 => &[
+                ParameterMode::Position,
+                ParameterMode::Immediate,
+                ParameterMode::Relative,
+            ],
+        }
+    }
+
+    pub fn supports(self, code: InstructionCode) -> bool {
+        self.opcodes().contains(&code)
+    }
+
+    /// Whether `instruction`'s opcode and all its parameter modes are in the
+    /// profile.
+    pub fn allows(self, instruction: &Instruction) -> bool {
+        self.supports(instruction.code())
+            && instruction
+                .args()
+                .iter()
+                .all(|arg| self.modes().contains(&arg.mode()))
+    }
+}
+
+/// The test suites of the day 2, 5 and 7 interpreters, run under every
+/// profile that has their instructions.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Address, BufferIo, Error, Integer, Machine, RunOutcome, Value};
+
+    fn machine(profile: Profile, program: &[Integer], input: &[Integer]) -> Machine<BufferIo> {
+        let input = input.iter().map(|v| Value(*v));
+        let mut m = Machine::with_io(program, BufferIo::with_input(input));
+        m.set_profile(profile);
+        m
+    }
+
+    /// Final memory of a program run to completion.
+    fn run(profile: Profile, program: &[Integer]) -> Vec<Integer> {
+        let mut m = machine(profile, program, &[]);
+        assert_eq!(m.run(), Ok(RunOutcome::Halted));
+        m.memory().to_vec()
+    }
+
+    /// The first output of a program given `input`.
+    fn first_output(profile: Profile, program: &[Integer], input: Integer) -> Integer {
+        let mut m = machine(profile, program, &[input]);
+        assert_eq!(m.run(), Ok(RunOutcome::Halted));
+        m.io().output[0].0
+    }
+
+    /// Profiles from `first` on, and the ones before it.
+    fn split(first: Profile) -> (Vec<Profile>, Vec<Profile>) {
+        Profile::ALL.iter().copied().partition(|p| *p >= first)
+    }
+
+    fn rejected(profile: Profile, program: &[Integer], input: Integer) -> bool {
+        let mut m = machine(profile, program, &[input]);
+        matches!(m.run(), Err(Error::InvalidInstruction { .. }))
+    }
+
+    #[test]
+    fn day2_examples() {
+        for &profile in &Profile::ALL {
+            assert_eq!(run(profile, &[1, 0, 0, 0, 99]), [2, 0, 0, 0, 99]);
+            assert_eq!(run(profile, &[2, 3, 0, 3, 99]), [2, 3, 0, 6, 99]);
+            assert_eq!(run(profile, &[2, 4, 4, 5, 99, 0]), [2, 4, 4, 5, 99, 9801]);
+            assert_eq!(
+                run(profile, &[1, 1, 1, 4, 99, 5, 6, 0, 99]),
+                [30, 1, 1, 4, 2, 5, 6, 0, 99]
+            );
+        }
+    }
+
+    #[test]
+    fn day5_comparisons() {
+        let (supported, older) = split(Profile::Day5);
+        // Whether the input is equal to, then less than, 8: position mode,
+        // then immediate mode.
+        let equal_position = [3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+        let less_position = [3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8];
+        let equal_immediate = [3, 3, 1108, -1, 8, 3, 4, 3, 99];
+        let less_immediate = [3, 3, 1107, -1, 8, 3, 4, 3, 99];
+
+        for &profile in &supported {
+            assert_eq!(first_output(profile, &equal_position, 8), 1);
+            assert_eq!(first_output(profile, &equal_position, 10), 0);
+            assert_eq!(first_output(profile, &less_position, 7), 1);
+            assert_eq!(first_output(profile, &less_position, 8), 0);
+            assert_eq!(first_output(profile, &less_position, 10), 0);
+            assert_eq!(first_output(profile, &equal_immediate, 8), 1);
+            assert_eq!(first_output(profile, &equal_immediate, 0), 0);
+            assert_eq!(first_output(profile, &less_immediate, 7), 1);
+            assert_eq!(first_output(profile, &less_immediate, 8), 0);
+            assert_eq!(first_output(profile, &less_immediate, 10), 0);
+        }
+        for &profile in &older {
+            assert!(rejected(profile, &equal_position, 8));
+            assert!(rejected(profile, &less_immediate, 8));
+        }
+    }
+
+    #[test]
+    fn day5_jumps() {
+        let (supported, older) = split(Profile::Day5);
+        // Outputs 0 if the input was zero and 1 otherwise.
+        let position = [3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9];
+        let immediate = [3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1];
+        let larger = [
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0,
+            0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
+            20, 1105, 1, 46, 98, 99,
+        ];
+
+        for &profile in &supported {
+            assert_eq!(first_output(profile, &position, 0), 0);
+            assert_eq!(first_output(profile, &position, 1), 1);
+            assert_eq!(first_output(profile, &immediate, 0), 0);
+            assert_eq!(first_output(profile, &immediate, 1), 1);
+            assert_eq!(first_output(profile, &larger, 5), 999);
+            assert_eq!(first_output(profile, &larger, 8), 1000);
+            assert_eq!(first_output(profile, &larger, 10), 1001);
+        }
+        for &profile in &older {
+            assert!(rejected(profile, &position, 0));
+            assert!(rejected(profile, &larger, 5));
+        }
+    }
+
+    #[test]
+    fn day7_amplifiers() {
+        let (supported, older) = split(Profile::Day5);
+        let program = [
+            3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
+        ];
+
+        for &profile in &supported {
+            let mut signal = 0;
+            for phase in &[4, 3, 2, 1, 0] {
+                let mut amp = machine(profile, &program, &[*phase, signal]);
+                assert_eq!(amp.run(), Ok(RunOutcome::Halted));
+                signal = amp.io().output[0].0;
+            }
+            assert_eq!(signal, 43210);
+        }
+        for &profile in &older {
+            assert!(rejected(profile, &program, 4));
+        }
+    }
+
+    #[test]
+    fn day7_feedback_loop() {
+        let (supported, older) = split(Profile::Day5);
+        let program = [
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+
+        for &profile in &supported {
+            let mut amps: Vec<_> = [9, 8, 7, 6, 5]
+                .iter()
+                .map(|phase| machine(profile, &program, &[*phase]))
+                .collect();
+            let mut signal = Value(0);
+            while !amps[4].is_terminated() {
+                for amp in &mut amps {
+                    amp.io_mut().input.push_back(signal);
+                    amp.run().unwrap();
+                    signal = amp.io_mut().output.pop_front().unwrap();
+                }
+            }
+            assert_eq!(signal, Value(139629729));
+        }
+        for &profile in &older {
+            assert!(rejected(profile, &program, 9));
+        }
+    }
+
+    #[test]
+    fn day9_relative_mode() {
+        let (supported, older) = split(Profile::Day9);
+        let quine = [
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        for &profile in &supported {
+            let mut m = machine(profile, &quine, &[]);
+            assert_eq!(m.run(), Ok(RunOutcome::Halted));
+            let output: Vec<Integer> = m.io().output.iter().map(|v| v.0).collect();
+            assert_eq!(output, quine);
+        }
+        for &profile in &older {
+            let mut m = machine(profile, &quine, &[]);
+            assert_eq!(
+                m.run(),
+                Err(Error::InvalidInstruction {
+                    instruction_value: Value(109),
+                    instruction_location: Address(0),
+                })
+            );
+        }
+
+        // Relative mode alone is enough to be rejected.
+        let mut m = machine(Profile::Day5, &[22201, 0, 0, 0, 99], &[]);
+        assert!(matches!(m.run(), Err(Error::InvalidInstruction { .. })));
+        assert!(!Profile::Day5.supports(InstructionCode::RelBaseAdjsust));
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(Profile::default(), Profile::Day9);
+        assert_eq!(Profile::from_name("day5"), Some(Profile::Day5));
+        assert_eq!(Profile::from_name("2"), Some(Profile::Day2));
+        assert_eq!(Profile::from_name("7"), None);
+    }
+}
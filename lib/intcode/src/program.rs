@@ -0,0 +1,307 @@
+//! Loading programs from files.
+//!
+//! The text format is the puzzle input format: integers separated by commas.
+//! Whitespace and newlines may appear anywhere between values, a trailing
+//! comma is allowed, and `;` or `#` starts a comment that runs to the end of
+//! the line. Errors give the line and column of the offending token.
+//!
+//! Large generated programs can be stored in a compact binary form instead:
+//!
+//! ```text
+//! "ICP1" len value...
+//! ```
+//!
+//! where every number after the magic is a varint (see `encoding`). The
+//! loaders tell the two apart by the magic, so either kind of file can be
+//! passed to `from_path`.
+
+use crate::encoding::{self, Reader};
+use crate::Integer;
+use std::fmt;
+use std::io::{self, Read};
+use std::ops::Deref;
+use std::path::Path;
+use std::str::FromStr;
+
+const MAGIC: &[u8] = b"ICP1";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Program(Vec<Integer>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    /// A token that is not an integer.
+    InvalidNumber(String),
+    /// Two values with no comma between them.
+    MissingComma,
+    /// A comma with no value before it.
+    MissingValue,
+}
+
+/// A syntax error in a text program. Lines and columns count from 1, and
+/// columns count characters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Debug)]
+pub enum ProgramError {
+    Io(io::Error),
+    Parse(ParseError),
+    /// The file is neither binary nor valid UTF-8; the first bad byte is at
+    /// this offset.
+    NotUtf8(usize),
+    /// A binary program ended, or held a malformed number, at this offset.
+    Truncated(usize),
+    /// Unexpected bytes follow a binary program, starting at this offset.
+    TrailingData(usize),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: ", self.line, self.column)?;
+        match &self.kind {
+            ParseErrorKind::InvalidNumber(token) => write!(f, "invalid number '{}'", token),
+            ParseErrorKind::MissingComma => write!(f, "expected ',' before this value"),
+            ParseErrorKind::MissingValue => write!(f, "expected a value before ','"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgramError::Io(e) => e.fmt(f),
+            ProgramError::Parse(e) => e.fmt(f),
+            ProgramError::NotUtf8(at) => write!(f, "invalid UTF-8 at byte {}", at),
+            ProgramError::Truncated(at) => write!(f, "program truncated at byte {}", at),
+            ProgramError::TrailingData(at) => write!(f, "unexpected data at byte {}", at),
+        }
+    }
+}
+
+impl std::error::Error for ProgramError {}
+
+impl From<io::Error> for ProgramError {
+    fn from(e: io::Error) -> Self {
+        ProgramError::Io(e)
+    }
+}
+
+impl From<ParseError> for ProgramError {
+    fn from(e: ParseError) -> Self {
+        ProgramError::Parse(e)
+    }
+}
+
+impl FromStr for Program {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Self, ParseError> {
+        let mut code = Vec::new();
+        let mut expecting_value = true;
+
+        for (line_index, line) in text.lines().enumerate() {
+            let error = |column: usize, kind| ParseError {
+                line: line_index + 1,
+                column: column + 1,
+                kind,
+            };
+            let chars: Vec<char> = line.chars().collect();
+            let mut i = 0;
+            while i < chars.len() {
+                match chars[i] {
+                    ';' | '#' => break,
+                    c if c.is_whitespace() => i += 1,
+                    ',' if expecting_value => return Err(error(i, ParseErrorKind::MissingValue)),
+                    ',' => {
+                        expecting_value = true;
+                        i += 1;
+                    }
+                    _ => {
+                        let start = i;
+                        while i < chars.len() && !matches!(chars[i], ',' | ';' | '#')
+                            && !chars[i].is_whitespace()
+                        {
+                            i += 1;
+                        }
+                        if !expecting_value {
+                            return Err(error(start, ParseErrorKind::MissingComma));
+                        }
+                        let token: String = chars[start..i].iter().collect();
+                        match token.parse::<Integer>() {
+                            Ok(v) => code.push(v),
+                            Err(_) => {
+                                return Err(error(start, ParseErrorKind::InvalidNumber(token)))
+                            }
+                        }
+                        expecting_value = false;
+                    }
+                }
+            }
+        }
+        Ok(Program(code))
+    }
+}
+
+impl fmt::Display for Program {
+    /// The text format, on one line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, v) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", v)?;
+        }
+        Ok(())
+    }
+}
+
+impl Deref for Program {
+    type Target = [Integer];
+
+    fn deref(&self) -> &[Integer] {
+        &self.0
+    }
+}
+
+impl From<Vec<Integer>> for Program {
+    fn from(code: Vec<Integer>) -> Self {
+        Program(code)
+    }
+}
+
+impl Program {
+    pub fn into_vec(self) -> Vec<Integer> {
+        self.0
+    }
+
+    /// Loads a text or binary program file.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, ProgramError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Reads a text or binary program to the end of `reader`.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, ProgramError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Decodes a text or binary program.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProgramError> {
+        if !bytes.starts_with(MAGIC) {
+            let text = std::str::from_utf8(bytes)
+                .map_err(|e| ProgramError::NotUtf8(e.valid_up_to()))?;
+            return Ok(text.parse()?);
+        }
+
+        let mut r = Reader::new(&bytes[MAGIC.len()..]);
+        let truncated = |r: &Reader| ProgramError::Truncated(MAGIC.len() + r.position);
+        // The length comes from the file, so grow as values are read rather
+        // than trusting it for an up-front allocation.
+        let len = r.unsigned().ok_or_else(|| truncated(&r))?;
+        let mut code = Vec::new();
+        for _ in 0..len {
+            code.push(r.signed().ok_or_else(|| truncated(&r))?);
+        }
+        if !r.is_empty() {
+            return Err(ProgramError::TrailingData(MAGIC.len() + r.position));
+        }
+        Ok(Program(code))
+    }
+
+    /// The binary form.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        encoding::write_unsigned(&mut out, self.0.len() as u64);
+        for v in &self.0 {
+            encoding::write_signed(&mut out, *v);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(text: &str) -> (usize, usize, ParseErrorKind) {
+        let e = text.parse::<Program>().unwrap_err();
+        (e.line, e.column, e.kind)
+    }
+
+    #[test]
+    fn parses_text() {
+        let text = "\
+            ; header comment\n\
+            1, 0, 0, 3, # add\n\
+            \t99,\n\
+            -5,\r\n";
+        let program: Program = text.parse().unwrap();
+        assert_eq!(&program[..], &[1, 0, 0, 3, 99, -5]);
+        assert_eq!(program.to_string(), "1,0,0,3,99,-5");
+
+        assert_eq!(&"".parse::<Program>().unwrap()[..], &[] as &[Integer]);
+        assert_eq!(&"1,2,3\n".parse::<Program>().unwrap()[..], &[1, 2, 3]);
+    }
+
+    #[test]
+    fn reports_positions() {
+        assert_eq!(
+            parse_error("1,2,\n3,x4,5"),
+            (2, 3, ParseErrorKind::InvalidNumber("x4".to_string()))
+        );
+        assert_eq!(parse_error("1,,2"), (1, 3, ParseErrorKind::MissingValue));
+        assert_eq!(parse_error(",1"), (1, 1, ParseErrorKind::MissingValue));
+        assert_eq!(parse_error("1\n 2"), (2, 2, ParseErrorKind::MissingComma));
+        assert_eq!(
+            parse_error("99999999999999999999"),
+            (1, 1, ParseErrorKind::InvalidNumber("99999999999999999999".to_string()))
+        );
+        assert_eq!(
+            "1,\n  2,3.5".parse::<Program>().unwrap_err().to_string(),
+            "2:5: invalid number '3.5'"
+        );
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let program = Program::from(vec![1, -1, 1_000_000, Integer::MIN, 99]);
+        let bytes = program.to_bytes();
+        assert!(bytes.len() < program.to_string().len());
+        assert_eq!(Program::from_bytes(&bytes).unwrap(), program);
+        assert_eq!(Program::from_reader(&bytes[..]).unwrap(), program);
+
+        assert!(matches!(
+            Program::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(ProgramError::Truncated(_))
+        ));
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert!(matches!(
+            Program::from_bytes(&extra),
+            Err(ProgramError::TrailingData(at)) if at == bytes.len()
+        ));
+        assert!(matches!(
+            Program::from_bytes(&[0xff, 0xfe]),
+            Err(ProgramError::NotUtf8(0))
+        ));
+    }
+
+    #[test]
+    fn loads_day_inputs() {
+        let path = format!("{}/../../day/09/SensorBoost/input.txt", env!("CARGO_MANIFEST_DIR"));
+        let program = Program::from_path(&path).unwrap();
+        assert_eq!(&program[..4], &[1102, 34463338, 34463338, 63]);
+        assert!(matches!(
+            Program::from_path("does/not/exist"),
+            Err(ProgramError::Io(_))
+        ));
+    }
+}
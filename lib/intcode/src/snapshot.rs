@@ -0,0 +1,268 @@
+//! Saved machine state.
+//!
+//! A `Snapshot` holds everything needed to resume a program: memory, ip,
+//! relative base, whether it has halted, and input that was supplied but not
+//! yet read. `to_bytes` produces a compact binary form for saving long runs
+//! to disk:
+//!
+//! ```text
+//! "ICS1" flags ip relbase memory-len memory... input-len input...
+//! ```
+//!
+//! where every number after the magic is a varint (see `encoding`). Bit 0 of
+//! `flags` is set when the machine has halted. Bit 1 is set for sparse
+//! memory, which is written as its non-zero cells rather than every cell:
+//!
+//! ```text
+//! memory-len cell-count (address-gap value)...
+//! ```
+//!
+//! where each address is given as its distance from the previous one.
+
+use crate::encoding::{self, Reader};
+use crate::memory::SparseMemory;
+use crate::{Address, MemoryBackend, Value};
+use std::fmt;
+
+const MAGIC: &[u8] = b"ICS1";
+const FLAG_TERMINATED: u64 = 1;
+const FLAG_SPARSE: u64 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub memory: MemoryBackend,
+    pub ip: Address,
+    pub relbase: Address,
+    pub terminated: bool,
+    pub input: Vec<Value>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SnapshotError {
+    BadMagic,
+    /// The data ended, or held a malformed number, at this byte offset.
+    Truncated(usize),
+    /// Unexpected bytes follow the snapshot, starting at this offset.
+    TrailingData(usize),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::BadMagic => write!(f, "not an intcode snapshot"),
+            SnapshotError::Truncated(at) => write!(f, "snapshot truncated at byte {}", at),
+            SnapshotError::TrailingData(at) => write!(f, "unexpected data at byte {}", at),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl Snapshot {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        let mut flags = if self.terminated { FLAG_TERMINATED } else { 0 };
+        if let MemoryBackend::Sparse(_) = self.memory {
+            flags |= FLAG_SPARSE;
+        }
+        encoding::write_unsigned(&mut out, flags);
+        encoding::write_unsigned(&mut out, self.ip.0 as u64);
+        encoding::write_unsigned(&mut out, self.relbase.0 as u64);
+
+        encoding::write_unsigned(&mut out, self.memory.len() as u64);
+        match &self.memory {
+            MemoryBackend::Dense(memory) => {
+                for v in memory {
+                    encoding::write_signed(&mut out, *v);
+                }
+            }
+            MemoryBackend::Sparse(memory) => {
+                encoding::write_unsigned(&mut out, memory.cells().count() as u64);
+                let mut previous = 0;
+                for (address, v) in memory.cells() {
+                    encoding::write_unsigned(&mut out, (address - previous) as u64);
+                    encoding::write_signed(&mut out, v);
+                    previous = address;
+                }
+            }
+        }
+
+        encoding::write_unsigned(&mut out, self.input.len() as u64);
+        for v in &self.input {
+            encoding::write_signed(&mut out, v.0);
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut r = Reader::new(bytes);
+        if r.bytes(MAGIC.len()) != Some(MAGIC) {
+            return Err(SnapshotError::BadMagic);
+        }
+
+        let truncated = |r: &Reader| SnapshotError::Truncated(r.position);
+        let flags = r.unsigned().ok_or_else(|| truncated(&r))?;
+        let ip = r.unsigned().ok_or_else(|| truncated(&r))? as usize;
+        let relbase = r.unsigned().ok_or_else(|| truncated(&r))? as usize;
+
+        // Lengths come from the file, so grow as values are read rather than
+        // trusting them for an up-front allocation.
+        let len = r.unsigned().ok_or_else(|| truncated(&r))?;
+        let memory = if flags & FLAG_SPARSE == 0 {
+            let mut memory = Vec::new();
+            for _ in 0..len {
+                memory.push(r.signed().ok_or_else(|| truncated(&r))?);
+            }
+            MemoryBackend::Dense(memory)
+        } else {
+            // Zeros don't allocate, so this only sets the length.
+            let mut memory = SparseMemory::new();
+            if len > 0 {
+                memory.set(len as usize - 1, 0);
+            }
+            let count = r.unsigned().ok_or_else(|| truncated(&r))?;
+            let mut address = 0u64;
+            for _ in 0..count {
+                let at = r.position;
+                let gap = r.unsigned().ok_or_else(|| truncated(&r))?;
+                address = match address.checked_add(gap) {
+                    Some(a) if a < len => a,
+                    _ => return Err(SnapshotError::Truncated(at)),
+                };
+                let v = r.signed().ok_or_else(|| truncated(&r))?;
+                memory.set(address as usize, v);
+            }
+            MemoryBackend::Sparse(memory)
+        };
+
+        let len = r.unsigned().ok_or_else(|| truncated(&r))?;
+        let mut input = Vec::new();
+        for _ in 0..len {
+            input.push(Value(r.signed().ok_or_else(|| truncated(&r))?));
+        }
+
+        if !r.is_empty() {
+            return Err(SnapshotError::TrailingData(r.position));
+        }
+
+        Ok(Snapshot {
+            memory,
+            ip: Address(ip),
+            relbase: Address(relbase),
+            terminated: flags & FLAG_TERMINATED != 0,
+            input,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BufferIo, Integer, Machine, MemoryKind, RunOutcome};
+
+    // in [11]; loop: add [11], #1, [11]; out [11]; jt #1, #loop
+    const COUNTER: [Integer; 12] = [3, 11, 1001, 11, 1, 11, 4, 11, 1105, 1, 2, 0];
+
+    #[test]
+    fn clone_forks_execution() {
+        let mut m = Machine::with_io(&COUNTER, BufferIo::with_input(vec![Value(10)]));
+        assert_eq!(m.run_until_output(), Ok(RunOutcome::Output(Value(11))));
+
+        let mut fork = m.clone();
+        fork.poke(Address(11), Value(100)).unwrap();
+        assert_eq!(fork.run_until_output(), Ok(RunOutcome::Output(Value(101))));
+        assert_eq!(m.run_until_output(), Ok(RunOutcome::Output(Value(12))));
+    }
+
+    #[test]
+    fn restore_rewinds_state_and_input() {
+        let io = BufferIo::with_input(vec![Value(1), Value(2)]);
+        let mut m = Machine::with_io(&[3, 9, 3, 10, 4, 9, 4, 10, 99, 0, 0], io);
+        let start = m.snapshot();
+        assert_eq!(start.input, vec![Value(1), Value(2)]);
+
+        m.run().unwrap();
+        assert!(m.is_terminated());
+        assert_eq!(m.snapshot().memory.to_vec()[9..], [1, 2]);
+
+        m.restore(&start).unwrap();
+        m.io_mut().output.clear();
+        assert_eq!(m.run(), Ok(RunOutcome::Halted));
+        assert_eq!(m.io().output, vec![Value(1), Value(2)]);
+    }
+
+    #[test]
+    fn channel_input_survives_snapshot() {
+        let mut m = Machine::new(&[3, 5, 4, 5, 99, 0]);
+        m.input().send(Value(7)).unwrap();
+        let snapshot = m.snapshot();
+        assert_eq!(snapshot.input, vec![Value(7)]);
+
+        m.run().unwrap();
+        assert_eq!(m.output().as_ref().unwrap().recv(), Ok(Value(7)));
+
+        m.restore(&Snapshot {
+            input: vec![Value(-3)],
+            ..snapshot
+        })
+        .unwrap();
+        m.run().unwrap();
+        assert_eq!(m.output().as_ref().unwrap().recv(), Ok(Value(-3)));
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let snapshot = Snapshot {
+            memory: MemoryBackend::Dense(vec![1, -1, 1 << 40, 0]),
+            ip: Address(300),
+            relbase: Address(2),
+            terminated: true,
+            input: vec![Value(-5)],
+        };
+        let bytes = snapshot.to_bytes();
+        assert_eq!(Snapshot::from_bytes(&bytes), Ok(snapshot));
+
+        assert_eq!(Snapshot::from_bytes(b"nope"), Err(SnapshotError::BadMagic));
+        assert_eq!(
+            Snapshot::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(SnapshotError::Truncated(bytes.len() - 1))
+        );
+        let mut long = bytes.clone();
+        long.push(0);
+        assert_eq!(
+            Snapshot::from_bytes(&long),
+            Err(SnapshotError::TrailingData(bytes.len()))
+        );
+    }
+
+    #[test]
+    fn sparse_memory_stays_sparse() {
+        // rbo #1000000000000; add #3, #4, [rb+5]; out [rb+5]; hlt
+        let program = [109, 1_000_000_000_000, 21101, 3, 4, 5, 204, 5, 99];
+        let mut m = Machine::with_io(&program, BufferIo::new());
+        m.set_memory_kind(MemoryKind::Sparse).unwrap();
+        let start = m.snapshot();
+        m.run().unwrap();
+
+        let end = m.snapshot();
+        assert_eq!(end.memory.get(1_000_000_000_005), 7);
+        let bytes = end.to_bytes();
+        assert!(bytes.len() < 64);
+        assert_eq!(Snapshot::from_bytes(&bytes), Ok(end.clone()));
+
+        m.restore(&start).unwrap();
+        assert_eq!(m.memory().len(), program.len());
+        m.restore(&end).unwrap();
+        assert_eq!(m.peek(Address(1_000_000_000_005)), Value(7));
+
+        let mut dense = Machine::new(&program);
+        assert!(dense.restore(&end).is_err());
+        dense.restore(&start).unwrap();
+        assert_eq!(dense.memory().to_vec(), program);
+
+        let mut bad = bytes.clone();
+        let at = bad.len() - 4;
+        bad[at] = 0x7f;
+        assert!(Snapshot::from_bytes(&bad).is_err());
+    }
+}
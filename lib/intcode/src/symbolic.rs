@@ -0,0 +1,999 @@
+//! Symbolic execution.
+//!
+//! `explore` runs a program with its inputs left unknown: the `n`th value it
+//! reads is `Expr::Input(n)`, and arithmetic on unknowns builds expression
+//! trees instead of numbers. A jump whose condition depends on input forks
+//! the run, and each side carries the constraint that sends it that way.
+//! Every path is reported with its constraints and an expression for each
+//! output, so a program that is a pure function of its inputs, such as
+//! day 19's beam test, can be evaluated natively without running it.
+//!
+//! Loops whose trip count depends on input fork without end. Giving the
+//! explorer a range for the inputs bounds them: a branch that no inputs in
+//! range can take is not followed, though its condition is still recorded,
+//! so each path stays exact for any inputs.
+//!
+//! Opcodes, addresses, jump targets and relative base adjustments must not
+//! depend on input; a path on which one does ends there. Overflow of values
+//! that depend on input does not fork: evaluating the expression for inputs
+//! that overflow gives `None`.
+//!
+//! Expressions share their subexpressions, so a tree printed in full can be
+//! far larger than the program run that built it. A loop can also build
+//! trees as deep as its trip count, so expressions are evaluated, compared,
+//! printed and dropped without recursion.
+
+use crate::decoding::{
+    header, invalid_address, invalid_instruction, relative, ADD, EQUALS, INPUT, JUMP_IF_FALSE,
+    JUMP_IF_TRUE, LESS_THAN, MULT, OUTPUT, REL_BASE_ADJUST,
+};
+use crate::{Address, Error, Integer, Memory, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+/// A value computed from the program's inputs.
+#[derive(Clone)]
+pub enum Expr {
+    Const(Integer),
+    /// The `n`th value read, counting from zero.
+    Input(usize),
+    Add(Arc<Expr>, Arc<Expr>),
+    Mul(Arc<Expr>, Arc<Expr>),
+    /// 1 if the first is less than the second, otherwise 0.
+    LessThan(Arc<Expr>, Arc<Expr>),
+    /// 1 if both are equal, otherwise 0.
+    Equals(Arc<Expr>, Arc<Expr>),
+}
+
+impl Expr {
+    /// The value, if it does not depend on input.
+    pub fn constant(&self) -> Option<Integer> {
+        match self {
+            Expr::Const(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn sum(a: Arc<Expr>, b: Arc<Expr>) -> Arc<Expr> {
+        match (a.constant(), b.constant()) {
+            (Some(x), Some(y)) if x.checked_add(y).is_some() => Arc::new(Expr::Const(x + y)),
+            (Some(0), _) => b,
+            (_, Some(0)) => a,
+            _ => Arc::new(Expr::Add(a, b)),
+        }
+    }
+
+    pub fn product(a: Arc<Expr>, b: Arc<Expr>) -> Arc<Expr> {
+        match (a.constant(), b.constant()) {
+            (Some(x), Some(y)) if x.checked_mul(y).is_some() => Arc::new(Expr::Const(x * y)),
+            (Some(0), _) | (_, Some(0)) => Arc::new(Expr::Const(0)),
+            (Some(1), _) => b,
+            (_, Some(1)) => a,
+            _ => Arc::new(Expr::Mul(a, b)),
+        }
+    }
+
+    pub fn less_than(a: Arc<Expr>, b: Arc<Expr>) -> Arc<Expr> {
+        match (a.constant(), b.constant()) {
+            (Some(x), Some(y)) => Arc::new(Expr::Const((x < y) as Integer)),
+            _ if Arc::ptr_eq(&a, &b) => Arc::new(Expr::Const(0)),
+            _ => Arc::new(Expr::LessThan(a, b)),
+        }
+    }
+
+    pub fn equals(a: Arc<Expr>, b: Arc<Expr>) -> Arc<Expr> {
+        match (a.constant(), b.constant()) {
+            (Some(x), Some(y)) => Arc::new(Expr::Const((x == y) as Integer)),
+            _ if Arc::ptr_eq(&a, &b) => Arc::new(Expr::Const(1)),
+            _ => Arc::new(Expr::Equals(a, b)),
+        }
+    }
+
+    /// The value for `inputs`, or `None` if it reads past them or overflows.
+    pub fn eval(&self, inputs: &[Integer]) -> Option<Integer> {
+        Evaluator::new(inputs).eval(self)
+    }
+
+    fn operands(&self) -> Option<(&Arc<Expr>, &Arc<Expr>)> {
+        match self {
+            Expr::Const(_) | Expr::Input(_) => None,
+            Expr::Add(a, b) | Expr::Mul(a, b) | Expr::LessThan(a, b) | Expr::Equals(a, b) => {
+                Some((a, b))
+            }
+        }
+    }
+}
+
+/// Moves the operands of `expr` that nothing else holds onto `stack`,
+/// leaving `leaf` in their place.
+fn unlink(expr: &mut Expr, leaf: &mut Option<Arc<Expr>>, stack: &mut Vec<Arc<Expr>>) {
+    if let Expr::Add(a, b) | Expr::Mul(a, b) | Expr::LessThan(a, b) | Expr::Equals(a, b) = expr {
+        for operand in &mut [a, b] {
+            if Arc::strong_count(operand) == 1 {
+                let leaf = leaf.get_or_insert_with(|| Arc::new(Expr::Const(0)));
+                stack.push(std::mem::replace(*operand, Arc::clone(leaf)));
+            }
+        }
+    }
+}
+
+/// Dropping a deep tree recursively would overflow the stack, so the nodes
+/// only this one owns are taken apart on a list instead.
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let mut leaf = None;
+        let mut stack = Vec::new();
+        unlink(self, &mut leaf, &mut stack);
+        while let Some(operand) = stack.pop() {
+            if let Ok(mut expr) = Arc::try_unwrap(operand) {
+                unlink(&mut expr, &mut leaf, &mut stack);
+            }
+        }
+    }
+}
+
+/// Folds `root` bottom up, with `leaf` for constants and inputs and `node`
+/// for operators, keeping operator results in `memo` so each shared
+/// subexpression is visited once.
+fn fold<T, L, N>(root: &Expr, memo: &mut HashMap<*const Expr, T>, leaf: L, node: N) -> T
+where
+    T: Copy,
+    L: Fn(&Expr) -> T,
+    N: Fn(&Expr, T, T) -> T,
+{
+    let get = |memo: &HashMap<*const Expr, T>, expr: &Expr| match expr.operands() {
+        Some(_) => memo[&(expr as *const Expr)],
+        None => leaf(expr),
+    };
+    if root.operands().is_none() || memo.contains_key(&(root as *const Expr)) {
+        return get(memo, root);
+    }
+
+    let mut stack = vec![(root, false)];
+    while let Some((expr, expanded)) = stack.pop() {
+        let (a, b) = match expr.operands() {
+            Some(operands) => operands,
+            None => continue,
+        };
+        let key = expr as *const Expr;
+        if expanded {
+            let value = node(expr, get(memo, a), get(memo, b));
+            memo.insert(key, value);
+        } else if !memo.contains_key(&key) {
+            stack.push((expr, true));
+            stack.push((b, false));
+            stack.push((a, false));
+        }
+    }
+    get(memo, root)
+}
+
+/// Evaluates expressions for one set of inputs, visiting each shared
+/// subexpression once.
+struct Evaluator<'a> {
+    inputs: &'a [Integer],
+    memo: HashMap<*const Expr, Option<Integer>>,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(inputs: &'a [Integer]) -> Self {
+        Self {
+            inputs,
+            memo: HashMap::new(),
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Option<Integer> {
+        let inputs = self.inputs;
+        let leaf = |expr: &Expr| match expr {
+            Expr::Const(value) => Some(*value),
+            Expr::Input(n) => inputs.get(*n).copied(),
+            _ => unreachable!(),
+        };
+        fold(expr, &mut self.memo, leaf, |expr, a, b| match (a, b) {
+            (Some(a), Some(b)) => match expr {
+                Expr::Add(..) => a.checked_add(b),
+                Expr::Mul(..) => a.checked_mul(b),
+                Expr::LessThan(..) => Some((a < b) as Integer),
+                _ => Some((a == b) as Integer),
+            },
+            _ => None,
+        })
+    }
+
+    fn holds(&mut self, constraint: &Constraint) -> bool {
+        self.eval(&constraint.condition).map(|v| v != 0) == Some(constraint.nonzero)
+    }
+}
+
+/// Bounds expressions over a box of inputs, visiting each shared
+/// subexpression once. Bounds are widened to `Integer`'s range, which holds
+/// every value that does not overflow.
+struct Bounds<'a> {
+    inputs: &'a [(Integer, Integer)],
+    memo: HashMap<*const Expr, (i128, i128)>,
+}
+
+impl<'a> Bounds<'a> {
+    fn new(inputs: &'a [(Integer, Integer)]) -> Self {
+        Self {
+            inputs,
+            memo: HashMap::new(),
+        }
+    }
+
+    fn bound(&mut self, expr: &Expr) -> (i128, i128) {
+        let inputs = self.inputs;
+        let leaf = |expr: &Expr| match expr {
+            Expr::Const(value) => (*value as i128, *value as i128),
+            Expr::Input(n) => (inputs[*n].0 as i128, inputs[*n].1 as i128),
+            _ => unreachable!(),
+        };
+        fold(expr, &mut self.memo, leaf, combine)
+    }
+}
+
+/// Bounds of operator `expr` given those of its operands.
+fn combine(expr: &Expr, a: (i128, i128), b: (i128, i128)) -> (i128, i128) {
+    let (lo, hi) = match expr {
+        Expr::Add(..) => (a.0 + b.0, a.1 + b.1),
+        Expr::Mul(..) => {
+            let products = [a.0 * b.0, a.0 * b.1, a.1 * b.0, a.1 * b.1];
+            (
+                *products.iter().min().unwrap(),
+                *products.iter().max().unwrap(),
+            )
+        }
+        Expr::LessThan(..) if a.1 < b.0 => (1, 1),
+        Expr::LessThan(..) if a.0 >= b.1 => (0, 0),
+        Expr::Equals(..) if a.0 == a.1 && b == a => (1, 1),
+        Expr::Equals(..) if a.1 < b.0 || b.1 < a.0 => (0, 0),
+        _ => (0, 1),
+    };
+    let clamp = |v: i128| v.max(Integer::MIN as i128).min(Integer::MAX as i128);
+    (clamp(lo), clamp(hi))
+}
+
+/// Whether some inputs in `inputs` satisfy every constraint, found by
+/// splitting the box until the constraints are decided on each part.
+fn satisfiable(constraints: &[Constraint], inputs: &mut [(Integer, Integer)]) -> bool {
+    let mut bounds = Bounds::new(inputs);
+    let mut decided = true;
+    for constraint in constraints {
+        let (lo, hi) = bounds.bound(&constraint.condition);
+        let (zero, nonzero) = (lo <= 0 && 0 <= hi, lo != 0 || hi != 0);
+        if !(if constraint.nonzero { nonzero } else { zero }) {
+            return false;
+        }
+        decided &= zero != nonzero;
+    }
+    if decided {
+        return true;
+    }
+
+    let widest = (0..inputs.len()).max_by_key(|&i| inputs[i].1 as i128 - inputs[i].0 as i128);
+    match widest {
+        Some(i) if inputs[i].0 < inputs[i].1 => {
+            let (lo, hi) = inputs[i];
+            let mid = (lo as i128 + hi as i128).div_euclid(2) as Integer;
+            inputs[i] = (lo, mid);
+            let mut found = satisfiable(constraints, inputs);
+            if !found {
+                inputs[i] = (mid + 1, hi);
+                found = satisfiable(constraints, inputs);
+            }
+            inputs[i] = (lo, hi);
+            found
+        }
+        // A single point, where bounds may be loose only through overflow.
+        _ => {
+            let point: Vec<Integer> = inputs.iter().map(|(v, _)| *v).collect();
+            let mut evaluator = Evaluator::new(&point);
+            constraints.iter().all(|c| evaluator.holds(c))
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        enum Piece<'e> {
+            Expr(&'e Expr),
+            Text(&'static str),
+        }
+
+        let mut stack = vec![Piece::Expr(self)];
+        while let Some(piece) = stack.pop() {
+            let expr = match piece {
+                Piece::Text(text) => {
+                    f.write_str(text)?;
+                    continue;
+                }
+                Piece::Expr(expr) => expr,
+            };
+            let op = match expr {
+                Expr::Const(value) => {
+                    write!(f, "{}", value)?;
+                    continue;
+                }
+                Expr::Input(n) => {
+                    write!(f, "in{}", n)?;
+                    continue;
+                }
+                Expr::Add(..) => " + ",
+                Expr::Mul(..) => " * ",
+                Expr::LessThan(..) => " < ",
+                Expr::Equals(..) => " == ",
+            };
+            let (a, b) = expr.operands().unwrap();
+            f.write_str("(")?;
+            stack.push(Piece::Text(")"));
+            stack.push(Piece::Expr(b));
+            stack.push(Piece::Text(op));
+            stack.push(Piece::Expr(a));
+        }
+        Ok(())
+    }
+}
+
+/// `{:?}` prints the same as `{}`, which does not recurse.
+impl fmt::Debug for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Compares pointers first, and each pair of shared subexpressions once.
+impl PartialEq for Expr {
+    fn eq(&self, other: &Expr) -> bool {
+        let mut seen = HashSet::new();
+        let mut stack = vec![(self, other)];
+        while let Some((a, b)) = stack.pop() {
+            if std::ptr::eq(a, b) || !seen.insert((a as *const Expr, b as *const Expr)) {
+                continue;
+            }
+            match (a, b, a.operands(), b.operands()) {
+                (Expr::Const(x), Expr::Const(y), ..) if x == y => {}
+                (Expr::Input(x), Expr::Input(y), ..) if x == y => {}
+                (_, _, Some((a1, a2)), Some((b1, b2)))
+                    if std::mem::discriminant(a) == std::mem::discriminant(b) =>
+                {
+                    stack.push((a2, b2));
+                    stack.push((a1, b1));
+                }
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+impl Eq for Expr {}
+
+/// A branch taken on a path: `condition` is nonzero exactly when `nonzero`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constraint {
+    pub condition: Arc<Expr>,
+    pub nonzero: bool,
+}
+
+impl Constraint {
+    pub fn holds(&self, inputs: &[Integer]) -> bool {
+        Evaluator::new(inputs).holds(self)
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = if self.nonzero { "!=" } else { "==" };
+        write!(f, "{} {} 0", self.condition, op)
+    }
+}
+
+/// Why a path stopped.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PathEnd {
+    Halted,
+    /// The program faulted, as it would on `Machine`.
+    Fault(Error),
+    /// The instruction at `ip` needs an opcode, address, jump target or
+    /// relative base adjustment that depends on input.
+    Symbolic {
+        ip: Address,
+    },
+    /// The path ran for the explorer's step limit without halting.
+    OutOfSteps,
+}
+
+/// One way through the program.
+#[derive(Debug, Clone)]
+pub struct Path {
+    /// What the inputs must satisfy to take this path, in the order the
+    /// branches were reached.
+    pub constraints: Vec<Constraint>,
+    /// How many inputs the path reads.
+    pub inputs: usize,
+    pub outputs: Vec<Arc<Expr>>,
+    pub end: PathEnd,
+}
+
+impl Path {
+    pub fn is_taken(&self, inputs: &[Integer]) -> bool {
+        let mut evaluator = Evaluator::new(inputs);
+        inputs.len() >= self.inputs && self.constraints.iter().all(|c| evaluator.holds(c))
+    }
+
+    /// The outputs for `inputs`, which must take this path.
+    pub fn outputs(&self, inputs: &[Integer]) -> Option<Vec<Integer>> {
+        let mut evaluator = Evaluator::new(inputs);
+        self.outputs.iter().map(|e| evaluator.eval(e)).collect()
+    }
+}
+
+/// Every path through a program.
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub paths: Vec<Path>,
+}
+
+impl Summary {
+    /// The path `inputs` take, if they supply every input it reads.
+    pub fn path(&self, inputs: &[Integer]) -> Option<&Path> {
+        self.paths.iter().find(|path| path.is_taken(inputs))
+    }
+
+    /// The outputs for `inputs`, if they take a path that halts.
+    pub fn run(&self, inputs: &[Integer]) -> Option<Vec<Integer>> {
+        match self.path(inputs)? {
+            path if path.end == PathEnd::Halted => path.outputs(inputs),
+            _ => None,
+        }
+    }
+}
+
+/// Exploring forked more often than the explorer allows.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TooManyPaths {
+    pub limit: usize,
+}
+
+impl fmt::Display for TooManyPaths {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "more than {} paths", self.limit)
+    }
+}
+
+impl std::error::Error for TooManyPaths {}
+
+/// Where control goes after one instruction.
+enum Event {
+    Next(usize),
+    /// Jump to `target` if `condition` is `nonzero`, else go on to `next`.
+    Fork {
+        condition: Arc<Expr>,
+        nonzero: bool,
+        target: Result<usize, PathEnd>,
+        next: usize,
+    },
+    End(PathEnd),
+}
+
+#[derive(Clone)]
+struct State {
+    memory: Vec<Arc<Expr>>,
+    ip: usize,
+    relbase: usize,
+    inputs: usize,
+    steps: u64,
+    constraints: Vec<Constraint>,
+    outputs: Vec<Arc<Expr>>,
+}
+
+struct Decoded {
+    opcode: u8,
+    modes: [u8; 3],
+    params: Vec<Arc<Expr>>,
+}
+
+impl State {
+    fn peek(&self, address: usize) -> Arc<Expr> {
+        match self.memory.get(address) {
+            Some(value) => Arc::clone(value),
+            None => Arc::new(Expr::Const(0)),
+        }
+    }
+
+    fn invalid_address(&self, address: Integer) -> PathEnd {
+        PathEnd::Fault(invalid_address(address, self.ip))
+    }
+
+    fn symbolic(&self) -> PathEnd {
+        PathEnd::Symbolic {
+            ip: Address(self.ip),
+        }
+    }
+
+    /// Decodes as `FastMachine` does, but an opcode that depends on input
+    /// ends the path, and so may a parameter when it is used.
+    fn decode(&self) -> Result<Decoded, PathEnd> {
+        let value = self
+            .peek(self.ip)
+            .constant()
+            .ok_or_else(|| self.symbolic())?;
+        let header = header(value, self.ip).map_err(PathEnd::Fault)?;
+
+        let mut params = Vec::with_capacity(header.arity);
+        for i in 0..header.arity {
+            let param = self
+                .memory
+                .get(self.ip + 1 + i)
+                .ok_or_else(|| PathEnd::Fault(invalid_instruction(value, self.ip)))?;
+            if let (0, Some(address)) = (header.modes[i], param.constant()) {
+                if address < 0 {
+                    return Err(self.invalid_address(address));
+                }
+            }
+            params.push(Arc::clone(param));
+        }
+        Ok(Decoded {
+            opcode: header.opcode,
+            modes: header.modes,
+            params,
+        })
+    }
+
+    fn address(&self, d: &Decoded, i: usize) -> Result<usize, PathEnd> {
+        let param = d.params[i].constant().ok_or_else(|| self.symbolic())?;
+        if d.modes[i] == 0 {
+            return Ok(param as usize);
+        }
+        relative(self.relbase, param, self.ip).map_err(PathEnd::Fault)
+    }
+
+    fn read(&self, d: &Decoded, i: usize) -> Result<Arc<Expr>, PathEnd> {
+        if d.modes[i] == 1 {
+            return Ok(Arc::clone(&d.params[i]));
+        }
+        Ok(self.peek(self.address(d, i)?))
+    }
+
+    fn store(&mut self, d: &Decoded, i: usize, value: Arc<Expr>) -> Result<(), PathEnd> {
+        if d.modes[i] == 1 {
+            return Err(PathEnd::Fault(Error::WriteToImmediate {
+                ip: Address(self.ip),
+            }));
+        }
+        let address = self.address(d, i)?;
+        if address >= self.memory.len() {
+            self.memory.resize(address + 1, Arc::new(Expr::Const(0)));
+        }
+        self.memory[address] = value;
+        Ok(())
+    }
+
+    fn target(&self, d: &Decoded) -> Result<usize, PathEnd> {
+        let target = self.read(d, 1)?;
+        match target.constant() {
+            Some(target) if target < 0 => Err(self.invalid_address(target)),
+            Some(target) => Ok(target as usize),
+            None => Err(self.symbolic()),
+        }
+    }
+
+    fn arithmetic(
+        &mut self,
+        d: &Decoded,
+        checked: fn(Integer, Integer) -> Option<Integer>,
+        build: fn(Arc<Expr>, Arc<Expr>) -> Arc<Expr>,
+    ) -> Result<(), PathEnd> {
+        let (a, b) = (self.read(d, 0)?, self.read(d, 1)?);
+        if let (Some(x), Some(y)) = (a.constant(), b.constant()) {
+            if checked(x, y).is_none() {
+                return Err(PathEnd::Fault(Error::ArithmeticOverflow {
+                    ip: Address(self.ip),
+                }));
+            }
+        }
+        self.store(d, 2, build(a, b))
+    }
+
+    fn step(&mut self) -> Result<Event, PathEnd> {
+        let d = self.decode()?;
+        let next = self.ip + 1 + d.params.len();
+        match d.opcode {
+            ADD => self.arithmetic(&d, Integer::checked_add, Expr::sum)?,
+            MULT => self.arithmetic(&d, Integer::checked_mul, Expr::product)?,
+            INPUT => {
+                let value = Arc::new(Expr::Input(self.inputs));
+                self.store(&d, 0, value)?;
+                self.inputs += 1;
+            }
+            OUTPUT => {
+                let value = self.read(&d, 0)?;
+                self.outputs.push(value);
+            }
+            JUMP_IF_TRUE | JUMP_IF_FALSE => {
+                let nonzero = d.opcode == JUMP_IF_TRUE;
+                let condition = self.read(&d, 0)?;
+                let taken = match condition.constant() {
+                    Some(value) => Some((value != 0) == nonzero),
+                    // A branch already decided on this path goes the same way.
+                    None => self
+                        .constraints
+                        .iter()
+                        .find(|c| Arc::ptr_eq(&c.condition, &condition))
+                        .map(|c| c.nonzero == nonzero),
+                };
+                match taken {
+                    Some(true) => return Ok(Event::Next(self.target(&d)?)),
+                    Some(false) => {}
+                    None => {
+                        return Ok(Event::Fork {
+                            condition,
+                            nonzero,
+                            target: self.target(&d),
+                            next,
+                        })
+                    }
+                }
+            }
+            LESS_THAN => {
+                let value = Expr::less_than(self.read(&d, 0)?, self.read(&d, 1)?);
+                self.store(&d, 2, value)?;
+            }
+            EQUALS => {
+                let value = Expr::equals(self.read(&d, 0)?, self.read(&d, 1)?);
+                self.store(&d, 2, value)?;
+            }
+            REL_BASE_ADJUST => {
+                let offset = self.read(&d, 0)?;
+                let offset = offset.constant().ok_or_else(|| self.symbolic())?;
+                match (self.relbase as Integer).checked_add(offset) {
+                    Some(relbase) if relbase >= 0 => self.relbase = relbase as usize,
+                    _ => {
+                        return Err(PathEnd::Fault(Error::NegativeRelativeBase {
+                            relbase: Value((self.relbase as Integer).wrapping_add(offset)),
+                            ip: Address(self.ip),
+                        }))
+                    }
+                }
+            }
+            _ => return Ok(Event::End(PathEnd::Halted)),
+        }
+        Ok(Event::Next(next))
+    }
+
+    fn finish(self, end: PathEnd) -> Path {
+        Path {
+            constraints: self.constraints,
+            inputs: self.inputs,
+            outputs: self.outputs,
+            end,
+        }
+    }
+}
+
+/// Runs programs symbolically, within limits on how far it goes.
+#[derive(Debug, Clone)]
+pub struct Explorer {
+    max_paths: usize,
+    max_steps: u64,
+    inputs: Option<(Integer, Integer)>,
+}
+
+impl Default for Explorer {
+    fn default() -> Self {
+        Self {
+            max_paths: 1024,
+            max_steps: 1_000_000,
+            inputs: None,
+        }
+    }
+}
+
+impl Explorer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The most paths to follow before giving up with `TooManyPaths`.
+    pub fn set_max_paths(&mut self, paths: usize) {
+        self.max_paths = paths;
+    }
+
+    /// The most instructions to run on any one path, counting from the start
+    /// of the program.
+    pub fn set_max_steps(&mut self, steps: u64) {
+        self.max_steps = steps;
+    }
+
+    /// Follows only branches that some inputs within `range` can take.
+    pub fn set_input_range(&mut self, range: RangeInclusive<Integer>) {
+        self.inputs = Some((*range.start(), *range.end()));
+    }
+
+    /// Which ways a branch on `condition` can go from `state`.
+    fn feasible(&self, state: &State, condition: &Arc<Expr>) -> (bool, bool) {
+        let (lo, hi) = match self.inputs {
+            Some(range) => range,
+            None => return (true, true),
+        };
+        let mut inputs = vec![(lo, hi); state.inputs];
+        let mut constraints = state.constraints.clone();
+        let mut way = |nonzero| {
+            constraints.push(Constraint {
+                condition: Arc::clone(condition),
+                nonzero,
+            });
+            let found = satisfiable(&constraints, &mut inputs);
+            constraints.pop();
+            found
+        };
+        (way(true), way(false))
+    }
+
+    pub fn explore(&self, program: &Memory) -> Result<Summary, TooManyPaths> {
+        let start = State {
+            memory: program.iter().map(|v| Arc::new(Expr::Const(*v))).collect(),
+            ip: 0,
+            relbase: 0,
+            inputs: 0,
+            steps: 0,
+            constraints: Vec::new(),
+            outputs: Vec::new(),
+        };
+        let mut pending = vec![start];
+        let mut paths = Vec::new();
+        while let Some(mut state) = pending.pop() {
+            loop {
+                if state.steps == self.max_steps {
+                    paths.push(state.finish(PathEnd::OutOfSteps));
+                    break;
+                }
+                state.steps += 1;
+                match state.step() {
+                    Ok(Event::Next(ip)) => state.ip = ip,
+                    Ok(Event::Fork {
+                        condition,
+                        nonzero,
+                        target,
+                        next,
+                    }) => {
+                        let taken = match self.feasible(&state, &condition) {
+                            (true, true) => None,
+                            (true, false) => Some(nonzero),
+                            (false, true) => Some(!nonzero),
+                            // No inputs in range reach here at all.
+                            (false, false) => break,
+                        };
+                        if let Some(jump) = taken {
+                            state.constraints.push(Constraint {
+                                condition,
+                                nonzero: nonzero == jump,
+                            });
+                            match target {
+                                Ok(target) if jump => state.ip = target,
+                                Err(end) if jump => {
+                                    paths.push(state.finish(end));
+                                    break;
+                                }
+                                _ => state.ip = next,
+                            }
+                            continue;
+                        }
+                        if paths.len() + pending.len() + 2 > self.max_paths {
+                            return Err(TooManyPaths {
+                                limit: self.max_paths,
+                            });
+                        }
+                        let mut jumped = state.clone();
+                        jumped.constraints.push(Constraint {
+                            condition: Arc::clone(&condition),
+                            nonzero,
+                        });
+                        match target {
+                            Ok(target) => {
+                                jumped.ip = target;
+                                pending.push(jumped);
+                            }
+                            Err(end) => paths.push(jumped.finish(end)),
+                        }
+                        state.constraints.push(Constraint {
+                            condition,
+                            nonzero: !nonzero,
+                        });
+                        state.ip = next;
+                    }
+                    Ok(Event::End(end)) | Err(end) => {
+                        paths.push(state.finish(end));
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(Summary { paths })
+    }
+}
+
+/// Explores `program` with the default limits.
+pub fn explore(program: &Memory) -> Result<Summary, TooManyPaths> {
+    Explorer::new().explore(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::{BufferIo, FastMachine, Program};
+
+    #[test]
+    fn builds_expressions() {
+        let program = assemble(
+            "
+                    in   [x]
+                    in   [y]
+                    mul  [x], [x], [t]
+                    add  [t], [y], [t]
+                    add  [t], #0, [t]
+                    out  [t]
+                    hlt
+            x:      .data 0
+            y:      .data 0
+            t:      .data 0
+            ",
+        )
+        .unwrap();
+        let summary = explore(&program).unwrap();
+        assert_eq!(summary.paths.len(), 1);
+        let path = &summary.paths[0];
+        assert_eq!(path.end, PathEnd::Halted);
+        assert_eq!(path.inputs, 2);
+        assert!(path.constraints.is_empty());
+        assert_eq!(path.outputs[0].to_string(), "((in0 * in0) + in1)");
+        assert_eq!(summary.run(&[3, 4]), Some(vec![13]));
+        assert_eq!(summary.run(&[3]), None);
+    }
+
+    #[test]
+    fn forks_on_input_branches() {
+        let program = assemble(
+            "
+                    in   [x]
+                    lt   [x], #10, [c]
+                    jt   [c], #small
+                    out  #1
+                    jt   [c], #small
+                    hlt
+            small:  out  [x]
+                    hlt
+            x:      .data 0
+            c:      .data 0
+            ",
+        )
+        .unwrap();
+        let summary = explore(&program).unwrap();
+        // The second test of `c` is already decided.
+        assert_eq!(summary.paths.len(), 2);
+        let conditions: Vec<String> = summary
+            .paths
+            .iter()
+            .map(|p| p.constraints.iter().map(|c| c.to_string()).collect())
+            .collect();
+        assert!(conditions.contains(&"(in0 < 10) != 0".to_string()));
+        assert!(conditions.contains(&"(in0 < 10) == 0".to_string()));
+        assert_eq!(summary.run(&[5]), Some(vec![5]));
+        assert_eq!(summary.run(&[50]), Some(vec![1]));
+    }
+
+    #[test]
+    fn stops_where_input_picks_an_address() {
+        // in [3]; out [0]; hlt
+        let summary = explore(&[3, 3, 4, 0, 99]).unwrap();
+        assert_eq!(summary.paths.len(), 1);
+        assert_eq!(summary.paths[0].end, PathEnd::Symbolic { ip: Address(2) });
+        assert_eq!(summary.run(&[0]), None);
+    }
+
+    #[test]
+    fn deep_expressions_do_not_recurse() {
+        // Adds the input to itself 300000 times, then branches on the sum.
+        let program = assemble(
+            "
+                    in   [x]
+            loop:   add  [acc], [x], [acc]
+                    add  [n], #-1, [n]
+                    jt   [n], #loop
+                    lt   [acc], #0, [t]
+                    jt   [t], #neg
+                    out  [acc]
+                    hlt
+            neg:    out  #-1
+                    hlt
+            x:      .data 0
+            acc:    .data 0
+            n:      .data 300000
+            t:      .data 0
+            ",
+        )
+        .unwrap();
+        let mut explorer = Explorer::new();
+        explorer.set_max_steps(2_000_000);
+        explorer.set_input_range(0..=1000);
+        let summary = explorer.explore(&program).unwrap();
+        assert_eq!(summary.paths.len(), 1);
+        assert_eq!(summary.run(&[2]), Some(vec![600_000]));
+
+        let output = &summary.paths[0].outputs[0];
+        assert!(output.to_string().starts_with("((((("));
+        assert_eq!(output.eval(&[-1]), Some(-300_000));
+        assert!(format!("{:?}", summary).contains(&format!("{:?}", output)));
+
+        // The same sum built again, and one that differs at the bottom.
+        let sum = |first: Expr| {
+            let x = Arc::new(Expr::Input(0));
+            (1..300_000).fold(Arc::new(first), |acc, _| Expr::sum(acc, Arc::clone(&x)))
+        };
+        assert_eq!(*output, sum(Expr::Input(0)));
+        assert_ne!(*output, sum(Expr::Input(1)));
+    }
+
+    #[test]
+    fn limits_paths_and_steps() {
+        // Counts the input down to zero.
+        let program = assemble(
+            "
+                    in   [x]
+            loop:   add  [x], #-1, [x]
+                    jt   [x], #loop
+                    hlt
+            x:      .data 0
+            ",
+        )
+        .unwrap();
+        let mut explorer = Explorer::new();
+        explorer.set_max_paths(10);
+        assert_eq!(
+            explorer.explore(&program).unwrap_err(),
+            TooManyPaths { limit: 10 }
+        );
+
+        // jt #1, #0
+        explorer.set_max_steps(100);
+        let summary = explorer.explore(&[1105, 1, 0]).unwrap();
+        assert_eq!(summary.paths[0].end, PathEnd::OutOfSteps);
+
+        // Counting down from at most 5 takes at most 5 trips.
+        explorer.set_input_range(1..=5);
+        let summary = explorer.explore(&program).unwrap();
+        assert_eq!(summary.paths.len(), 5);
+        assert!(summary.paths.iter().all(|p| p.end == PathEnd::Halted));
+        assert!(summary.run(&[3]).is_some());
+        // Paths keep every branch they took, so inputs out of range find none.
+        assert_eq!(summary.run(&[9]), None);
+    }
+
+    /// Day 19's program tests one point of the beam; over the puzzle's grid
+    /// its paths give the same answer as running it.
+    #[test]
+    fn solves_day_19() {
+        let path = format!(
+            "{}/../../day/19/TractorBeam/input.txt",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let program = Program::from_path(path).unwrap();
+        let mut explorer = Explorer::new();
+        explorer.set_input_range(0..=49);
+        let summary = explorer.explore(&program).unwrap();
+        assert!(summary.paths.iter().all(|p| p.inputs == 2));
+
+        for x in 0..50 {
+            for y in 0..50 {
+                let mut io = BufferIo::with_input(vec![Value(x), Value(y)]);
+                FastMachine::new(&program).run(&mut io).unwrap();
+                let expected: Vec<Integer> = io.output.iter().map(|v| v.0).collect();
+                assert_eq!(summary.run(&[x, y]), Some(expected), "({}, {})", x, y);
+            }
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Machines on their own threads.
+//!
+//! A `ThreadedRunner` spawns one thread per `Machine<ChannelIo>` and makes
+//! its input reads block, so machines wired together with
+//! `ChannelIo::with_channels` wait on each other the way the puzzle
+//! describes. `join` waits for every thread and returns each machine with
+//! the result of its run.
+//!
+//! A machine that will never halt on its own (one waiting on a peer that has
+//! halted, or one that loops forever) is stopped by `shutdown`: blocked reads
+//! give up, and running machines stop within a few thousand instructions.
+
+use crate::{ChannelIo, Error, Integer, Machine, RunOutcome, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// Instructions run between checks of the shutdown flag.
+const SLICE: u64 = 4096;
+
+/// A handle that stops every machine of a runner. Can be sent to other
+/// threads.
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown(Arc<AtomicBool>);
+
+impl Shutdown {
+    pub fn signal(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_signalled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// A machine whose thread has finished.
+#[derive(Debug)]
+pub struct Finished {
+    pub machine: Machine<ChannelIo>,
+    /// `Halted`, or `NeedsInput` for a machine stopped while waiting for
+    /// input, or `StepLimit` for one stopped while running.
+    pub result: Result<RunOutcome, Error>,
+}
+
+#[derive(Debug, Default)]
+pub struct ThreadedRunner {
+    workers: Vec<JoinHandle<Finished>>,
+    inputs: Vec<Sender<Value>>,
+    shutdown: Shutdown,
+}
+
+impl ThreadedRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spawns `count` copies of `program` in a ring: machine `i` sends its
+    /// output to machine `i + 1`, and the last to the first. Each machine
+    /// first reads its entry of `inputs`, if any.
+    pub fn ring(program: &[Integer], count: usize, inputs: &[Vec<Value>]) -> Self {
+        let channels: Vec<_> = (0..count).map(|_| channel()).collect();
+        let senders: Vec<_> = channels.iter().map(|(tx, _)| tx.clone()).collect();
+
+        // Queue every initial input before any machine starts, so no machine
+        // can send to a neighbour ahead of that neighbour's own inputs.
+        for (sender, input) in senders.iter().zip(inputs) {
+            for v in input {
+                sender.send(*v).unwrap();
+            }
+        }
+
+        let mut runner = Self::new();
+        for (i, input) in channels.into_iter().enumerate() {
+            let io = ChannelIo::with_channels(input, senders[(i + 1) % count].clone());
+            runner.spawn(Machine::with_io(program, io));
+        }
+        runner
+    }
+
+    /// Starts `machine` on a new thread and returns its index. Input reads
+    /// block until a value arrives or the runner is shut down.
+    pub fn spawn(&mut self, mut machine: Machine<ChannelIo>) -> usize {
+        let io = machine.io_mut();
+        io.set_block_for_input(true);
+        io.set_cancel(Some(self.shutdown.0.clone()));
+        self.inputs.push(io.input().clone());
+
+        let shutdown = self.shutdown.clone();
+        self.workers.push(thread::spawn(move || {
+            let result = loop {
+                match machine.run_for(SLICE) {
+                    Ok(RunOutcome::StepLimit) if !shutdown.is_signalled() => {}
+                    result => break result,
+                }
+            };
+            Finished { machine, result }
+        }));
+        self.workers.len() - 1
+    }
+
+    /// The input channel of machine `index`.
+    pub fn input(&self, index: usize) -> &Sender<Value> {
+        &self.inputs[index]
+    }
+
+    pub fn len(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.workers.is_empty()
+    }
+
+    /// A handle for stopping the machines from elsewhere.
+    pub fn shutdown_handle(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    /// Asks every machine to stop; `join` still has to be called.
+    pub fn shutdown(&self) {
+        self.shutdown.signal();
+    }
+
+    /// Waits for every thread, in spawn order. A panic on a machine's thread
+    /// is resumed here.
+    pub fn join(self) -> Vec<Finished> {
+        self.workers
+            .into_iter()
+            .map(|worker| match worker.join() {
+                Ok(finished) => finished,
+                Err(panic) => std::panic::resume_unwind(panic),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IntcodeIo;
+
+    #[test]
+    fn amplifier_ring() {
+        let program = [
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+        let mut inputs: Vec<_> = [9, 8, 7, 6, 5].iter().map(|p| vec![Value(*p)]).collect();
+        inputs[0].push(Value(0));
+
+        let finished = ThreadedRunner::ring(&program, 5, &inputs).join();
+        assert!(finished.iter().all(|f| f.result == Ok(RunOutcome::Halted)));
+
+        // The last machine's final output is left unread in machine 0's input.
+        let first = finished[0].machine.io();
+        assert_eq!(first.pending_input(), vec![Value(139629729)]);
+    }
+
+    #[test]
+    fn shutdown_stops_blocked_and_running_machines() {
+        let mut runner = ThreadedRunner::new();
+        // in [3]; hlt -- waits forever for input.
+        runner.spawn(Machine::new(&[3, 3, 99, 0]));
+        // jt #1, #0 -- spins forever.
+        runner.spawn(Machine::new(&[1105, 1, 0]));
+
+        runner.shutdown();
+        let finished = runner.join();
+        assert_eq!(finished[0].result, Ok(RunOutcome::NeedsInput));
+        assert_eq!(finished[0].machine.ip(), crate::Address(0));
+        assert_eq!(finished[1].result, Ok(RunOutcome::StepLimit));
+    }
+
+    #[test]
+    fn errors_are_collected() {
+        let mut runner = ThreadedRunner::new();
+        let id = runner.spawn(Machine::new(&[3, 5, 4, 5, 99, 0]));
+        runner.spawn(Machine::new(&[42]));
+        runner.input(id).send(Value(7)).unwrap();
+
+        let mut finished = runner.join();
+        assert!(matches!(finished[1].result, Err(Error::InvalidInstruction { .. })));
+        assert_eq!(finished[0].result, Ok(RunOutcome::Halted));
+        let output = finished[0].machine.output().take().unwrap();
+        assert_eq!(output.try_recv(), Ok(Value(7)));
+    }
+}
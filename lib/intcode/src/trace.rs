@@ -0,0 +1,251 @@
+//! Step-level execution tracing.
+//!
+//! A `Tracer` installed with `Machine::set_tracer` receives one `TraceRecord`
+//! per executed instruction. Two writers are provided: `JsonLinesTracer` for
+//! tooling and `TextTracer` for reading or diffing two runs side by side.
+
+use crate::{Address, Instruction, Value};
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceRecord {
+    /// Address of the executed instruction.
+    pub ip: Address,
+    /// Relative base before the instruction executed.
+    pub relbase: Address,
+    pub instruction: Instruction,
+    /// Resolved values of the instruction's read parameters, in memory order.
+    pub reads: Vec<Value>,
+    pub writes: Vec<(Address, Value)>,
+    pub input: Option<Value>,
+    pub output: Option<Value>,
+}
+
+pub trait Tracer: Send {
+    fn trace(&mut self, record: &TraceRecord);
+}
+
+impl<F> Tracer for F
+where
+    F: FnMut(&TraceRecord) + Send,
+{
+    fn trace(&mut self, record: &TraceRecord) {
+        self(record)
+    }
+}
+
+fn join<T, F: Fn(&T) -> String>(items: &[T], f: F) -> String {
+    items.iter().map(f).collect::<Vec<_>>().join(",")
+}
+
+fn json_option(value: Option<Value>) -> String {
+    match value {
+        Some(v) => v.0.to_string(),
+        None => "null".to_string(),
+    }
+}
+
+/// Writes each record as a single JSON object per line.
+pub struct JsonLinesTracer<W: Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> JsonLinesTracer<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            error: None,
+        }
+    }
+
+    /// The first write error encountered, if any. Records after an error are
+    /// dropped.
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_record(&mut self, r: &TraceRecord) -> io::Result<()> {
+        let args = join(&r.instruction.args(), |a| format!("\"{}\"", a));
+        writeln!(
+            self.writer,
+            "{{\"ip\":{},\"relbase\":{},\"op\":\"{}\",\"args\":[{}],\"reads\":[{}],\"writes\":[{}],\"input\":{},\"output\":{}}}",
+            r.ip.0,
+            r.relbase.0,
+            r.instruction.code().mnemonic(),
+            args,
+            join(&r.reads, |v| v.0.to_string()),
+            join(&r.writes, |(a, v)| format!("[{},{}]", a.0, v.0)),
+            json_option(r.input),
+            json_option(r.output),
+        )
+    }
+}
+
+impl<W: Write + Send> Tracer for JsonLinesTracer<W> {
+    fn trace(&mut self, record: &TraceRecord) {
+        if self.error.is_none() {
+            if let Err(e) = self.write_record(record) {
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+/// Writes each record as one fixed-layout line:
+///
+/// ```text
+/// 00004 rb=0     add [1], #2, [3]          r=5,2 w=[3]:7
+/// ```
+pub struct TextTracer<W: Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> TextTracer<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            error: None,
+        }
+    }
+
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_record(&mut self, r: &TraceRecord) -> io::Result<()> {
+        let mut line = format!(
+            "{:05} rb={:<5} {:<25}",
+            r.ip.0,
+            r.relbase.0,
+            r.instruction.to_string()
+        );
+        if !r.reads.is_empty() {
+            line.push_str(&format!(" r={}", join(&r.reads, |v| v.0.to_string())));
+        }
+        if !r.writes.is_empty() {
+            line.push_str(&format!(
+                " w={}",
+                join(&r.writes, |(a, v)| format!("[{}]:{}", a.0, v.0))
+            ));
+        }
+        if let Some(v) = r.input {
+            line.push_str(&format!(" in={}", v.0));
+        }
+        if let Some(v) = r.output {
+            line.push_str(&format!(" out={}", v.0));
+        }
+        writeln!(self.writer, "{}", line.trim_end())
+    }
+}
+
+impl<W: Write + Send> Tracer for TextTracer<W> {
+    fn trace(&mut self, record: &TraceRecord) {
+        if self.error.is_none() {
+            if let Err(e) = self.write_record(record) {
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Arg, Machine};
+    use std::sync::{Arc, Mutex};
+
+    fn collect(program: &[i64], input: &[i64]) -> Vec<TraceRecord> {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let sink = records.clone();
+
+        let mut m = Machine::new(program);
+        m.set_tracer(move |r: &TraceRecord| sink.lock().unwrap().push(r.clone()));
+        for v in input {
+            m.input().send(Value(*v)).unwrap();
+        }
+        m.run().unwrap();
+
+        let records = records.lock().unwrap().clone();
+        records
+    }
+
+    #[test]
+    fn records_every_instruction() {
+        // in [9]; add [9], #2, [10]; out [10]; hlt
+        let records = collect(&[3, 9, 1001, 9, 2, 10, 4, 10, 99, 0, 0], &[5]);
+
+        let ips: Vec<usize> = records.iter().map(|r| r.ip.0).collect();
+        assert_eq!(ips, vec![0, 2, 6, 8]);
+
+        assert_eq!(records[0].input, Some(Value(5)));
+        assert_eq!(records[0].writes, vec![(Address(9), Value(5))]);
+        assert_eq!(records[1].reads, vec![Value(5), Value(2)]);
+        assert_eq!(records[1].writes, vec![(Address(10), Value(7))]);
+        assert_eq!(records[2].output, Some(Value(7)));
+        assert_eq!(records[3].instruction, Instruction::Terminate);
+    }
+
+    #[test]
+    fn tracks_relbase() {
+        // rb #4; out rb+1; hlt
+        let records = collect(&[109, 4, 204, 1, 99, 42], &[]);
+        assert_eq!(records[1].relbase, Address(4));
+        assert_eq!(records[1].reads, vec![Value(42)]);
+        assert_eq!(
+            records[1].instruction,
+            Instruction::Output {
+                arg1: Arg::Offset(Value(1))
+            }
+        );
+    }
+
+    #[test]
+    fn input_wait_is_not_traced() {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let sink = records.clone();
+        let mut m = Machine::new(&[3, 3, 99, 0]);
+        m.set_tracer(move |r: &TraceRecord| sink.lock().unwrap().push(r.ip));
+
+        assert_eq!(m.run(), Ok(crate::RunOutcome::NeedsInput));
+        assert!(records.lock().unwrap().is_empty());
+
+        m.input().send(Value(1)).unwrap();
+        m.run().unwrap();
+        assert_eq!(*records.lock().unwrap(), vec![Address(0), Address(2)]);
+    }
+
+    #[test]
+    fn writers_format_records() {
+        let records = collect(&[3, 9, 1001, 9, 2, 10, 4, 10, 99, 0, 0], &[5]);
+
+        let mut json = JsonLinesTracer::new(Vec::new());
+        let mut text = TextTracer::new(Vec::new());
+        for r in &records {
+            json.trace(r);
+            text.trace(r);
+        }
+
+        let json = String::from_utf8(json.into_inner()).unwrap();
+        assert_eq!(
+            json.lines().nth(1).unwrap(),
+            "{\"ip\":2,\"relbase\":0,\"op\":\"add\",\"args\":[\"[9]\",\"#2\",\"[10]\"],\"reads\":[5,2],\"writes\":[[10,7]],\"input\":null,\"output\":null}"
+        );
+
+        let text = String::from_utf8(text.into_inner()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "00000 rb=0     in [9]                    w=[9]:5 in=5");
+        assert_eq!(lines[1], "00002 rb=0     add [9], #2, [10]         r=5,2 w=[10]:7");
+        assert_eq!(lines[2], "00006 rb=0     out [10]                  r=7 out=7");
+        assert_eq!(lines[3], "00008 rb=0     hlt");
+    }
+}
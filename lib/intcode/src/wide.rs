@@ -0,0 +1,213 @@
+//! An engine for programs whose values outgrow `Integer`.
+//!
+//! `WideMachine` runs programs the way `FastMachine` does, decoding them
+//! with the same code, but keeps memory and I/O in any `Word`. With `i128`
+//! it has twice the room of the other engines; with `BigInt` (the `bignum`
+//! feature) `add` and `mul` never overflow. Addresses, opcodes and relative
+//! base offsets must still fit an `Integer`; values reported in an `Error`
+//! are clamped to one.
+
+use crate::arithmetic::Word;
+use crate::decoding::{
+    decode, invalid_address, Decoded, ADD, EQUALS, INPUT, JUMP_IF_FALSE, JUMP_IF_TRUE, LESS_THAN,
+    MULT, OUTPUT, REL_BASE_ADJUST,
+};
+use crate::{Address, Error, Integer, Memory, RunOutcome, Value};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+#[derive(Debug, Clone)]
+pub struct WideMachine<W> {
+    memory: Vec<W>,
+    ip: usize,
+    relbase: usize,
+    terminated: bool,
+}
+
+impl<W: Word> WideMachine<W> {
+    pub fn new(program: &Memory) -> Self {
+        Self::from_words(program.iter().map(|v| W::from(*v)).collect())
+    }
+
+    pub fn from_words(program: Vec<W>) -> Self {
+        Self {
+            memory: program,
+            ip: 0,
+            relbase: 0,
+            terminated: false,
+        }
+    }
+
+    pub fn memory(&self) -> &[W] {
+        &self.memory
+    }
+
+    pub fn ip(&self) -> Address {
+        Address(self.ip)
+    }
+
+    pub fn relbase(&self) -> Address {
+        Address(self.relbase)
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    fn read(&self, d: &Decoded<W>, i: usize) -> Result<W, Error> {
+        d.read(i, self.relbase, self.ip, &self.memory)
+    }
+
+    fn store(&mut self, d: &Decoded<W>, i: usize, value: W) -> Result<(), Error> {
+        let address = d.write_address(i, self.relbase, self.ip)?;
+        if address >= self.memory.len() {
+            self.memory.resize(address + 1, W::from(0));
+        }
+        self.memory[address] = value;
+        Ok(())
+    }
+
+    fn jump(&mut self, target: W) -> Result<(), Error> {
+        match target.to_integer().map(usize::try_from) {
+            Some(Ok(target)) => {
+                self.ip = target;
+                Ok(())
+            }
+            _ => Err(invalid_address(target.saturate(), self.ip)),
+        }
+    }
+
+    fn overflow(&self) -> Error {
+        Error::ArithmeticOverflow {
+            ip: Address(self.ip),
+        }
+    }
+
+    /// Runs until the program halts or needs input, taking inputs from the
+    /// front of `input` and appending outputs to `output`.
+    pub fn run(
+        &mut self,
+        input: &mut VecDeque<W>,
+        output: &mut Vec<W>,
+    ) -> Result<RunOutcome, Error> {
+        if self.terminated {
+            return Ok(RunOutcome::Halted);
+        }
+
+        loop {
+            let d = decode(&self.memory, self.ip)?;
+            let next = self.ip + d.size as usize;
+            match d.opcode {
+                ADD => {
+                    let v = self.read(&d, 0)?.checked_add(&self.read(&d, 1)?);
+                    let v = v.ok_or_else(|| self.overflow())?;
+                    self.store(&d, 2, v)?;
+                }
+                MULT => {
+                    let v = self.read(&d, 0)?.checked_mul(&self.read(&d, 1)?);
+                    let v = v.ok_or_else(|| self.overflow())?;
+                    self.store(&d, 2, v)?;
+                }
+                INPUT => match input.pop_front() {
+                    Some(v) => self.store(&d, 0, v)?,
+                    None => return Ok(RunOutcome::NeedsInput),
+                },
+                OUTPUT => output.push(self.read(&d, 0)?),
+                JUMP_IF_TRUE | JUMP_IF_FALSE => {
+                    let nonzero = self.read(&d, 0)? != W::from(0);
+                    if nonzero == (d.opcode == JUMP_IF_TRUE) {
+                        let target = self.read(&d, 1)?;
+                        self.jump(target)?;
+                        continue;
+                    }
+                }
+                LESS_THAN => {
+                    let v = (self.read(&d, 0)? < self.read(&d, 1)?) as Integer;
+                    self.store(&d, 2, W::from(v))?;
+                }
+                EQUALS => {
+                    let v = (self.read(&d, 0)? == self.read(&d, 1)?) as Integer;
+                    self.store(&d, 2, W::from(v))?;
+                }
+                REL_BASE_ADJUST => {
+                    let offset = self.read(&d, 0)?;
+                    let relbase = offset
+                        .to_integer()
+                        .and_then(|offset| (self.relbase as Integer).checked_add(offset));
+                    match relbase {
+                        Some(relbase) if relbase >= 0 => self.relbase = relbase as usize,
+                        _ => {
+                            let offset = offset.saturate();
+                            return Err(Error::NegativeRelativeBase {
+                                relbase: Value((self.relbase as Integer).saturating_add(offset)),
+                                ip: Address(self.ip),
+                            });
+                        }
+                    }
+                }
+                _ => {
+                    self.terminated = true;
+                    self.ip = next;
+                    return Ok(RunOutcome::Halted);
+                }
+            }
+            self.ip = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    /// Raises its input to the `n`th power.
+    fn power(n: Integer) -> Vec<Integer> {
+        assemble(&format!(
+            "
+                    in   [x]
+                    add  #1, #0, [acc]
+            loop:   mul  [acc], [x], [acc]
+                    add  [n], #-1, [n]
+                    jt   [n], #loop
+                    out  [acc]
+                    hlt
+            n:      .data {}
+            x:      .data 0
+            acc:    .data 0
+            ",
+            n
+        ))
+        .unwrap()
+    }
+
+    fn run<W: Word>(program: &[Integer], input: W) -> (Result<RunOutcome, Error>, Vec<W>) {
+        let mut m = WideMachine::<W>::new(program);
+        let mut output = Vec::new();
+        let result = m.run(&mut vec![input].into(), &mut output);
+        (result, output)
+    }
+
+    #[test]
+    fn i128_computes_past_i64() {
+        assert_eq!(run(&power(2), 10i64), (Ok(RunOutcome::Halted), vec![100]));
+        assert_eq!(run(&power(30), 10i128).1, vec![10i128.pow(30)]);
+        assert_eq!(
+            run(&power(30), 10i64),
+            (Err(Error::ArithmeticOverflow { ip: Address(6) }), vec![])
+        );
+        assert_eq!(
+            run(&power(40), 10i128).0,
+            Err(Error::ArithmeticOverflow { ip: Address(6) })
+        );
+    }
+
+    #[cfg(feature = "bignum")]
+    #[test]
+    fn bignum_never_overflows() {
+        use num_bigint::BigInt;
+        let (result, output) = run(&power(100), BigInt::from(10));
+        assert_eq!(result, Ok(RunOutcome::Halted));
+        assert_eq!(output, vec![num_traits::pow(BigInt::from(10), 100)]);
+    }
+}